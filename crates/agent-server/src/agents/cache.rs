@@ -0,0 +1,431 @@
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+use url::Url;
+
+use crate::agents::output::{CacheInfo, NewsOutput, ScrapeOutput, SearchOutput};
+
+/// Query parameters that only track where a visitor came from. `ref` is left
+/// alone because sites such as GitHub use it to pick what the page shows.
+const TRACKING_PARAMS: [&str; 3] = ["fbclid", "gclid", "cmpid"];
+
+/// How often expired entries are swept from the job store.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// The agents whose results are cached, each with its own time to live.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    Search,
+    News,
+    Scrape,
+}
+
+impl CacheKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheKind::Search => "search",
+            CacheKind::News => "news",
+            CacheKind::Scrape => "scrape",
+        }
+    }
+}
+
+/// How long cached results stay fresh, read from the environment.
+/// `None` disables caching for that agent.
+#[derive(Debug, Clone)]
+pub struct CacheSettings {
+    pub search_ttl: Option<Duration>,
+    pub news_ttl: Option<Duration>,
+    pub scrape_ttl: Option<Duration>,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            search_ttl: Some(Duration::from_secs(15 * 60)),
+            news_ttl: Some(Duration::from_secs(5 * 60)),
+            scrape_ttl: Some(Duration::from_secs(60 * 60)),
+        }
+    }
+}
+
+impl CacheSettings {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        // 0 disables the cache for that agent
+        let ttl = |key: &str, default: Option<Duration>| match std::env::var(key).ok().and_then(|v| v.parse::<u64>().ok()) {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => default,
+        };
+
+        Self {
+            search_ttl: ttl("CACHE_TTL_SEARCH_SECS", defaults.search_ttl),
+            news_ttl: ttl("CACHE_TTL_NEWS_SECS", defaults.news_ttl),
+            scrape_ttl: ttl("CACHE_TTL_SCRAPE_SECS", defaults.scrape_ttl),
+        }
+    }
+
+    pub fn ttl(&self, kind: CacheKind) -> Option<Duration> {
+        match kind {
+            CacheKind::Search => self.search_ttl,
+            CacheKind::News => self.news_ttl,
+            CacheKind::Scrape => self.scrape_ttl,
+        }
+    }
+}
+
+/// Agent outputs that report whether they were served from the cache.
+pub trait Cached: Serialize + DeserializeOwned {
+    fn set_cache(&mut self, info: Option<CacheInfo>);
+}
+
+impl Cached for SearchOutput {
+    fn set_cache(&mut self, info: Option<CacheInfo>) {
+        self.cache = info;
+    }
+}
+
+impl Cached for NewsOutput {
+    fn set_cache(&mut self, info: Option<CacheInfo>) {
+        self.cache = info;
+    }
+}
+
+impl Cached for ScrapeOutput {
+    fn set_cache(&mut self, info: Option<CacheInfo>) {
+        self.cache = info;
+    }
+}
+
+/// A cached result as stored in the job store.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    cached_at: u64,
+    expires_at: u64,
+    value: serde_json::Value,
+}
+
+/// The part of an [`Entry`] the sweeper needs, so it can skip parsing the value.
+#[derive(Deserialize)]
+struct Expiry {
+    expires_at: u64,
+}
+
+/// Agent results stored in sled, keyed by agent and normalized request.
+#[derive(Clone)]
+pub struct ResultCache {
+    tree: sled::Tree,
+    settings: CacheSettings,
+}
+
+impl ResultCache {
+    pub fn new(tree: sled::Tree, settings: CacheSettings) -> Self {
+        Self { tree, settings }
+    }
+
+    async fn open() -> Result<Self, sled::Error> {
+        let db = crate::handlers::agents::DB.lock().await;
+        Ok(Self::new(db.open_tree("agent_cache")?, CacheSettings::from_env()))
+    }
+
+    /// Returns the cached result for `key` while it is fresh, otherwise runs
+    /// `run` and caches what it returns. `no_cache` skips the lookup but still
+    /// refreshes the stored result.
+    pub async fn get_or_run<T, E, F>(&self, kind: CacheKind, key: &str, no_cache: bool, run: F) -> Result<T, E>
+    where
+        T: Cached,
+        F: Future<Output = Result<T, E>>,
+    {
+        let Some(ttl) = self.settings.ttl(kind) else {
+            return run.await;
+        };
+        let key = format!("{}:{}", kind.as_str(), key);
+
+        if !no_cache {
+            if let Some(value) = self.load(&key) {
+                tracing::debug!(key = %key, "Serving cached result");
+                return Ok(value);
+            }
+        }
+
+        let mut value = run.await?;
+        value.set_cache(None);
+        let info = self.store(&key, &value, ttl);
+        value.set_cache(info);
+        Ok(value)
+    }
+
+    fn load<T: Cached>(&self, key: &str) -> Option<T> {
+        let entry: Entry = match self.tree.get(key) {
+            Ok(Some(bytes)) => serde_json::from_slice(&bytes).ok()?,
+            Ok(None) => return None,
+            Err(e) => {
+                tracing::warn!("Failed to read the result cache: {}", e);
+                return None;
+            }
+        };
+        if entry.expires_at <= now_secs() {
+            let _ = self.tree.remove(key);
+            return None;
+        }
+
+        // entries written by an older version may no longer deserialize
+        let mut value: T = serde_json::from_value(entry.value).ok()?;
+        value.set_cache(Some(CacheInfo {
+            hit: true,
+            cached_at: entry.cached_at,
+            expires_at: entry.expires_at,
+        }));
+        Some(value)
+    }
+
+    /// Removes every expired entry, returning how many were removed. Reads
+    /// only remove the entry they hit, so keys that are never asked for again
+    /// would otherwise stay in the store for good.
+    pub fn sweep(&self) -> usize {
+        let now = now_secs();
+        let mut removed = 0;
+        for item in self.tree.iter() {
+            let Ok((key, bytes)) = item else {
+                continue;
+            };
+            let expired = match serde_json::from_slice::<Expiry>(&bytes) {
+                Ok(entry) => entry.expires_at <= now,
+                // entries that no longer parse would never be served either
+                Err(_) => true,
+            };
+            if expired && matches!(self.tree.remove(&key), Ok(Some(_))) {
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Sweeps expired entries every [`SWEEP_INTERVAL`].
+    fn spawn_sweeper(&self) {
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                let removed = cache.sweep();
+                if removed > 0 {
+                    tracing::debug!("Swept {} expired results from the cache", removed);
+                }
+            }
+        });
+    }
+
+    fn store<T: Cached>(&self, key: &str, value: &T, ttl: Duration) -> Option<CacheInfo> {
+        let cached_at = now_secs();
+        let entry = Entry {
+            cached_at,
+            expires_at: cached_at + ttl.as_secs(),
+            value: serde_json::to_value(value).ok()?,
+        };
+        let stored = serde_json::to_vec(&entry)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| self.tree.insert(key, bytes).map_err(|e| e.to_string()));
+        if let Err(e) = stored {
+            tracing::warn!("Failed to write the result cache: {}", e);
+            return None;
+        }
+        Some(CacheInfo {
+            hit: false,
+            cached_at,
+            expires_at: entry.expires_at,
+        })
+    }
+}
+
+/// The process-wide result cache, or `None` when the job store cannot be opened.
+async fn cache() -> Option<&'static ResultCache> {
+    static CACHE: OnceCell<Option<ResultCache>> = OnceCell::const_new();
+    CACHE
+        .get_or_init(|| async {
+            let cache = ResultCache::open()
+                .await
+                .map_err(|e| tracing::error!("Result cache disabled: {}", e))
+                .ok()?;
+            cache.spawn_sweeper();
+            Some(cache)
+        })
+        .await
+        .as_ref()
+}
+
+/// Runs an agent through the process-wide result cache.
+pub async fn cached<T, E, F>(kind: CacheKind, key: &str, no_cache: bool, run: F) -> Result<T, E>
+where
+    T: Cached,
+    F: Future<Output = Result<T, E>>,
+{
+    match cache().await {
+        Some(cache) => cache.get_or_run(kind, key, no_cache, run).await,
+        None => run.await,
+    }
+}
+
+/// Normalizes a search query so differences in case and spacing share an entry.
+pub fn normalize_query(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Parses `url` without the parts that never change the page it names: the
+/// fragment and tracking parameters. Parsing lowercases the scheme and host;
+/// the path and the remaining parameters keep their case and order.
+pub fn without_tracking(url: &str) -> Option<Url> {
+    let mut url = Url::parse(url.trim()).ok()?;
+    url.set_fragment(None);
+
+    let pairs = url.query_pairs().count();
+    let params: Vec<(String, String)> = url
+        .query_pairs()
+        .into_owned()
+        .filter(|(key, _)| !(key.starts_with("utm_") || TRACKING_PARAMS.contains(&key.as_str())))
+        .collect();
+    if params.is_empty() {
+        url.set_query(None);
+    } else if params.len() < pairs {
+        url.query_pairs_mut().clear().extend_pairs(params);
+    }
+    Some(url)
+}
+
+/// Normalizes a url so the same page linked in different ways shares an
+/// entry: on top of [`without_tracking`], the parameters are sorted. The path
+/// is kept as is, since `/docs/` and `/docs` can be different pages.
+pub fn normalize_url(url: &str) -> String {
+    let Some(mut url) = without_tracking(url) else {
+        return url.trim().to_string();
+    };
+
+    let mut params: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    params.sort();
+    if !params.is_empty() {
+        url.query_pairs_mut().clear().extend_pairs(params);
+    }
+
+    url.to_string()
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn output(query: &str) -> SearchOutput {
+        SearchOutput {
+            query: query.to_string(),
+            results: Vec::new(),
+            sources: Vec::new(),
+            cache: None,
+        }
+    }
+
+    fn temporary_cache(settings: CacheSettings) -> ResultCache {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        ResultCache::new(db.open_tree("agent_cache").unwrap(), settings)
+    }
+
+    #[tokio::test]
+    async fn test_get_or_run_serves_fresh_results_from_the_cache() {
+        let cache = temporary_cache(CacheSettings::default());
+        let runs = AtomicUsize::new(0);
+        let run = |query: &'static str| {
+            let runs = &runs;
+            async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, String>(output(query))
+            }
+        };
+
+        let first = cache.get_or_run(CacheKind::Search, "rust", false, run("rust")).await.unwrap();
+        let info = first.cache.unwrap();
+        assert!(!info.hit);
+        assert_eq!(info.expires_at, info.cached_at + 15 * 60);
+
+        let second = cache.get_or_run(CacheKind::Search, "rust", false, run("other")).await.unwrap();
+        assert_eq!(second.query, "rust");
+        assert_eq!(second.cache, Some(CacheInfo { hit: true, ..info }));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // the same key under another agent is a different entry
+        let news = cache.get_or_run(CacheKind::News, "rust", false, run("news")).await.unwrap();
+        assert_eq!(news.query, "news");
+
+        let bypassed = cache.get_or_run(CacheKind::Search, "rust", true, run("fresh")).await.unwrap();
+        assert_eq!(bypassed.query, "fresh");
+        assert!(!bypassed.cache.unwrap().hit);
+        let refreshed = cache.get_or_run(CacheKind::Search, "rust", false, run("stale")).await.unwrap();
+        assert_eq!(refreshed.query, "fresh");
+
+        let failed = cache.get_or_run(CacheKind::Scrape, "x", false, async { Err::<SearchOutput, _>("down") }).await;
+        assert_eq!(failed.unwrap_err(), "down");
+        let retried = cache.get_or_run(CacheKind::Scrape, "x", false, run("up")).await.unwrap();
+        assert!(!retried.cache.unwrap().hit);
+    }
+
+    #[tokio::test]
+    async fn test_expired_and_disabled_entries_are_not_served() {
+        let settings = CacheSettings {
+            search_ttl: Some(Duration::ZERO),
+            news_ttl: None,
+            ..CacheSettings::default()
+        };
+        let cache = temporary_cache(settings);
+
+        cache.get_or_run(CacheKind::Search, "rust", false, async { Ok::<_, ()>(output("old")) }).await.unwrap();
+        let next = cache.get_or_run(CacheKind::Search, "rust", false, async { Ok::<_, ()>(output("new")) }).await.unwrap();
+        assert_eq!(next.query, "new");
+
+        let uncached = cache.get_or_run(CacheKind::News, "rust", false, async { Ok::<_, ()>(output("news")) }).await.unwrap();
+        assert_eq!(uncached.cache, None);
+        assert!(cache.tree.get("news:rust").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sweep_removes_only_expired_entries() {
+        let settings = CacheSettings {
+            search_ttl: Some(Duration::ZERO),
+            ..CacheSettings::default()
+        };
+        let cache = temporary_cache(settings);
+
+        cache.get_or_run(CacheKind::Search, "old", false, async { Ok::<_, ()>(output("old")) }).await.unwrap();
+        cache.get_or_run(CacheKind::News, "fresh", false, async { Ok::<_, ()>(output("fresh")) }).await.unwrap();
+        cache.tree.insert("scrape:broken", b"not json".as_slice()).unwrap();
+
+        assert_eq!(cache.sweep(), 2);
+        assert!(cache.tree.get("search:old").unwrap().is_none());
+        assert!(cache.tree.get("news:fresh").unwrap().is_some());
+        assert_eq!(cache.sweep(), 0);
+    }
+
+    #[test]
+    fn test_normalize_request_keys() {
+        assert_eq!(normalize_query("  Rust   ASYNC\truntimes "), "rust async runtimes");
+        assert_eq!(
+            normalize_url("https://Example.com/docs/?b=2&utm_source=x&a=1#intro"),
+            "https://example.com/docs/?a=1&b=2"
+        );
+        assert_ne!(normalize_url("https://example.com/docs/"), normalize_url("https://example.com/docs"));
+        assert_eq!(
+            normalize_url("https://github.com/o/r/blob/x.md?ref=dev"),
+            "https://github.com/o/r/blob/x.md?ref=dev"
+        );
+        assert_eq!(normalize_url("https://example.com/?fbclid=abc"), "https://example.com/");
+        assert_eq!(normalize_url("not a url "), "not a url");
+    }
+}
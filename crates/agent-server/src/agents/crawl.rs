@@ -0,0 +1,329 @@
+use std::collections::{HashSet, VecDeque};
+
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+use url::{Origin, Url};
+
+use crate::agents::output::{CrawlOutput, CrawledPage, Source};
+use crate::agents::scrape::{self, ScrapeFormat};
+use crate::scraping::fetch::parse_url;
+use crate::scraping::{Fetcher, ScrapeError};
+
+const DEFAULT_MAX_DEPTH: u32 = 2;
+const MAX_DEPTH_LIMIT: u32 = 5;
+const DEFAULT_MAX_PAGES: u32 = 20;
+const MAX_PAGES_LIMIT: u32 = 100;
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct CrawlRequest {
+    #[schemars(description = "The URL to start crawling from")]
+    pub url: String,
+    #[schemars(description = "How many links deep to follow from the start page (default 2, max 5)")]
+    pub max_depth: Option<u32>,
+    #[schemars(description = "Maximum number of pages to fetch (default 20, max 100)")]
+    pub max_pages: Option<u32>,
+    #[schemars(description = "Only follow urls whose path matches one of these patterns, e.g. \"/docs/*\"")]
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[schemars(description = "Never follow urls whose path matches one of these patterns, e.g. \"*.pdf\"")]
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// Reported after each page of a crawl is fetched.
+#[derive(Debug, Clone, Serialize)]
+pub struct CrawlProgress {
+    /// 1-based position of the page in the crawl.
+    pub index: usize,
+    pub max_pages: usize,
+    /// Links waiting to be fetched.
+    pub queued: usize,
+    #[serde(flatten)]
+    pub page: CrawledPage,
+}
+
+/// Crawls same-origin links breadth-first from `request.url`, reporting each
+/// fetched page to `progress` when given.
+///
+/// Only a failure on the start page fails the crawl; later pages that cannot
+/// be fetched are listed in the index with their error.
+pub async fn native_agent(
+    fetcher: &Fetcher,
+    request: &CrawlRequest,
+    progress: Option<&UnboundedSender<CrawlProgress>>,
+) -> Result<CrawlOutput, ScrapeError> {
+    let start = parse_url(&request.url)?;
+    let max_depth = request.max_depth.unwrap_or(DEFAULT_MAX_DEPTH).min(MAX_DEPTH_LIMIT);
+    let max_pages = request
+        .max_pages
+        .unwrap_or(DEFAULT_MAX_PAGES)
+        .clamp(1, MAX_PAGES_LIMIT) as usize;
+
+    let mut queue = VecDeque::from([(start.clone(), 0)]);
+    let mut seen = HashSet::from([crawl_key(&start)]);
+    // set from the start page after redirects, e.g. http -> https
+    let mut origin: Option<Origin> = None;
+    let mut pages = Vec::new();
+    let mut sections = Vec::new();
+    let mut sources = Vec::new();
+
+    while let Some((url, depth)) = queue.pop_front() {
+        if pages.len() >= max_pages {
+            break;
+        }
+
+        let mut links = Vec::new();
+        let page = match fetch_page(fetcher, &url).await {
+            Ok((final_url, extracted)) => {
+                seen.insert(crawl_key(&final_url));
+                origin.get_or_insert_with(|| final_url.origin());
+                links = extracted.links;
+                let title = extracted.title.as_deref().unwrap_or(final_url.as_str());
+                sources.push(Source::new(final_url.to_string(), title, &extracted.content));
+                sections.push(format!(
+                    "## {}\n\nSource [{}]: {}\n\n{}",
+                    title,
+                    sources.len(),
+                    final_url,
+                    extracted.content
+                ));
+                CrawledPage {
+                    url: final_url.to_string(),
+                    title: extracted.title,
+                    depth,
+                    chars: extracted.content.chars().count(),
+                    error: None,
+                }
+            }
+            Err(e) if pages.is_empty() => return Err(e),
+            Err(e) => CrawledPage {
+                url: url.to_string(),
+                title: None,
+                depth,
+                chars: 0,
+                error: Some(e.to_string()),
+            },
+        };
+
+        if depth < max_depth {
+            if let Some(origin) = &origin {
+                for link in links {
+                    if link.origin() == *origin
+                        && path_allowed(&link, &request.include, &request.exclude)
+                        && seen.insert(crawl_key(&link))
+                    {
+                        queue.push_back((link, depth + 1));
+                    }
+                }
+            }
+        }
+
+        tracing::info!(target: "crawl", url = %page.url, depth, "Crawled page {} of at most {}", pages.len() + 1, max_pages);
+        if let Some(progress) = progress {
+            let _ = progress.send(CrawlProgress {
+                index: pages.len() + 1,
+                max_pages,
+                queued: queue.len(),
+                page: page.clone(),
+            });
+        }
+        pages.push(page);
+    }
+
+    Ok(CrawlOutput {
+        url: start.to_string(),
+        markdown: render_bundle(&start, &pages, &sections),
+        pages,
+        sources,
+    })
+}
+
+async fn fetch_page(fetcher: &Fetcher, url: &Url) -> Result<(Url, scrape::Extracted), ScrapeError> {
+    let fetched = fetcher.fetch(url.as_str()).await?;
+    let extracted = scrape::extract(&fetched, ScrapeFormat::Markdown, &fetcher.options().document_limits)?;
+    Ok((fetched.url, extracted))
+}
+
+/// The combined Markdown: a page index followed by every page's content.
+fn render_bundle(start: &Url, pages: &[CrawledPage], sections: &[String]) -> String {
+    let index = pages
+        .iter()
+        .enumerate()
+        .map(|(i, page)| match &page.error {
+            Some(error) => format!("{}. {} (failed: {})", i + 1, page.url, error),
+            None => format!(
+                "{}. [{}]({})",
+                i + 1,
+                page.title.as_deref().unwrap_or(&page.url),
+                page.url
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut bundle = format!("# Crawl of {}\n\n{}", start, index);
+    for section in sections {
+        bundle.push_str("\n\n---\n\n");
+        bundle.push_str(section);
+    }
+    bundle
+}
+
+/// Key used to avoid fetching a page twice when links differ only by a trailing slash.
+fn crawl_key(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    url.as_str().trim_end_matches('/').to_string()
+}
+
+fn path_allowed(url: &Url, include: &[String], exclude: &[String]) -> bool {
+    let path = url.path();
+    (include.is_empty() || include.iter().any(|p| glob_matches(p, path)))
+        && !exclude.iter().any(|p| glob_matches(p, path))
+}
+
+/// Matches `text` against a pattern where `*` stands for any run of characters.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || !text[first.len()..].ends_with(last) {
+        return false;
+    }
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraping::fetch::tests::local_options;
+    use axum::extract::Path;
+    use axum::http::{header, StatusCode};
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use axum::Router;
+
+    /// A small docs site: every page links to the next two, plus an external link.
+    async fn docs_site() -> String {
+        let app = Router::new().route(
+            "/docs/{page}",
+            get(|Path(page): Path<String>| async move {
+                let n: u32 = match page.trim_end_matches(".html").parse() {
+                    Ok(n) => n,
+                    Err(_) => return StatusCode::NOT_FOUND.into_response(),
+                };
+                if n == 3 {
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+                let html = format!(
+                    r#"<html><head><title>Page {n}</title></head><body>
+                    <nav><a href="/docs/0">Home</a> <a href="/private/admin">Admin</a> <a href="/docs/{n}.pdf">PDF</a></nav>
+                    <article><p>Page {n} explains one part of the system in enough detail to be content.</p>
+                    <p><a href="{a}">next</a> <a href="./{b}#section">after</a> <a href="https://elsewhere.example/">away</a></p></article>
+                    </body></html>"#,
+                    n = n,
+                    a = n + 1,
+                    b = n + 2,
+                );
+                ([(header::CONTENT_TYPE, "text/html")], html).into_response()
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    fn request(url: String) -> CrawlRequest {
+        CrawlRequest {
+            url,
+            max_depth: None,
+            max_pages: None,
+            include: vec!["/docs/*".to_string()],
+            exclude: vec!["*.pdf".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_crawl_follows_same_origin_links_breadth_first() {
+        let base_url = docs_site().await;
+        let fetcher = Fetcher::new(local_options());
+        let (progress, mut events) = tokio::sync::mpsc::unbounded_channel();
+
+        let output = native_agent(&fetcher, &request(format!("{}/docs/0", base_url)), Some(&progress))
+            .await
+            .unwrap();
+        drop(progress);
+
+        let visited: Vec<(&str, u32)> = output
+            .pages
+            .iter()
+            .map(|p| (p.url.trim_start_matches(&base_url), p.depth))
+            .collect();
+        assert_eq!(
+            visited,
+            [("/docs/0", 0), ("/docs/1", 1), ("/docs/2", 1), ("/docs/3", 2), ("/docs/4", 2)]
+        );
+        assert!(output.pages[3].error.is_some());
+
+        assert!(output.markdown.starts_with(&format!(
+            "# Crawl of {base}/docs/0\n\n1. [Page 0]({base}/docs/0)\n2. [Page 1]({base}/docs/1)",
+            base = base_url
+        )));
+        assert!(output.markdown.contains(&format!("4. {}/docs/3 (failed: ", base_url)));
+        // the failed page is not a source, so the last page is the fourth
+        assert_eq!(output.sources.len(), 4);
+        assert!(output.markdown.contains(&format!("## Page 4\n\nSource [4]: {}/docs/4", base_url)));
+        assert_eq!(output.sources[3].title, "Page 4");
+        assert!(!output.markdown.contains("## Page 5"));
+
+        let mut indexes = Vec::new();
+        while let Some(event) = events.recv().await {
+            indexes.push(event.index);
+        }
+        assert_eq!(indexes, [1, 2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_crawl_respects_page_limit_and_start_failures() {
+        let base_url = docs_site().await;
+        let fetcher = Fetcher::new(local_options());
+
+        let mut limited = request(format!("{}/docs/0", base_url));
+        limited.max_pages = Some(2);
+        limited.max_depth = Some(5);
+        let output = native_agent(&fetcher, &limited, None).await.unwrap();
+        assert_eq!(output.pages.len(), 2);
+
+        let missing = request(format!("{}/docs/missing", base_url));
+        assert!(matches!(
+            native_agent(&fetcher, &missing, None).await,
+            Err(ScrapeError::Status(StatusCode::NOT_FOUND))
+        ));
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("/docs/*", "/docs/intro"));
+        assert!(!glob_matches("/docs/*", "/blog/intro"));
+        assert!(glob_matches("*.pdf", "/files/a.pdf"));
+        assert!(glob_matches("/api/*/v2/*", "/api/users/v2/list"));
+        assert!(!glob_matches("/api/*/v2/*", "/api/users/v1/list"));
+        assert!(glob_matches("/exact", "/exact"));
+        assert!(!glob_matches("/exact", "/exact/more"));
+        assert!(glob_matches("*", ""));
+    }
+}
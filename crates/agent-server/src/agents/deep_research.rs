@@ -1,33 +1,598 @@
-use crate::utils::utils::run_agent;
-use tokio::process::Child;
-use tracing;
+use futures::future::join_all;
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc::UnboundedSender;
 
-pub async fn agent(stream_id: &str, input: &str) -> Result<Child, String> {
-    run_agent(stream_id, input, "./packages/genaiscript/genaisrc/deep-research.genai.mts", 60).await
+use crate::agents::llm::{self, ChatClient, ChatMessage, LlmError};
+use crate::agents::output::{
+    ground_citations, truncate_chars, ResearchFinding, ResearchOutput, SearchResult, Source,
+};
+use crate::agents::scrape::{self, ScrapeFormat};
+use crate::agents::search::collect_results;
+use crate::agents::searxng::{SearxngClient, SearxngError, SearxngQuery};
+use crate::scraping::{self, Fetcher};
+
+const DEFAULT_BREADTH: u32 = 3;
+const MAX_BREADTH: u32 = 6;
+const DEFAULT_DEPTH: u32 = 2;
+const MAX_DEPTH: u32 = 4;
+/// Search results read in full for each sub-question.
+const SOURCES_PER_QUESTION: usize = 3;
+/// Characters of each scraped page passed to the summarizer.
+const MAX_SOURCE_CHARS: usize = 4000;
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ResearchRequest {
+    #[schemars(description = "The research topic")]
+    pub topic: String,
+    #[schemars(description = "Sub-questions researched in each round (default 3, max 6)")]
+    pub breadth: Option<u32>,
+    #[schemars(description = "Rounds of research; rounds after the first follow up on gaps in the findings (default 2, max 4)")]
+    pub depth: Option<u32>,
+}
+
+#[derive(Debug)]
+pub enum ResearchError {
+    Llm(LlmError),
+    Search(SearxngError),
+}
+
+impl std::fmt::Display for ResearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResearchError::Llm(e) => write!(f, "{}", e),
+            ResearchError::Search(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ResearchError {}
+
+impl From<LlmError> for ResearchError {
+    fn from(e: LlmError) -> Self {
+        ResearchError::Llm(e)
+    }
+}
+
+impl From<SearxngError> for ResearchError {
+    fn from(e: SearxngError) -> Self {
+        ResearchError::Search(e)
+    }
+}
+
+/// Reported as each step of the research completes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum ResearchEvent {
+    Plan {
+        round: u32,
+        questions: Vec<String>,
+    },
+    Search {
+        question: String,
+        results: usize,
+    },
+    Scrape {
+        url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Summarize {
+        question: String,
+        sources: Vec<usize>,
+    },
+    Synthesize {
+        findings: usize,
+        sources: usize,
+    },
+}
+
+impl ResearchEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResearchEvent::Plan { .. } => "plan",
+            ResearchEvent::Search { .. } => "search",
+            ResearchEvent::Scrape { .. } => "scrape",
+            ResearchEvent::Summarize { .. } => "summarize",
+            ResearchEvent::Synthesize { .. } => "synthesize",
+        }
+    }
+}
+
+/// The services a research run calls.
+#[derive(Debug, Clone)]
+pub struct Researcher {
+    pub llm: ChatClient,
+    pub searxng: SearxngClient,
+    pub fetcher: Fetcher,
+    /// Plans questions and writes the report.
+    pub large_model: String,
+    /// Summarizes the sources found for each question.
+    pub small_model: String,
+}
+
+impl Researcher {
+    /// Uses `OPENAI_API_BASE`, `GENAISCRIPT_MODEL_LARGE` and `GENAISCRIPT_MODEL_SMALL`
+    /// (falling back to the large model), SearxNG and the shared scraper.
+    pub fn from_env() -> Result<Self, ResearchError> {
+        let large_model =
+            llm::model_from_env("GENAISCRIPT_MODEL_LARGE").ok_or(LlmError::NotConfigured("GENAISCRIPT_MODEL_LARGE"))?;
+        Ok(Self {
+            llm: ChatClient::from_env()?,
+            searxng: SearxngClient::from_env()?,
+            fetcher: scraping::fetcher(),
+            small_model: llm::model_from_env("GENAISCRIPT_MODEL_SMALL").unwrap_or_else(|| large_model.clone()),
+            large_model,
+        })
+    }
+}
+
+enum Step {
+    Plan,
+    Search(Vec<String>),
+    Scrape(Vec<(String, Vec<SearchResult>)>),
+    Summarize(Vec<(String, Vec<usize>)>),
+    Synthesize,
+}
+
+/// A page the research read, numbered by its position in `Run::sources`.
+struct ReadPage {
+    source: Source,
+    excerpt: String,
+}
+
+struct Run<'a> {
+    researcher: &'a Researcher,
+    topic: &'a str,
+    breadth: usize,
+    depth: u32,
+    round: u32,
+    sources: Vec<ReadPage>,
+    findings: Vec<ResearchFinding>,
+    progress: Option<&'a UnboundedSender<ResearchEvent>>,
+}
+
+/// Researches `request.topic` by stepping through plan → search → scrape →
+/// summarize for each round, then synthesizing a cited report.
+pub async fn native_agent(
+    researcher: &Researcher,
+    request: &ResearchRequest,
+    progress: Option<&UnboundedSender<ResearchEvent>>,
+) -> Result<ResearchOutput, ResearchError> {
+    let mut run = Run {
+        researcher,
+        topic: request.topic.trim(),
+        breadth: request.breadth.unwrap_or(DEFAULT_BREADTH).clamp(1, MAX_BREADTH) as usize,
+        depth: request.depth.unwrap_or(DEFAULT_DEPTH).clamp(1, MAX_DEPTH),
+        round: 0,
+        sources: Vec::new(),
+        findings: Vec::new(),
+        progress,
+    };
+
+    let mut step = Step::Plan;
+    loop {
+        step = match step {
+            Step::Plan => match run.plan().await? {
+                questions if questions.is_empty() => Step::Synthesize,
+                questions => Step::Search(questions),
+            },
+            Step::Search(questions) => Step::Scrape(run.search(questions).await?),
+            Step::Scrape(hits) => Step::Summarize(run.scrape(hits).await),
+            Step::Summarize(questions) => {
+                run.summarize(questions).await?;
+                run.round += 1;
+                match run.round < run.depth {
+                    true => Step::Plan,
+                    false => Step::Synthesize,
+                }
+            }
+            Step::Synthesize => return run.synthesize().await,
+        };
+    }
 }
 
+impl Run<'_> {
+    fn emit(&self, event: ResearchEvent) {
+        tracing::info!(target: "deep_research", step = event.name(), "{}", serde_json::to_string(&event).unwrap_or_default());
+        if let Some(progress) = self.progress {
+            let _ = progress.send(event);
+        }
+    }
+
+    /// Breaks the topic into sub-questions, or on later rounds asks for
+    /// follow-up questions on gaps in the findings.
+    async fn plan(&mut self) -> Result<Vec<String>, ResearchError> {
+        let prompt = match self.round {
+            0 => format!(
+                "Break the following research question into at most {} focused sub-questions that together answer it.\n\n\
+                 Research question: {}\n\n\
+                 Respond with a JSON array of strings and nothing else.",
+                self.breadth, self.topic
+            ),
+            _ => format!(
+                "Research question: {}\n\nFindings so far:\n\n{}\n\n\
+                 Identify the most important gaps in these findings and write at most {} follow-up questions that \
+                 would fill them. Do not repeat questions that were already researched.\n\n\
+                 Respond with a JSON array of strings and nothing else, or [] if nothing important is missing.",
+                self.topic,
+                self.findings_text(),
+                self.breadth
+            ),
+        };
+        let reply = self
+            .researcher
+            .llm
+            .complete(
+                &self.researcher.large_model,
+                &[ChatMessage::system("You are an expert research strategist."), ChatMessage::user(prompt)],
+            )
+            .await?;
+
+        let asked: Vec<&str> = self.findings.iter().map(|f| f.question.as_str()).collect();
+        let mut questions = parse_questions(&reply, &asked, self.breadth);
+        if questions.is_empty() && self.round == 0 {
+            questions.push(self.topic.to_string());
+        }
+        self.emit(ResearchEvent::Plan {
+            round: self.round + 1,
+            questions: questions.clone(),
+        });
+        Ok(questions)
+    }
+
+    async fn search(&self, questions: Vec<String>) -> Result<Vec<(String, Vec<SearchResult>)>, ResearchError> {
+        let searches = questions.iter().map(|question| {
+            let mut query = SearxngQuery::new(question.clone());
+            query.categories = vec!["general".to_string()];
+            collect_results(&self.researcher.searxng, query, SOURCES_PER_QUESTION)
+        });
+        let results = join_all(searches).await;
+        let mut hits = Vec::new();
+        for (question, results) in questions.into_iter().zip(results) {
+            let results = results?;
+            self.emit(ResearchEvent::Search {
+                question: question.clone(),
+                results: results.len(),
+            });
+            hits.push((question, results));
+        }
+        Ok(hits)
+    }
+
+    /// Reads every new result page, numbering it as a source. Pages that
+    /// cannot be fetched fall back to their search snippet.
+    async fn scrape(&mut self, hits: Vec<(String, Vec<SearchResult>)>) -> Vec<(String, Vec<usize>)> {
+        let mut new_results: Vec<&SearchResult> = Vec::new();
+        for result in hits.iter().flat_map(|(_, results)| results) {
+            let known = self.sources.iter().any(|s| s.source.url == result.url)
+                || new_results.iter().any(|r| r.url == result.url);
+            if !known {
+                new_results.push(result);
+            }
+        }
+
+        let fetcher = &self.researcher.fetcher;
+        let pages = join_all(new_results.iter().map(|result| async move {
+            let fetched = fetcher.fetch(&result.url).await?;
+            scrape::extract(&fetched, ScrapeFormat::Markdown, &fetcher.options().document_limits)
+        }))
+        .await;
+
+        for (result, page) in new_results.into_iter().zip(pages) {
+            let (title, excerpt, error) = match page {
+                Ok(page) if !page.content.trim().is_empty() => (
+                    page.title.unwrap_or_else(|| result.title.clone()),
+                    truncate_chars(&page.content, MAX_SOURCE_CHARS),
+                    None,
+                ),
+                Ok(_) => (result.title.clone(), result.snippet.clone(), Some("no readable content".to_string())),
+                Err(e) => (result.title.clone(), result.snippet.clone(), Some(e.to_string())),
+            };
+            self.emit(ResearchEvent::Scrape {
+                url: result.url.clone(),
+                error,
+            });
+            self.sources.push(ReadPage {
+                source: Source::new(result.url.clone(), title, &result.snippet),
+                excerpt,
+            });
+        }
+
+        hits.into_iter()
+            .map(|(question, results)| {
+                let numbers = results
+                    .iter()
+                    .filter_map(|r| self.sources.iter().position(|s| s.source.url == r.url))
+                    .map(|i| i + 1)
+                    .collect();
+                (question, numbers)
+            })
+            .collect()
+    }
+
+    async fn summarize(&mut self, questions: Vec<(String, Vec<usize>)>) -> Result<(), ResearchError> {
+        let prompts: Vec<String> = questions
+            .iter()
+            .map(|(question, numbers)| {
+                let sources = numbers
+                    .iter()
+                    .map(|&n| {
+                        let page = &self.sources[n - 1];
+                        format!("[{}] {} ({})\n{}", n, page.source.title, page.source.url, page.excerpt)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                format!(
+                    "Question: {}\n\nSources:\n\n{}\n\n\
+                     Answer the question in a few concise paragraphs using only these sources. \
+                     If they do not answer it, say what is missing.",
+                    question,
+                    if sources.is_empty() { "(none found)".to_string() } else { sources }
+                )
+            })
+            .collect();
+        let (llm, model) = (&self.researcher.llm, &self.researcher.small_model);
+        let summaries = join_all(prompts.into_iter().map(|prompt| async move {
+            let messages = [
+                ChatMessage::system(
+                    "You are an expert researcher. Cite the sources you use with their numbers in square brackets, e.g. [2].",
+                ),
+                ChatMessage::user(prompt),
+            ];
+            llm.complete(model, &messages).await
+        }))
+        .await;
+
+        for ((question, sources), summary) in questions.into_iter().zip(summaries) {
+            let summary = ground_citations(summary?.trim(), self.sources.len());
+            self.emit(ResearchEvent::Summarize {
+                question: question.clone(),
+                sources: sources.clone(),
+            });
+            self.findings.push(ResearchFinding {
+                question,
+                summary,
+                sources,
+            });
+        }
+        Ok(())
+    }
+
+    async fn synthesize(self) -> Result<ResearchOutput, ResearchError> {
+        self.emit(ResearchEvent::Synthesize {
+            findings: self.findings.len(),
+            sources: self.sources.len(),
+        });
+        let prompt = format!(
+            "Research question: {}\n\nFindings:\n\n{}\n\n\
+             Write a Markdown report that directly answers the research question and integrates the findings, \
+             ending with a short section on limitations and open questions. Keep the numbered citations from the \
+             findings, e.g. [3]. Do not add a list of sources; one is appended automatically.",
+            self.topic,
+            self.findings_text()
+        );
+        let report = self
+            .researcher
+            .llm
+            .complete(
+                &self.researcher.large_model,
+                &[ChatMessage::system("You are an expert research synthesizer."), ChatMessage::user(prompt)],
+            )
+            .await?;
+
+        let report = ground_citations(report.trim(), self.sources.len());
+        let sources: Vec<Source> = self.sources.into_iter().map(|page| page.source).collect();
+        Ok(ResearchOutput {
+            topic: self.topic.to_string(),
+            report: with_source_list(&report, &sources),
+            findings: self.findings,
+            sources,
+        })
+    }
+
+    fn findings_text(&self) -> String {
+        self.findings
+            .iter()
+            .map(|f| format!("### {}\n\n{}", f.question, f.summary))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// Reads the questions out of a model reply: a JSON array of strings or of
+/// `{"question": ...}` objects, an object holding such an array, or failing
+/// that one question per line. Questions already in `asked` are dropped.
+fn parse_questions(reply: &str, asked: &[&str], limit: usize) -> Vec<String> {
+    let json = match (reply.find(['[', '{']), reply.rfind([']', '}'])) {
+        (Some(start), Some(end)) if start < end => serde_json::from_str::<Value>(&reply[start..=end]).ok(),
+        _ => None,
+    };
+    let items = match json {
+        Some(Value::Array(items)) => Some(items),
+        Some(Value::Object(fields)) => fields.into_iter().find_map(|(_, value)| match value {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }),
+        _ => None,
+    };
+    let candidates: Vec<String> = match items {
+        Some(items) => items
+            .into_iter()
+            .filter_map(|item| match item {
+                Value::String(question) => Some(question),
+                Value::Object(fields) => fields.get("question").and_then(Value::as_str).map(str::to_string),
+                _ => None,
+            })
+            .collect(),
+        None => reply
+            .lines()
+            .map(|line| line.trim().trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '-' | '*' | '.' | ')')))
+            .filter(|line| line.ends_with('?'))
+            .map(str::to_string)
+            .collect(),
+    };
+
+    let mut questions: Vec<String> = Vec::new();
+    for question in candidates {
+        let question = question.trim().to_string();
+        let seen = |other: &str| other.eq_ignore_ascii_case(&question);
+        if !question.is_empty() && !asked.iter().any(|q| seen(q)) && !questions.iter().any(|q| seen(q)) {
+            questions.push(question);
+        }
+    }
+    questions.truncate(limit);
+    questions
+}
+
+/// Appends the numbered source list the report's citation markers refer to.
+fn with_source_list(report: &str, sources: &[Source]) -> String {
+    if sources.is_empty() {
+        return report.to_string();
+    }
+    let sources = sources
+        .iter()
+        .enumerate()
+        .map(|(i, s)| format!("[{}] [{}]({})", i + 1, s.title, s.url))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("{}\n\n## Sources\n\n{}", report, sources)
+}
 
 #[cfg(test)]
 mod tests {
-    use crate::agents::deep_research::agent;
-    use std::fmt::Debug;
+    use super::*;
+    use crate::agents::llm::tests::mock_chat;
+    use crate::scraping::fetch::tests::local_options;
+    use axum::extract::{Path, Query};
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use std::collections::HashMap;
+
+    /// A SearxNG instance whose results link to pages on the same server.
+    /// Every question finds its own page plus a shared overview page, and
+    /// one page is missing so its snippet stands in for it.
+    async fn mock_web() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let links = base_url.clone();
+        let app = Router::new()
+            .route(
+                "/search",
+                get(move |Query(params): Query<HashMap<String, String>>| {
+                    let base_url = links.clone();
+                    async move {
+                        let query = params.get("q").cloned().unwrap_or_default();
+                        let page = params.get("pageno").map(String::as_str).unwrap_or("1");
+                        let results = match page {
+                            "1" => {
+                                let slug = query.to_lowercase().replace(|c: char| !c.is_alphanumeric(), "-");
+                                serde_json::json!([
+                                    { "url": format!("{}/pages/{}", base_url, slug), "title": query, "content": "snippet" },
+                                    { "url": format!("{}/pages/overview", base_url), "title": "Overview", "content": "overview snippet" },
+                                ])
+                            }
+                            _ => serde_json::json!([]),
+                        };
+                        Json(serde_json::json!({ "query": query, "results": results }))
+                    }
+                }),
+            )
+            .route(
+                "/pages/{slug}",
+                get(|Path(slug): Path<String>| async move {
+                    if slug.starts_with("why") {
+                        return Err(axum::http::StatusCode::NOT_FOUND);
+                    }
+                    Ok(axum::response::Html(format!(
+                        "<html><head><title>Page {slug}</title></head><body><article><p>Everything about {slug}.</p></article></body></html>"
+                    )))
+                }),
+            );
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        base_url
+    }
+
+    /// Answers each prompt by the step it belongs to.
+    fn research_reply(body: &Value) -> String {
+        let prompt = body["messages"][1]["content"].as_str().unwrap();
+        if prompt.starts_with("Break the following") {
+            r#"Here you go: ["What is Rust?", "Why use Rust?", "what is rust?"]"#.to_string()
+        } else if prompt.contains("follow-up questions") {
+            "1. Who maintains Rust?\n2. Why use Rust?".to_string()
+        } else if prompt.starts_with("Question: ") {
+            let sources = prompt.matches("\n[").count();
+            format!("{} is answered by {} sources [1].", prompt.lines().next().unwrap(), sources)
+        } else {
+            assert_eq!(body["model"], "large");
+            "# Rust\n\nRust is a systems language [1][2][9].".to_string()
+        }
+    }
 
     #[tokio::test]
-    #[ignore]
-    async fn test_deepresearch() {
-        // a really provocative question for research that generally yields infinite complexity with each run
-        let input = "What is a life of meaning?";
+    async fn test_research_steps_through_rounds_and_cites_sources() {
+        let researcher = Researcher {
+            llm: ChatClient::new(mock_chat(research_reply).await, Some("test-key".to_string())),
+            searxng: SearxngClient::new(mock_web().await, None).unwrap(),
+            fetcher: Fetcher::new(local_options()),
+            large_model: "large".to_string(),
+            small_model: "small".to_string(),
+        };
+        let request = ResearchRequest {
+            topic: "What is Rust?".to_string(),
+            breadth: Some(2),
+            depth: None,
+        };
+        let (progress, mut events) = tokio::sync::mpsc::unbounded_channel();
+
+        let output = native_agent(&researcher, &request, Some(&progress)).await.unwrap();
+        drop(progress);
 
-        let mut command = agent("test-deepresearch-agent", input).await.unwrap();
+        let questions: Vec<&str> = output.findings.iter().map(|f| f.question.as_str()).collect();
+        assert_eq!(questions, ["What is Rust?", "Why use Rust?", "Who maintains Rust?"]);
+        // the overview page is shared, so it keeps the number it got first
+        assert_eq!(output.findings[0].sources, [1, 2]);
+        assert_eq!(output.findings[1].sources, [3, 2]);
+        assert_eq!(output.findings[2].sources, [4, 2]);
+        assert_eq!(output.findings[0].summary, "Question: What is Rust? is answered by 2 sources [1].");
 
-        // let mut stdout = String::new();
+        assert_eq!(output.sources[0].title, "Page what-is-rust-");
+        // the missing page is cited by its search result title
+        assert_eq!(output.sources[2].title, "Why use Rust?");
+        // the made-up [9] is dropped, so every marker left names a source
+        assert!(output.report.starts_with("# Rust\n\nRust is a systems language [1][2].\n\n## Sources\n\n[1] [Page what-is-rust-]("));
+        assert_eq!(output.sources.len(), 4);
+        assert_eq!(output.sources[2].snippet, "snippet");
+        assert!(output.report.ends_with(&format!("[4] [Page who-maintains-rust-]({})", output.sources[3].url)));
 
-        // command.stdout.take().unwrap().read_to_string(&mut stdout).await.unwrap();
+        let mut steps = Vec::new();
+        while let Some(event) = events.recv().await {
+            steps.push(event.name());
+        }
+        assert_eq!(
+            steps,
+            [
+                "plan", "search", "search", "scrape", "scrape", "scrape", "summarize", "summarize",
+                "plan", "search", "scrape", "summarize", "synthesize",
+            ]
+        );
+    }
 
-        // println!("stdout: {}", stdout);
-        // // Optionally, you can capture and inspect stdout if needed:
-        let _output = command.wait_with_output().await.expect("Failed to wait for output");
-        // println!("Stdout: {}", String::from_utf8_lossy(&output.stdout));
-        // println!("Stderr: {}", String::from_utf8_lossy(&output.stderr));
+    #[test]
+    fn test_parse_questions() {
+        assert_eq!(parse_questions(r#"["A?", "B?", "C?"]"#, &[], 2), ["A?", "B?"]);
+        assert_eq!(
+            parse_questions(
+                "```json\n{\"subQuestions\": [{\"id\": \"SQ1\", \"question\": \"A?\"}, {\"question\": \"B?\"}]}\n```",
+                &["b?"],
+                5
+            ),
+            ["A?"]
+        );
+        assert_eq!(parse_questions("Sure:\n1. First?\n2) Second?\n- not a question", &[], 5), ["First?", "Second?"]);
+        assert!(parse_questions("[]", &[], 3).is_empty());
     }
-} 
+}
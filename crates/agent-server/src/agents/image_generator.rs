@@ -1,6 +1,45 @@
 use crate::utils::utils::run_agent;
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use tokio::process::Child;
 
+/// Directory the image generator script writes its images to.
+pub const IMAGE_DIR: &str = "./open-web-agent-rs/generated/images";
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ImageRequest {
+    #[schemars(description = "The image description")]
+    pub description: String,
+    #[schemars(description = "Also return the image as an embedded resource with its HTTP URI")]
+    pub include_resource: Option<bool>,
+}
+
+/// Input handed to `image-generator.genai.mts`.
+#[derive(Debug, Serialize)]
+pub struct ImageAgentInput<'a> {
+    pub description: &'a str,
+    #[serde(rename = "imageId")]
+    pub image_id: &'a str,
+    pub output_dir: &'a str,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneratedImage {
+    pub id: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+impl GeneratedImage {
+    /// Public URL the image is served from by the agent-server.
+    pub fn uri(&self) -> String {
+        let base = std::env::var("AGENT_SERVER_BASE_URL")
+            .unwrap_or_else(|_| "http://localhost:3006".to_string());
+        format!("{}/generated/image/{}", base.trim_end_matches('/'), self.id)
+    }
+}
+
 pub async fn agent(stream_id: &str, input: &str) -> Result<Child, String> {
     tracing::debug!(
                 "Running image generator, \ninput: {}",
@@ -8,3 +47,87 @@ pub async fn agent(stream_id: &str, input: &str) -> Result<Child, String> {
             );
     run_agent(stream_id, input, "./packages/genaiscript/genaisrc/image-generator.genai.mts", 10).await
 }
+
+/// Loads a generated image from [`IMAGE_DIR`].
+pub async fn load_image(id: &str) -> std::io::Result<GeneratedImage> {
+    load_image_from(Path::new(IMAGE_DIR), id).await
+}
+
+/// Loads the image stored as `<dir>/<id>.<ext>`, detecting its MIME type.
+pub async fn load_image_from(dir: &Path, id: &str) -> std::io::Result<GeneratedImage> {
+    // ids are generated uuids; anything else could escape the image directory
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid image id: {}", id),
+        ));
+    }
+
+    let path = find_image(dir, id).await?;
+    let data = tokio::fs::read(&path).await?;
+    let mime_type = sniff_mime_type(&data)
+        .map(str::to_string)
+        .unwrap_or_else(|| mime_guess::from_path(&path).first_or_octet_stream().to_string());
+
+    Ok(GeneratedImage {
+        id: id.to_string(),
+        mime_type,
+        data,
+    })
+}
+
+async fn find_image(dir: &Path, id: &str) -> std::io::Result<PathBuf> {
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.file_stem().and_then(|s| s.to_str()) == Some(id) {
+            return Ok(path);
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("No generated image with id {}", id),
+    ))
+}
+
+/// Detects common image formats from their magic bytes.
+fn sniff_mime_type(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_mime_type() {
+        assert_eq!(sniff_mime_type(b"\x89PNG\r\n\x1a\n"), Some("image/png"));
+        assert_eq!(sniff_mime_type(&[0xFF, 0xD8, 0xFF, 0xE0]), Some("image/jpeg"));
+        assert_eq!(sniff_mime_type(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff_mime_type(b"not an image"), None);
+    }
+
+    #[tokio::test]
+    async fn test_load_image_from_dir() {
+        let dir = std::env::temp_dir().join(format!("images-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(dir.join("abc-123.png"), b"\x89PNG\r\n\x1a\nrest")
+            .await
+            .unwrap();
+
+        let image = load_image_from(&dir, "abc-123").await.unwrap();
+        assert_eq!(image.mime_type, "image/png");
+        assert_eq!(image.data.len(), 12);
+
+        assert!(load_image_from(&dir, "missing").await.is_err());
+        assert!(load_image_from(&dir, "../abc-123").await.is_err());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(180);
+/// genaiscript model ids may name their provider, e.g. `openai:gpt-4o`.
+const PROVIDER_PREFIXES: [&str; 7] = ["openai:", "azure:", "ollama:", "lmstudio:", "llamafile:", "litellm:", "github:"];
+
+#[derive(Debug)]
+pub enum LlmError {
+    NotConfigured(&'static str),
+    Http(reqwest::Error),
+    Status(reqwest::StatusCode, String),
+    EmptyResponse,
+}
+
+impl std::fmt::Display for LlmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LlmError::NotConfigured(key) => write!(f, "{} is not set", key),
+            LlmError::Http(e) => write!(f, "Chat completion request failed: {}", e),
+            LlmError::Status(status, body) => {
+                write!(f, "Chat completion endpoint responded with {}: {}", status, body)
+            }
+            LlmError::EmptyResponse => write!(f, "Chat completion returned no message"),
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(e: reqwest::Error) -> Self {
+        LlmError::Http(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: "system".to_string(),
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    temperature: f32,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    #[serde(default)]
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+/// Client for an OpenAI-compatible `/chat/completions` endpoint.
+#[derive(Debug, Clone)]
+pub struct ChatClient {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl ChatClient {
+    pub fn new(base_url: impl Into<String>, api_key: Option<String>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: api_key.filter(|k| !k.is_empty()),
+        }
+    }
+
+    /// Builds a client from `OPENAI_API_BASE` and `OPENAI_API_KEY`.
+    pub fn from_env() -> Result<Self, LlmError> {
+        let base_url = std::env::var("OPENAI_API_BASE").unwrap_or_default();
+        if base_url.is_empty() {
+            return Err(LlmError::NotConfigured("OPENAI_API_BASE"));
+        }
+        Ok(Self::new(base_url, std::env::var("OPENAI_API_KEY").ok()))
+    }
+
+    /// Returns the assistant message for `messages`.
+    pub async fn complete(&self, model: &str, messages: &[ChatMessage]) -> Result<String, LlmError> {
+        let mut request = self
+            .http
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&ChatRequest {
+                model,
+                messages,
+                temperature: 0.2,
+            });
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(LlmError::Status(status, body));
+        }
+        let response: ChatResponse = response.json().await?;
+        response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content)
+            .filter(|content| !content.trim().is_empty())
+            .ok_or(LlmError::EmptyResponse)
+    }
+}
+
+/// Reads a model id from `key`, dropping a genaiscript provider prefix.
+pub fn model_from_env(key: &str) -> Option<String> {
+    let model = std::env::var(key).ok()?;
+    let model = model.trim();
+    let model = PROVIDER_PREFIXES
+        .iter()
+        .find_map(|prefix| model.strip_prefix(prefix))
+        .unwrap_or(model);
+    Some(model.to_string()).filter(|m| !m.is_empty())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use serde_json::Value;
+
+    /// Serves an OpenAI-compatible chat endpoint that answers with `reply`
+    /// applied to the request body.
+    pub(crate) async fn mock_chat(reply: fn(&Value) -> String) -> String {
+        let app = Router::new().route(
+            "/v1/chat/completions",
+            post(move |headers: HeaderMap, Json(body): Json<Value>| async move {
+                if headers.get("authorization").and_then(|h| h.to_str().ok()) != Some("Bearer test-key") {
+                    return Err(StatusCode::UNAUTHORIZED);
+                }
+                Ok(Json(serde_json::json!({
+                    "id": "chatcmpl-test",
+                    "object": "chat.completion",
+                    "model": body["model"],
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": reply(&body) },
+                        "finish_reason": "stop",
+                    }],
+                })))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}/v1", addr)
+    }
+
+    #[tokio::test]
+    async fn test_complete_returns_first_choice() {
+        let base_url = mock_chat(|body| {
+            format!("{} said: {}", body["model"].as_str().unwrap(), body["messages"][1]["content"].as_str().unwrap())
+        })
+        .await;
+
+        let client = ChatClient::new(format!("{}/", base_url), Some("test-key".to_string()));
+        let reply = client
+            .complete("small", &[ChatMessage::system("Be brief."), ChatMessage::user("hello")])
+            .await
+            .unwrap();
+        assert_eq!(reply, "small said: hello");
+
+        let unauthorized = ChatClient::new(base_url, None);
+        assert!(matches!(
+            unauthorized.complete("small", &[ChatMessage::user("hello")]).await,
+            Err(LlmError::Status(StatusCode::UNAUTHORIZED, _))
+        ));
+    }
+}
@@ -0,0 +1,248 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use rmcp::model::{LoggingLevel, LoggingMessageNotificationParam};
+use rmcp::service::Peer;
+use rmcp::RoleServer;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+tokio::task_local! {
+    /// Sink for log records emitted while handling the current MCP request.
+    static REQUEST_LOG: RequestLog;
+}
+
+/// Where the current request's log records go, and the lowest level its session wants.
+struct RequestLog {
+    sink: mpsc::UnboundedSender<LogRecord>,
+    level: LogLevel,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub level: LoggingLevel,
+    pub logger: String,
+    pub data: Value,
+}
+
+/// Orders MCP logging levels from `debug` (0) to `emergency` (7).
+pub fn severity(level: &LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
+
+fn from_tracing(level: &tracing::Level) -> LoggingLevel {
+    match *level {
+        tracing::Level::ERROR => LoggingLevel::Error,
+        tracing::Level::WARN => LoggingLevel::Warning,
+        tracing::Level::INFO => LoggingLevel::Info,
+        _ => LoggingLevel::Debug,
+    }
+}
+
+/// The minimum level a client asked for with `logging/setLevel`.
+#[derive(Debug, Clone)]
+pub struct LogLevel(Arc<AtomicU8>);
+
+impl Default for LogLevel {
+    fn default() -> Self {
+        Self::new(&LoggingLevel::Info)
+    }
+}
+
+impl LogLevel {
+    pub fn new(level: &LoggingLevel) -> Self {
+        Self(Arc::new(AtomicU8::new(severity(level))))
+    }
+
+    pub fn set(&self, level: &LoggingLevel) {
+        self.0.store(severity(level), Ordering::Relaxed);
+    }
+
+    pub fn enabled(&self, level: &LoggingLevel) -> bool {
+        severity(level) >= self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Forwards tracing events emitted inside [`with_request_logging`] to the MCP
+/// client. Events below the session's level are dropped before their fields
+/// are recorded.
+pub struct McpLogLayer;
+
+impl<S: Subscriber> Layer<S> for McpLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let _ = REQUEST_LOG.try_with(|log| {
+            let level = from_tracing(event.metadata().level());
+            if !log.level.enabled(&level) {
+                return;
+            }
+            let mut fields = FieldVisitor(Map::new());
+            event.record(&mut fields);
+            let _ = log.sink.send(LogRecord {
+                level,
+                logger: event.metadata().target().to_string(),
+                data: Value::Object(fields.0),
+            });
+        });
+    }
+}
+
+struct FieldVisitor(Map<String, Value>);
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), Value::from(format!("{:?}", value)));
+    }
+}
+
+/// Runs `future` with its log records at `level` or above captured into `sink`.
+pub async fn scoped<F: Future>(sink: mpsc::UnboundedSender<LogRecord>, level: LogLevel, future: F) -> F::Output {
+    REQUEST_LOG.scope(RequestLog { sink, level }, future).await
+}
+
+/// Runs an agent that reports progress, sending each update to the client of
+/// the current request as an `info` message from the `progress` logger, with
+/// the update under `progress` and its name under `event`.
+pub async fn log_progress<P, F>(event_name: fn(&P) -> &'static str, run: impl FnOnce(mpsc::UnboundedSender<P>) -> F) -> F::Output
+where
+    P: Serialize,
+    F: Future,
+{
+    let (progress, mut updates) = mpsc::unbounded_channel();
+    let forward = async move {
+        while let Some(update) = updates.recv().await {
+            let _ = REQUEST_LOG.try_with(|log| {
+                if !log.level.enabled(&LoggingLevel::Info) {
+                    return;
+                }
+                let _ = log.sink.send(LogRecord {
+                    level: LoggingLevel::Info,
+                    logger: "progress".to_string(),
+                    data: serde_json::json!({ "event": event_name(&update), "progress": update }),
+                });
+            });
+        }
+    };
+    // Joined rather than spawned, so the forwarding stays inside the request's scope
+    let (output, ()) = tokio::join!(run(progress), forward);
+    output
+}
+
+/// Runs `future`, sending the tracing events it emits to the client as
+/// `notifications/message`, filtered by the client-selected level.
+pub async fn with_request_logging<F: Future>(
+    peer: Peer<RoleServer>,
+    level: LogLevel,
+    future: F,
+) -> F::Output {
+    let (sink, mut records) = mpsc::unbounded_channel::<LogRecord>();
+
+    let forwarder = tokio::spawn(async move {
+        while let Some(record) = records.recv().await {
+            let notification = LoggingMessageNotificationParam {
+                level: record.level,
+                logger: Some(record.logger),
+                data: record.data,
+            };
+            if peer.notify_logging_message(notification).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let output = scoped(sink, level, future).await;
+    let _ = forwarder.await;
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn test_level_filter() {
+        let level = LogLevel::default();
+        assert!(!level.enabled(&LoggingLevel::Debug));
+        assert!(level.enabled(&LoggingLevel::Info));
+        assert!(level.enabled(&LoggingLevel::Error));
+
+        level.set(&LoggingLevel::Error);
+        assert!(!level.enabled(&LoggingLevel::Warning));
+        assert!(level.enabled(&LoggingLevel::Critical));
+    }
+
+    #[tokio::test]
+    async fn test_captures_events_for_the_current_request_only() {
+        let _guard = tracing_subscriber::registry().with(McpLogLayer).set_default();
+        let (sink, mut records) = mpsc::unbounded_channel();
+
+        tracing::info!("outside the request");
+        scoped(sink, LogLevel::default(), async {
+            tracing::debug!("below the session's level");
+            tracing::warn!(target: "agent_stderr", stream_id = "tool-search", "searxng unreachable");
+        })
+        .await;
+
+        let record = records.recv().await.unwrap();
+        assert_eq!(record.level, LoggingLevel::Warning);
+        assert_eq!(record.logger, "agent_stderr");
+        assert_eq!(record.data["message"], "searxng unreachable");
+        assert_eq!(record.data["stream_id"], "tool-search");
+        assert!(records.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_log_progress_sends_each_update() {
+        let (sink, mut records) = mpsc::unbounded_channel();
+        let run = |progress: mpsc::UnboundedSender<u32>| async move {
+            progress.send(1).unwrap();
+            progress.send(2).unwrap();
+            "done"
+        };
+        let output = scoped(sink, LogLevel::default(), log_progress(|_| "step", run)).await;
+        assert_eq!(output, "done");
+
+        let first = records.recv().await.unwrap();
+        assert_eq!(first.level, LoggingLevel::Info);
+        assert_eq!(first.logger, "progress");
+        assert_eq!(first.data, serde_json::json!({ "event": "step", "progress": 1 }));
+        assert_eq!(records.recv().await.unwrap().data["progress"], 2);
+        assert!(records.recv().await.is_none());
+
+        // Clients that only want warnings get the output without the updates
+        let (sink, mut records) = mpsc::unbounded_channel();
+        scoped(sink, LogLevel::new(&LoggingLevel::Warning), log_progress(|_| "step", run)).await;
+        assert!(records.recv().await.is_none());
+    }
+}
@@ -3,33 +3,58 @@ pub(crate) mod scrape;
 pub(crate) mod search;
 pub(crate) mod image_generator;
 pub(crate) mod deep_research;
+pub(crate) mod output;
+pub(crate) mod logging;
+pub(crate) mod searxng;
+pub(crate) mod crawl;
+pub(crate) mod llm;
+pub(crate) mod cache;
 
 use std::sync::Arc;
 
 use rmcp::{
-    Error as McpError, RoleServer, ServerHandler, const_string, model::*,
-    service::RequestContext, tool,
+    Error as McpError, RoleServer, ServerHandler, const_string, handler::server::tool::ToolCallContext,
+    model::*, service::RequestContext, tool,
 };
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Child;
 
+use crate::scraping::{self, ScrapeError};
+use crate::upstream::{self, UpstreamRegistry};
+use crate::utils::base64::B64_ENCODER;
+use cache::CacheKind;
+use logging::LogLevel;
+use searxng::SearxngClient;
+
 #[derive(Clone)]
-pub struct Agents;
+pub struct Agents {
+    upstreams: Arc<UpstreamRegistry>,
+    log_level: LogLevel,
+}
 
 #[tool(tool_box)]
 impl Agents {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            upstreams: upstream::registry(),
+            log_level: LogLevel::default(),
+        }
     }
 
     #[tool(description = "Search the web for information")]
     async fn search(
         &self,
-        #[tool(param)]
-        #[schemars(description = "The search query")]
-        query: String,
+        #[tool(aggr)] request: search::SearchRequest,
     ) -> Result<CallToolResult, McpError> {
-        match search::agent("tool-search", &query).await {
-            Ok(child) => handle_agent_result(child).await,
+        let client = SearxngClient::from_env()
+            .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+        let search = search::native_agent(&client, &request);
+        match cache::cached(CacheKind::Search, &request.cache_key(), request.no_cache, search).await {
+            Ok(structured) => {
+                let text = output::render_results_table(&structured.results);
+                structured_result(text, structured)
+            }
             Err(e) => Err(McpError::internal_error(e.to_string(), None))
         }
     }
@@ -37,66 +62,110 @@ impl Agents {
     #[tool(description = "Search for news articles")]
     async fn news(
         &self,
-        #[tool(param)]
-        #[schemars(description = "The news search query")]
-        query: String,
+        #[tool(aggr)] request: news::NewsRequest,
     ) -> Result<CallToolResult, McpError> {
-        match news::agent("tool-news", &query).await {
-            Ok(child) => handle_agent_result(child).await,
+        let client = SearxngClient::from_env()
+            .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+        let news = news::native_agent(&client, &request);
+        match cache::cached(CacheKind::News, &request.cache_key(), request.no_cache, news).await {
+            Ok(structured) => {
+                let text = output::render_news_table(&structured.clusters);
+                structured_result(text, structured)
+            }
             Err(e) => Err(McpError::internal_error(e.to_string(), None))
         }
     }
 
-    #[tool(description = "Scrape content from a webpage")]
+    #[tool(description = "Scrape content from a webpage, PDF, Word, PowerPoint, OpenDocument or plain text file")]
     async fn scrape(
         &self,
-        #[tool(param)]
-        #[schemars(description = "The URL to scrape")]
-        url: String,
+        #[tool(aggr)] request: scrape::ScrapeRequest,
     ) -> Result<CallToolResult, McpError> {
-        match scrape::agent("tool-scrape", &url).await {
-            Ok(child) => handle_agent_result(child).await,
-            Err(e) => Err(McpError::internal_error(e.to_string(), None))
+        let fetcher = scraping::fetcher();
+        let scrape = scrape::native_agent(&fetcher, &request);
+        match cache::cached(CacheKind::Scrape, &request.cache_key(), request.no_cache, scrape).await {
+            Ok(structured) => structured_result(structured.content.clone(), structured),
+            Err(e) => Err(scrape_error(e))
+        }
+    }
+
+    #[tool(description = "Crawl a website from a starting page, following same-origin links, and return the pages as one Markdown document")]
+    async fn crawl(
+        &self,
+        #[tool(aggr)] request: crawl::CrawlRequest,
+    ) -> Result<CallToolResult, McpError> {
+        let fetcher = scraping::fetcher();
+        // each page reaches the client as a log message while the crawl runs
+        let run = |progress| async move { crawl::native_agent(&fetcher, &request, Some(&progress)).await };
+        match logging::log_progress(|_: &crawl::CrawlProgress| "page", run).await {
+            Ok(structured) => structured_result(structured.markdown.clone(), structured),
+            Err(e) => Err(scrape_error(e))
         }
     }
 
     #[tool(description = "Generate an image based on a description")]
     async fn generate_image(
         &self,
-        #[tool(param)]
-        #[schemars(description = "The image description")]
-        description: String,
+        #[tool(aggr)] request: image_generator::ImageRequest,
     ) -> Result<CallToolResult, McpError> {
-        match image_generator::agent("tool-image", &description).await {
-            Ok(child) => handle_agent_result(child).await,
+        let image_id = uuid::Uuid::new_v4().to_string();
+        let input = agent_input(&image_generator::ImageAgentInput {
+            description: &request.description,
+            image_id: &image_id,
+            output_dir: image_generator::IMAGE_DIR,
+        })?;
+
+        match image_generator::agent("tool-image", &input).await {
+            Ok(child) => {
+                let text = collect_agent_output(child).await?;
+                let image = image_generator::load_image(&image_id).await.map_err(|e| {
+                    McpError::internal_error(format!("Failed to load generated image: {}", e), None)
+                })?;
+                let data = B64_ENCODER.b64_encode_payload(&image.data);
+
+                let mut content = vec![
+                    Content::image(data.clone(), image.mime_type.clone()),
+                    Content::text(text),
+                ];
+                if request.include_resource.unwrap_or(false) {
+                    content.push(Content::resource(ResourceContents::BlobResourceContents {
+                        uri: image.uri(),
+                        mime_type: Some(image.mime_type),
+                        blob: data,
+                    }));
+                }
+                Ok(CallToolResult::success(content))
+            }
             Err(e) => Err(McpError::internal_error(e.to_string(), None))
         }
     }
 
-    #[tool(description = "Perform deep research on a topic")]
+    #[tool(description = "Perform deep research on a topic: plan sub-questions, search and read sources, then write a cited report")]
     async fn deep_research(
         &self,
-        #[tool(param)]
-        #[schemars(description = "The research topic")]
-        topic: String,
+        #[tool(aggr)] request: deep_research::ResearchRequest,
     ) -> Result<CallToolResult, McpError> {
-        match deep_research::agent("tool-research", &topic).await {
-            Ok(child) => handle_agent_result(child).await,
+        let researcher = deep_research::Researcher::from_env()
+            .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+        // each step reaches the client as a log message while the research runs
+        let research = |progress| async move { deep_research::native_agent(&researcher, &request, Some(&progress)).await };
+        match logging::log_progress(deep_research::ResearchEvent::name, research).await {
+            Ok(structured) => structured_result(structured.report.clone(), structured),
             Err(e) => Err(McpError::internal_error(e.to_string(), None))
         }
     }
 }
 
-#[tool(tool_box)]
 impl ServerHandler for Agents {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             protocol_version: ProtocolVersion::V_2024_11_05,
             capabilities: ServerCapabilities::builder()
+                .enable_logging()
                 .enable_tools()
                 .build(),
             server_info: Implementation::from_build_env(),
-            instructions: Some("This server provides various agent tools for web search, news search, web scraping, image generation, and deep research.".to_string()),
+            instructions: Some("This server provides various agent tools for web search, news search, web scraping, site crawling, image generation, and deep research.".to_string()),
         }
     }
 
@@ -112,12 +181,119 @@ impl ServerHandler for Agents {
         }
         Ok(self.get_info())
     }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let mut tools = Self::tool_box().list();
+        tools.extend(self.upstreams.tools().await);
+        Ok(ListToolsResult {
+            next_cursor: None,
+            tools,
+        })
+    }
+
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        tracing::debug!("Client set logging level to {:?}", request.level);
+        self.log_level.set(&request.level);
+        Ok(())
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let peer = context.peer.clone();
+        let tool = request.name.to_string();
+
+        logging::with_request_logging(peer, self.log_level.clone(), async move {
+            tracing::info!(tool = %tool, "Calling tool");
+
+            // namespaced tools (`<upstream>.<tool>`) are proxied to the upstream MCP servers
+            let result = match self
+                .upstreams
+                .call_tool(&request.name, request.arguments.clone())
+                .await
+            {
+                Some(result) => result,
+                None => {
+                    let context = ToolCallContext::new(self, request, context);
+                    Self::tool_box().call(context).await
+                }
+            };
+
+            if let Err(e) = &result {
+                tracing::error!(tool = %tool, "Tool failed: {}", e.message);
+            }
+            result
+        })
+        .await
+    }
 }
 
-async fn handle_agent_result(mut child: Child) -> Result<CallToolResult, McpError> {
-    use tokio::io::AsyncReadExt;
+/// Serializes a tool request into the JSON input handed to the agent scripts.
+fn agent_input<T: Serialize>(request: &T) -> Result<String, McpError> {
+    serde_json::to_string(request)
+        .map_err(|e| McpError::invalid_params(format!("Failed to encode agent input: {}", e), None))
+}
+
+/// Returns the agent text together with its structured JSON representation.
+fn structured_result<T: Serialize>(text: String, structured: T) -> Result<CallToolResult, McpError> {
+    Ok(CallToolResult::success(vec![
+        Content::text(text),
+        Content::json(structured)?,
+    ]))
+}
+
+/// Maps scraper failures to MCP errors, reporting refused and malformed urls
+/// as invalid requests rather than server faults.
+fn scrape_error(e: ScrapeError) -> McpError {
+    match &e {
+        ScrapeError::Disallowed { url, user_agent } => McpError::invalid_request(
+            e.to_string(),
+            Some(serde_json::json!({
+                "reason": "robots_txt_disallowed",
+                "url": url,
+                "user_agent": user_agent,
+            })),
+        ),
+        ScrapeError::Blocked { url, reason } => McpError::invalid_request(
+            e.to_string(),
+            Some(serde_json::json!({
+                "reason": "blocked_address",
+                "url": url,
+                "detail": reason,
+            })),
+        ),
+        ScrapeError::InvalidUrl(_) => McpError::invalid_params(e.to_string(), None),
+        _ => McpError::internal_error(e.to_string(), None),
+    }
+}
+
+async fn collect_agent_output(mut child: Child) -> Result<String, McpError> {
+    // stderr lines are logged while the agent runs, so they reach the client as log messages
+    let stderr = child.stderr.take();
+    let log_stderr = async move {
+        let mut lines = Vec::new();
+        if let Some(stderr) = stderr {
+            let mut reader = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = reader.next_line().await {
+                tracing::info!(target: "agent_stderr", "{}", line);
+                lines.push(line);
+            }
+        }
+        lines
+    };
 
-    let output = match child.wait_with_output().await {
+    let (output, stderr_lines) = tokio::join!(child.wait_with_output(), log_stderr);
+    let output = match output {
         Ok(output) => output,
         Err(e) => return Err(McpError::internal_error(format!("Failed to get agent output: {}", e), None)),
     };
@@ -125,12 +301,12 @@ async fn handle_agent_result(mut child: Child) -> Result<CallToolResult, McpErro
     let stdout = String::from_utf8_lossy(&output.stdout).to_string();
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        let stderr = stderr_lines.join("\n");
         return Err(McpError::internal_error(
             format!("Agent failed with status {}: {}", output.status, stderr),
             None,
         ));
     }
 
-    Ok(CallToolResult::success(vec![Content::text(stdout)]))
+    Ok(stdout)
 }
@@ -1,6 +1,278 @@
-use crate::utils::utils::run_agent;
-use tokio::process::Child;
+use std::collections::HashSet;
 
-pub async fn agent(stream_id: &str, input: &str) -> Result<Child, String> {
-    run_agent(stream_id, input, "./packages/genaiscript/genaisrc/news-search.genai.mts", 10).await
+use crate::agents::cache::{normalize_query, without_tracking};
+use crate::agents::output::{NewsCluster, NewsOutput, NewsSource, SearchResult, Source};
+use crate::agents::search::{collect_results, TimeRange};
+use crate::agents::searxng::{SearxngClient, SearxngError, SearxngQuery};
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_MAX_RESULTS: usize = 5;
+/// Raw articles fetched per requested story, leaving room for duplicates.
+const ARTICLES_PER_STORY: usize = 4;
+/// Minimum word overlap for two headlines to be treated as the same story.
+const TITLE_SIMILARITY_THRESHOLD: f64 = 0.6;
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct NewsRequest {
+    #[schemars(description = "The news search query")]
+    pub query: String,
+    #[schemars(description = "Maximum number of articles to return (default 5)")]
+    pub max_results: Option<u32>,
+    #[schemars(description = "Only return articles published within this time range")]
+    pub time_range: Option<TimeRange>,
+    #[schemars(description = "Preferred article language, e.g. \"en\" or \"de\"")]
+    pub language: Option<String>,
+    #[schemars(description = "Skip cached results and search again")]
+    #[serde(default)]
+    pub no_cache: bool,
+}
+
+impl NewsRequest {
+    /// Key under which the stories found by this search are cached.
+    pub fn cache_key(&self) -> String {
+        serde_json::json!([normalize_query(&self.query), self.max_results, self.time_range, self.language]).to_string()
+    }
+}
+
+/// Searches SearxNG's `news` category and groups coverage of the same story.
+pub async fn native_agent(
+    client: &SearxngClient,
+    request: &NewsRequest,
+) -> Result<NewsOutput, SearxngError> {
+    let mut query = SearxngQuery::new(request.query.clone());
+    query.categories = vec!["news".to_string()];
+    query.time_range = request.time_range;
+    query.language = request.language.clone();
+
+    let max_results = request
+        .max_results
+        .map(|n| n as usize)
+        .unwrap_or(DEFAULT_MAX_RESULTS);
+    let articles = collect_results(client, query, max_results * ARTICLES_PER_STORY).await?;
+
+    let mut clusters = cluster(articles);
+    clusters.truncate(max_results);
+    tracing::debug!("Native news search for {:?} returned {} stories", request.query, clusters.len());
+
+    Ok(NewsOutput {
+        query: request.query.clone(),
+        sources: clusters
+            .iter()
+            .flat_map(|c| c.sources.iter().map(move |s| Source::new(s.url.clone(), s.title.clone(), &c.snippet)))
+            .collect(),
+        clusters,
+        cache: None,
+    })
+}
+
+/// Groups articles about the same story and sorts the stories by recency.
+///
+/// Articles are merged when their canonical urls match or their headlines
+/// share most of their words.
+pub fn cluster(articles: Vec<SearchResult>) -> Vec<NewsCluster> {
+    let mut clusters: Vec<(HashSet<String>, HashSet<String>, NewsCluster)> = Vec::new();
+
+    for article in articles {
+        let url = canonical_url(&article.url);
+        let words = title_words(&article.title);
+        let source = NewsSource {
+            outlet: host(&article.url),
+            title: article.title.clone(),
+            url: article.url.clone(),
+            published: article.published.clone(),
+        };
+
+        let existing = clusters.iter_mut().find(|(urls, cluster_words, _)| {
+            urls.contains(&url) || jaccard(cluster_words, &words) >= TITLE_SIMILARITY_THRESHOLD
+        });
+
+        match existing {
+            Some((urls, _, cluster)) => {
+                if urls.insert(url) {
+                    if sortable_date(&source.published) > sortable_date(&cluster.published) {
+                        cluster.published = source.published.clone();
+                    }
+                    cluster.sources.push(source);
+                    cluster.source_count = cluster
+                        .sources
+                        .iter()
+                        .map(|s| s.outlet.as_str())
+                        .collect::<HashSet<_>>()
+                        .len();
+                }
+            }
+            None => clusters.push((
+                HashSet::from([url]),
+                words,
+                NewsCluster {
+                    title: article.title,
+                    url: article.url,
+                    snippet: article.snippet,
+                    published: article.published,
+                    source_count: 1,
+                    sources: vec![source],
+                },
+            )),
+        }
+    }
+
+    let mut clusters: Vec<NewsCluster> = clusters.into_iter().map(|(_, _, c)| c).collect();
+    // undated stories sort last
+    clusters.sort_by(|a, b| sortable_date(&b.published).cmp(&sortable_date(&a.published)));
+    clusters
+}
+
+/// A publication date that compares correctly as a string. Engines separate
+/// the date and time with either `T` or a space, and ISO-8601 dates only sort
+/// as strings when they agree.
+fn sortable_date(published: &Option<String>) -> Option<String> {
+    published.as_deref().map(|d| d.replace(' ', "T"))
+}
+
+/// Normalizes a url so the same article linked in different ways compares
+/// equal. On top of [`without_tracking`], the scheme, a `www.` or `m.` host
+/// prefix, a trailing slash and an `/amp` suffix are ignored. The path and
+/// the remaining parameters keep their case.
+pub fn canonical_url(url: &str) -> String {
+    let Some(url) = without_tracking(url) else {
+        return url.trim().to_string();
+    };
+
+    let host = url.host_str().unwrap_or_default();
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    let host = host.strip_prefix("m.").unwrap_or(host);
+    let port = url.port().map(|port| format!(":{}", port)).unwrap_or_default();
+    let path = url.path().trim_end_matches('/');
+    let path = path.strip_suffix("/amp").unwrap_or(path);
+
+    match url.query() {
+        Some(query) => format!("{}{}{}?{}", host, port, path, query),
+        None => format!("{}{}{}", host, port, path),
+    }
+}
+
+fn host(url: &str) -> String {
+    let host = without_tracking(url)
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+    host.trim_start_matches("www.").to_string()
+}
+
+/// Lowercased headline words, ignoring a trailing "- Outlet" / "| Outlet" suffix.
+fn title_words(title: &str) -> HashSet<String> {
+    let title = title
+        .rsplit_once(" - ")
+        .or_else(|| title.rsplit_once(" | "))
+        .map(|(headline, _)| headline)
+        .unwrap_or(title);
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 2)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let shared = a.intersection(b).count();
+    shared as f64 / (a.len() + b.len() - shared) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::searxng::tests::mock_searxng;
+
+    fn article(title: &str, url: &str, published: Option<&str>) -> SearchResult {
+        SearchResult {
+            title: title.to_string(),
+            url: url.to_string(),
+            snippet: String::new(),
+            published: published.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_native_news_merges_repeated_headlines() {
+        let client = SearxngClient::new(mock_searxng(None).await, None).unwrap();
+        let request = NewsRequest {
+            query: "rate decision".to_string(),
+            max_results: Some(2),
+            time_range: Some(TimeRange::Week),
+            language: None,
+            no_cache: false,
+        };
+
+        // the mock repeats one headline across every url, so it is a single story
+        let output = native_agent(&client, &request).await.unwrap();
+        assert_eq!(output.clusters.len(), 1);
+        let story = &output.clusters[0];
+        assert_eq!(story.sources.len(), 8);
+        assert_eq!(story.source_count, 1);
+        assert_eq!(story.published.as_deref(), Some("2025-06-01T00:00:00"));
+        assert_eq!(output.sources.len(), 8);
+        assert_eq!(output.sources[7].snippet, story.snippet);
+    }
+
+    #[test]
+    fn test_canonical_url() {
+        assert_eq!(
+            canonical_url("https://www.example.com/story/123/?utm_source=x&id=7#comments"),
+            "example.com/story/123?id=7"
+        );
+        assert_eq!(
+            canonical_url("http://m.example.com/story/123/amp"),
+            "example.com/story/123"
+        );
+
+        // paths are case-sensitive and `ref` can pick a different page
+        assert_eq!(canonical_url("https://Example.com/Story/AbC"), "example.com/Story/AbC");
+        assert_ne!(canonical_url("https://example.com/Story/AbC"), canonical_url("https://example.com/story/abc"));
+        assert_ne!(canonical_url("https://example.com/a?ref=main"), canonical_url("https://example.com/a?ref=dev"));
+    }
+
+    #[test]
+    fn test_cluster_dates_compare_with_either_separator() {
+        let clusters = cluster(vec![
+            article("Central bank raises interest rates", "https://a.example/1", Some("2025-06-01T12:00:00")),
+            article("Central bank raises interest rates", "https://b.example/2", Some("2025-06-01 13:00:00")),
+        ]);
+        assert_eq!(clusters[0].published.as_deref(), Some("2025-06-01 13:00:00"));
+    }
+
+    #[test]
+    fn test_clusters_same_story_from_multiple_outlets() {
+        let clusters = cluster(vec![
+            article(
+                "Central bank raises interest rates again - Reuters",
+                "https://reuters.com/a",
+                Some("2025-06-01T09:00:00"),
+            ),
+            article("Local team wins final", "https://sports.example/b", Some("2025-06-02T10:00:00")),
+            article(
+                "Central bank raises interest rates again | BBC News",
+                "https://bbc.co.uk/c",
+                Some("2025-06-01T12:00:00"),
+            ),
+            article(
+                "Central bank raises interest rates again - Reuters",
+                "https://www.reuters.com/a/?utm_source=feed",
+                None,
+            ),
+            article("Unrelated undated item", "https://example.com/d", None),
+        ]);
+
+        assert_eq!(clusters.len(), 3);
+        assert_eq!(clusters[0].title, "Local team wins final");
+
+        let rates = &clusters[1];
+        assert_eq!(rates.sources.len(), 2);
+        assert_eq!(rates.source_count, 2);
+        assert_eq!(rates.published.as_deref(), Some("2025-06-01T12:00:00"));
+
+        assert_eq!(clusters[2].published, None);
+    }
 }
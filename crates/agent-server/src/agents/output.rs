@@ -0,0 +1,359 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Characters of content kept in a source's snippet.
+const SNIPPET_CHARS: usize = 280;
+
+/// A single search hit extracted from agent output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+}
+
+/// A source an agent result is grounded in. Citation markers such as `[2]`
+/// in the result text refer to the source at that 1-based position.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Source {
+    pub url: String,
+    pub title: String,
+    /// Unix time, in seconds, at which the source was retrieved.
+    pub retrieved_at: u64,
+    pub snippet: String,
+}
+
+impl Source {
+    /// A source retrieved now, with `snippet` collapsed onto one line and shortened.
+    pub fn new(url: impl Into<String>, title: impl Into<String>, snippet: &str) -> Self {
+        let snippet = snippet.split_whitespace().collect::<Vec<_>>().join(" ");
+        Self {
+            url: url.into(),
+            title: title.into(),
+            retrieved_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            snippet: truncate_chars(&snippet, SNIPPET_CHARS),
+        }
+    }
+}
+
+/// Whether a result came from the result cache, and how long it stays fresh.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CacheInfo {
+    pub hit: bool,
+    /// Unix time, in seconds, at which the result was cached.
+    pub cached_at: u64,
+    pub expires_at: u64,
+}
+
+/// Structured payload returned alongside the text of search-like tools.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchOutput {
+    pub query: String,
+    pub results: Vec<SearchResult>,
+    pub sources: Vec<Source>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheInfo>,
+}
+
+/// One outlet's article about a news story.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewsSource {
+    pub outlet: String,
+    pub title: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+}
+
+/// A news story with every article found covering it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewsCluster {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+    /// Most recent publication date across the cluster's sources.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    /// Number of distinct outlets covering the story.
+    pub source_count: usize,
+    pub sources: Vec<NewsSource>,
+}
+
+/// Structured payload returned alongside the text of the news tool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewsOutput {
+    pub query: String,
+    pub clusters: Vec<NewsCluster>,
+    /// Every article, in the order the rendered table cites them.
+    pub sources: Vec<Source>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheInfo>,
+}
+
+/// Structured payload returned alongside the text of the scrape tool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrapeOutput {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub format: String,
+    pub content: String,
+    /// The scraped page itself.
+    pub sources: Vec<Source>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheInfo>,
+}
+
+/// One entry of a crawl's page index.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CrawledPage {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Number of links followed from the start page to reach this one.
+    pub depth: u32,
+    /// Length of the extracted Markdown, in characters.
+    pub chars: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Structured payload returned alongside the text of the crawl tool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrawlOutput {
+    pub url: String,
+    pub pages: Vec<CrawledPage>,
+    pub markdown: String,
+    /// Every page fetched without error, numbered as cited in the Markdown.
+    pub sources: Vec<Source>,
+}
+
+/// The answer found for one research sub-question.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResearchFinding {
+    pub question: String,
+    pub summary: String,
+    /// 1-based positions in the report's sources.
+    pub sources: Vec<usize>,
+}
+
+/// Structured payload returned alongside the text of the deep research tool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResearchOutput {
+    pub topic: String,
+    pub report: String,
+    pub findings: Vec<ResearchFinding>,
+    /// Every page read, numbered as the report cites them, e.g. `[1]` is the first.
+    pub sources: Vec<Source>,
+}
+
+/// Renders search results as the markdown table the search scripts produce,
+/// citing each result by its position.
+pub fn render_results_table(results: &[SearchResult]) -> String {
+    let mut table = String::from("| Title | Description | Link |\n|-------|-------------|------|\n");
+    for (i, result) in results.iter().enumerate() {
+        table.push_str(&format!(
+            "| {} | {} [{}] | [Link]({}) |\n",
+            escape_cell(&result.title),
+            escape_cell(&result.snippet),
+            i + 1,
+            result.url
+        ));
+    }
+    table
+}
+
+/// Renders news stories as the markdown table the news script produces,
+/// with an extra column counting the outlets covering each story. Summaries
+/// cite every article of their story, numbered across the whole table.
+pub fn render_news_table(clusters: &[NewsCluster]) -> String {
+    let mut table =
+        String::from("| Date | Title | Summary | Sources | Link |\n|------|-------|---------|---------|------|\n");
+    let mut cited = 0;
+    for cluster in clusters {
+        let markers: String = (cited + 1..=cited + cluster.sources.len())
+            .map(|n| format!("[{}]", n))
+            .collect();
+        cited += cluster.sources.len();
+        table.push_str(&format!(
+            "| {} | {} | {} | {} | [Link]({}) |\n",
+            cluster.published.as_deref().unwrap_or_default(),
+            escape_cell(&cluster.title),
+            format!("{} {}", escape_cell(&cluster.snippet), markers).trim_end(),
+            cluster.source_count,
+            cluster.url
+        ));
+    }
+    table
+}
+
+fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Shortens `text` to `max_chars` characters, marking the cut with an ellipsis.
+pub fn truncate_chars(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Removes citation markers in `text` that do not name one of `source_count`
+/// sources, so every remaining `[n]` resolves. A marker listing several
+/// sources, e.g. `[1, 7]`, keeps only the valid ones.
+pub fn ground_citations(text: &str, source_count: usize) -> String {
+    let mut grounded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        grounded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some((numbers, consumed)) = parse_marker(rest) else {
+            grounded.push('[');
+            rest = &rest[1..];
+            continue;
+        };
+        let valid: Vec<String> = numbers
+            .iter()
+            .filter(|&&n| (1..=source_count).contains(&n))
+            .map(usize::to_string)
+            .collect();
+        if valid.len() < numbers.len() {
+            tracing::warn!("Dropping citation marker {} that names no source", &rest[..consumed]);
+        }
+        if !valid.is_empty() {
+            grounded.push_str(&format!("[{}]", valid.join(", ")));
+        }
+        rest = &rest[consumed..];
+    }
+    grounded.push_str(rest);
+    grounded
+}
+
+/// Lists the distinct sources cited by markers in `text`, in order of first citation.
+pub fn cited_sources(text: &str) -> Vec<usize> {
+    let mut cited: Vec<usize> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start..];
+        match parse_marker(rest) {
+            Some((numbers, consumed)) => {
+                for n in numbers {
+                    if !cited.contains(&n) {
+                        cited.push(n);
+                    }
+                }
+                rest = &rest[consumed..];
+            }
+            None => rest = &rest[1..],
+        }
+    }
+    cited
+}
+
+/// Parses a citation marker such as `[3]` or `[1, 2]` at the start of `text`,
+/// returning the source numbers and the number of bytes consumed. Markdown
+/// links like `[1](url)` are not markers.
+fn parse_marker(text: &str) -> Option<(Vec<usize>, usize)> {
+    let close = text.find(']')?;
+    let inner = &text[1..close];
+    if inner.trim().is_empty() || !inner.chars().all(|c| c.is_ascii_digit() || c == ',' || c == ' ') {
+        return None;
+    }
+    if text[close + 1..].starts_with('(') {
+        return None;
+    }
+    let numbers = inner
+        .split(',')
+        .map(|n| n.trim().parse().ok())
+        .collect::<Option<Vec<usize>>>()?;
+    Some((numbers, close + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_results_table_escapes_cells() {
+        let table = render_results_table(&[SearchResult {
+            title: "A | B".to_string(),
+            url: "https://example.com".to_string(),
+            snippet: "line one\nline two".to_string(),
+            published: None,
+        }]);
+        assert!(table.ends_with("| A \\| B | line one line two [1] | [Link](https://example.com) |\n"));
+        assert_eq!(cited_sources(&table), [1]);
+    }
+
+    #[test]
+    fn test_render_news_table_includes_source_counts() {
+        let table = render_news_table(&[NewsCluster {
+            title: "Headline".to_string(),
+            url: "https://example.com/a".to_string(),
+            snippet: "Short summary".to_string(),
+            published: Some("2025-06-01".to_string()),
+            source_count: 3,
+            sources: Vec::new(),
+        }]);
+        assert!(table.starts_with("| Date | Title | Summary | Sources | Link |"));
+        assert!(table.contains("| 2025-06-01 | Headline | Short summary | 3 | [Link](https://example.com/a) |"));
+    }
+
+    #[test]
+    fn test_render_news_table_numbers_articles_across_stories() {
+        let article = |url: &str| NewsSource {
+            outlet: "example.com".to_string(),
+            title: "Headline".to_string(),
+            url: url.to_string(),
+            published: None,
+        };
+        let story = |sources: Vec<NewsSource>| NewsCluster {
+            title: "Headline".to_string(),
+            url: sources[0].url.clone(),
+            snippet: "Summary".to_string(),
+            published: None,
+            source_count: sources.len(),
+            sources,
+        };
+        let table = render_news_table(&[
+            story(vec![article("https://a.example/1"), article("https://b.example/1")]),
+            story(vec![article("https://a.example/2")]),
+        ]);
+        assert!(table.contains("| Summary [1][2] |"));
+        assert!(table.contains("| Summary [3] |"));
+    }
+
+    #[test]
+    fn test_ground_citations_drops_markers_without_a_source() {
+        let text = "Rust is fast [1][4]. It is safe [2, 9] and [5, 6] popular. See [docs](https://a.example), [x] and [1](https://b.example).";
+        let grounded = ground_citations(text, 2);
+        assert_eq!(
+            grounded,
+            "Rust is fast [1]. It is safe [2] and  popular. See [docs](https://a.example), [x] and [1](https://b.example)."
+        );
+        assert_eq!(cited_sources(&grounded), [1, 2]);
+        assert_eq!(cited_sources(text), [1, 4, 2, 9, 5, 6]);
+        assert_eq!(ground_citations("no markers [", 3), "no markers [");
+    }
+
+    #[test]
+    fn test_truncate_chars() {
+        assert_eq!(truncate_chars("héllo", 10), "héllo");
+        assert_eq!(truncate_chars("héllo", 2), "hé…");
+    }
+
+    #[test]
+    fn test_source_snippets_are_one_short_line() {
+        let source = Source::new("https://a.example", "A", &format!("first\n\n  second {}", "x".repeat(400)));
+        assert!(source.snippet.starts_with("first second xxx"));
+        assert_eq!(source.snippet.chars().count(), SNIPPET_CHARS + 1);
+        assert!(source.retrieved_at > 0);
+    }
+}
@@ -1,6 +1,213 @@
-use crate::utils::utils::run_agent;
-use tokio::process::Child;
+use crate::agents::cache::normalize_url;
+use crate::agents::output::{ScrapeOutput, Source};
+use crate::scraping::document::{self, ContentKind, DocumentLimits};
+use crate::scraping::{self, Fetched, Fetcher, ScrapeError};
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use url::Url;
 
-pub async fn agent(stream_id: &str, input: &str) -> Result<Child, String> {
-    run_agent(stream_id, input, "./packages/genaiscript/genaisrc/web-scrape.genai.mts", 10).await
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrapeFormat {
+    #[default]
+    Markdown,
+    Text,
+    Html,
+}
+
+impl ScrapeFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScrapeFormat::Markdown => "markdown",
+            ScrapeFormat::Text => "text",
+            ScrapeFormat::Html => "html",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ScrapeRequest {
+    #[schemars(description = "The URL to scrape")]
+    pub url: String,
+    #[schemars(description = "What to look for on the page")]
+    pub query: Option<String>,
+    #[schemars(description = "Output format: markdown (default), text or html")]
+    #[serde(default)]
+    pub format: ScrapeFormat,
+    #[schemars(description = "Skip a cached copy of the page and fetch it again")]
+    #[serde(default)]
+    pub no_cache: bool,
+}
+
+impl ScrapeRequest {
+    /// Key under which the content extracted by this scrape is cached.
+    pub fn cache_key(&self) -> String {
+        format!("{} {}", self.format.as_str(), normalize_url(&self.url))
+    }
+}
+
+/// Content extracted from a fetched response.
+#[derive(Debug, Clone)]
+pub struct Extracted {
+    pub title: Option<String>,
+    pub content: String,
+    /// Links found anywhere in the document, for agents that follow them.
+    pub links: Vec<Url>,
+}
+
+/// Extracts the readable content of `fetched` in the requested format.
+/// PDFs and office documents are recognised by their content, whatever
+/// Content-Type they were served with.
+pub fn extract(fetched: &Fetched, format: ScrapeFormat, limits: &DocumentLimits) -> Result<Extracted, ScrapeError> {
+    let document = match document::sniff(fetched) {
+        Some(ContentKind::Html) => {
+            let page = scraping::extract(&fetched.text(), &fetched.url);
+            let content = match format {
+                ScrapeFormat::Markdown => page.markdown,
+                ScrapeFormat::Text => page.text,
+                ScrapeFormat::Html => page.html,
+            };
+            return Ok(Extracted {
+                title: page.title,
+                content,
+                links: page.links,
+            });
+        }
+        Some(ContentKind::Text) => {
+            return Ok(Extracted {
+                title: None,
+                content: fetched.text(),
+                links: Vec::new(),
+            })
+        }
+        Some(ContentKind::Pdf) => document::read_pdf(&fetched.body, limits)?,
+        Some(ContentKind::Docx) => document::read_docx(&fetched.body, limits)?,
+        Some(ContentKind::Pptx) => document::read_pptx(&fetched.body, limits)?,
+        Some(ContentKind::OpenDocument) => document::read_open_document(&fetched.body, limits)?,
+        None => {
+            return Err(ScrapeError::UnsupportedContent(
+                fetched
+                    .mime_type()
+                    .unwrap_or_else(|| "application/octet-stream".to_string()),
+            ))
+        }
+    };
+
+    let content = match format {
+        ScrapeFormat::Markdown => document.to_markdown(),
+        ScrapeFormat::Text => document.to_text(),
+        ScrapeFormat::Html => document.to_html(),
+    };
+    Ok(Extracted {
+        title: document.title,
+        content,
+        links: Vec::new(),
+    })
+}
+
+/// Fetches the page and extracts its main content without the genaiscript runtime.
+pub async fn native_agent(fetcher: &Fetcher, request: &ScrapeRequest) -> Result<ScrapeOutput, ScrapeError> {
+    let fetched = fetcher.fetch(&request.url).await?;
+    let extracted = extract(&fetched, request.format, &fetcher.options().document_limits)?;
+    tracing::debug!("Native scrape of {} extracted {} bytes", fetched.url, extracted.content.len());
+
+    let url = fetched.url.to_string();
+    let source = Source::new(
+        url.clone(),
+        extracted.title.clone().unwrap_or_else(|| url.clone()),
+        &extracted.content,
+    );
+    Ok(ScrapeOutput {
+        url,
+        title: extracted.title,
+        format: request.format.as_str().to_string(),
+        content: extracted.content,
+        sources: vec![source],
+        cache: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraping::fetch::tests::local_options;
+    use axum::http::header;
+    use axum::routing::get;
+    use axum::Router;
+
+    const ARTICLE: &str = include_str!("../../fixtures/html/article.html");
+    const REPORT: &[u8] = include_bytes!("../../fixtures/documents/report.pdf");
+
+    async fn serve_fixtures() -> String {
+        let app = Router::new()
+            .route("/article", get(|| async { ([(header::CONTENT_TYPE, "text/html")], ARTICLE) }))
+            .route("/download", get(|| async { ([(header::CONTENT_TYPE, "application/octet-stream")], REPORT) }))
+            .route("/notes.txt", get(|| async { ([(header::CONTENT_TYPE, "text/plain")], "plain notes") }))
+            .route("/logo.png", get(|| async { ([(header::CONTENT_TYPE, "image/png")], vec![0x89u8, b'P', b'N', b'G']) }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    fn request(url: String, format: ScrapeFormat) -> ScrapeRequest {
+        ScrapeRequest {
+            url,
+            query: None,
+            format,
+            no_cache: false,
+        }
+    }
+
+    #[test]
+    fn test_cache_key_normalizes_the_url() {
+        assert_eq!(
+            request("https://example.com/a/?utm_source=feed#top".to_string(), ScrapeFormat::Text).cache_key(),
+            request("https://example.com/a".to_string(), ScrapeFormat::Text).cache_key()
+        );
+        assert_ne!(
+            request("https://example.com/a".to_string(), ScrapeFormat::Text).cache_key(),
+            request("https://example.com/a".to_string(), ScrapeFormat::Html).cache_key()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_native_scrape_formats() {
+        let base_url = serve_fixtures().await;
+        let fetcher = Fetcher::new(local_options());
+
+        let output = native_agent(&fetcher, &request(format!("{}/article", base_url), ScrapeFormat::Markdown))
+            .await
+            .unwrap();
+        assert_eq!(output.title.as_deref(), Some("Rust 1.80 Released"));
+        assert!(output.content.starts_with("# Rust 1.80 Released"));
+        assert_eq!(output.sources[0].url, output.url);
+        assert_eq!(output.sources[0].title, "Rust 1.80 Released");
+        assert!(output.sources[0].snippet.starts_with("# Rust 1.80 Released"));
+        assert!(output.content.contains(&format!("({}/docs/lazylock)", base_url)));
+
+        let output = native_agent(&fetcher, &request(format!("{}/article", base_url), ScrapeFormat::Text))
+            .await
+            .unwrap();
+        assert!(output.content.starts_with("Rust 1.80 Released\n\n"));
+
+        let output = native_agent(&fetcher, &request(format!("{}/notes.txt", base_url), ScrapeFormat::Markdown))
+            .await
+            .unwrap();
+        assert_eq!(output.content, "plain notes");
+
+        let output = native_agent(&fetcher, &request(format!("{}/download", base_url), ScrapeFormat::Text))
+            .await
+            .unwrap();
+        assert_eq!(output.title.as_deref(), Some("Quarterly Report Q3"));
+        assert!(output.content.starts_with("[Page 1]\n\nQuarterly Report\n"));
+        assert!(output.content.contains("[Page 2]"));
+
+        assert!(matches!(
+            native_agent(&fetcher, &request(format!("{}/logo.png", base_url), ScrapeFormat::Markdown)).await,
+            Err(ScrapeError::UnsupportedContent(mime)) if mime == "image/png"
+        ));
+    }
 }
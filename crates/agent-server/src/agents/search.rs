@@ -1,28 +1,194 @@
-use tokio::process::Child;
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
 use tracing;
-use crate::utils::utils::run_agent;
+use crate::agents::cache::{normalize_query, without_tracking};
+use crate::agents::output::{SearchOutput, SearchResult, Source};
+use crate::agents::searxng::{SearxngClient, SearxngError, SearxngQuery, SearxngResult};
 
-pub async fn agent(stream_id: &str, input: &str) -> Result<Child, String> {
-    run_agent(stream_id, input, "./packages/genaiscript/genaisrc/web-search.genai.mts", 10).await
+const DEFAULT_MAX_RESULTS: usize = 5;
+/// Upper bound on SearxNG pages fetched for a single search.
+const MAX_PAGES: u32 = 3;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeRange {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct SearchRequest {
+    #[schemars(description = "The search query")]
+    pub query: String,
+    #[schemars(description = "Maximum number of results to return (default 5)")]
+    pub max_results: Option<u32>,
+    #[schemars(description = "Only return results published within this time range")]
+    pub time_range: Option<TimeRange>,
+    #[schemars(description = "Preferred result language, e.g. \"en\" or \"de\"")]
+    pub language: Option<String>,
+    #[schemars(description = "Restrict results to a single site, e.g. \"docs.rs\"")]
+    pub site: Option<String>,
+    #[schemars(description = "Skip cached results and search again")]
+    #[serde(default)]
+    pub no_cache: bool,
+}
+
+impl SearchRequest {
+    /// Key under which the results of this search are cached.
+    pub fn cache_key(&self) -> String {
+        serde_json::json!([normalize_query(&self.query), self.max_results, self.time_range, self.language, self.site])
+            .to_string()
+    }
+}
+
+/// Searches SearxNG directly, without the genaiscript runtime.
+pub async fn native_agent(
+    client: &SearxngClient,
+    request: &SearchRequest,
+) -> Result<SearchOutput, SearxngError> {
+    let query_text = match &request.site {
+        Some(site) => format!("site:{} {}", site, request.query),
+        None => request.query.clone(),
+    };
+    let mut query = SearxngQuery::new(query_text);
+    query.categories = vec!["general".to_string()];
+    query.time_range = request.time_range;
+    query.language = request.language.clone();
+
+    let max_results = request
+        .max_results
+        .map(|n| n as usize)
+        .unwrap_or(DEFAULT_MAX_RESULTS);
+    let results = collect_results(client, query, max_results).await?;
+    tracing::debug!("Native search for {:?} returned {} results", request.query, results.len());
+
+    Ok(SearchOutput {
+        query: request.query.clone(),
+        sources: results
+            .iter()
+            .map(|r| Source::new(r.url.clone(), r.title.clone(), &r.snippet))
+            .collect(),
+        results,
+        cache: None,
+    })
+}
+
+/// Pages through SearxNG until `max_results` distinct results are collected.
+pub async fn collect_results(
+    client: &SearxngClient,
+    mut query: SearxngQuery,
+    max_results: usize,
+) -> Result<Vec<SearchResult>, SearxngError> {
+    let mut results: Vec<SearchResult> = Vec::new();
+    while results.len() < max_results && query.page <= MAX_PAGES {
+        let response = client.search(&query).await?;
+        if response.results.is_empty() {
+            break;
+        }
+        for result in response.results.into_iter().filter_map(normalize) {
+            let key = url_key(&result.url);
+            if !results.iter().any(|r| url_key(&r.url) == key) {
+                results.push(result);
+            }
+        }
+        query.page += 1;
+    }
+    results.truncate(max_results);
+    Ok(results)
+}
+
+/// Converts a raw SearxNG hit into a [`SearchResult`], dropping hits without a usable url.
+pub fn normalize(result: SearxngResult) -> Option<SearchResult> {
+    let url = result.url.trim().to_string();
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return None;
+    }
+    let title = collapse_whitespace(&result.title);
+    Some(SearchResult {
+        title: if title.is_empty() { url.clone() } else { title },
+        snippet: collapse_whitespace(result.content.as_deref().unwrap_or_default()),
+        published: result.published_date.filter(|d| !d.is_empty()),
+        url,
+    })
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Key used to de-duplicate results that differ only by fragment, tracking
+/// parameters, trailing slash or the case of the scheme and host. The path and
+/// query keep their case.
+fn url_key(url: &str) -> String {
+    let key = match without_tracking(url) {
+        Some(parsed) => parsed.to_string(),
+        None => url.split('#').next().unwrap_or(url).to_string(),
+    };
+    key.trim_end_matches('/').to_string()
 }
 
 
 #[cfg(test)]
 mod tests {
-    use std::fmt::Debug;
-    use crate::agents::search::agent;
+    use crate::agents::search::{native_agent, normalize, url_key, SearchRequest};
+    use crate::agents::searxng::tests::mock_searxng;
+    use crate::agents::searxng::{SearxngClient, SearxngResult};
 
     #[tokio::test]
-    #[ignore]
-    async fn test_search_execution() {
-        let input = "Who won the 2024 presidential election?";
+    async fn test_native_search_paginates_and_scopes_to_site() {
+        let client = SearxngClient::new(mock_searxng(None).await, None).unwrap();
+        let request = SearchRequest {
+            query: "async runtimes".to_string(),
+            max_results: Some(5),
+            time_range: None,
+            language: None,
+            site: Some("docs.rs".to_string()),
+            no_cache: false,
+        };
 
-        let mut command = agent("test-stream", input).await.unwrap();
+        let output = native_agent(&client, &request).await.unwrap();
+        assert_eq!(output.query, "async runtimes");
+        assert_eq!(output.results.len(), 5);
+        assert_eq!(output.sources.len(), 5);
+        assert_eq!(output.sources[4].url, output.results[4].url);
+        assert_eq!(output.results[4].url, "https://example.com/4");
+        assert!(output.results[0].title.contains("site:docs.rs async runtimes"));
+    }
+
+    #[test]
+    fn test_cache_key_ignores_case_and_spacing() {
+        let request = |query: &str, site: Option<&str>| -> SearchRequest {
+            serde_json::from_value(serde_json::json!({ "query": query, "site": site })).unwrap()
+        };
+        assert_eq!(request("Async  Runtimes", None).cache_key(), request(" async runtimes", None).cache_key());
+        assert_ne!(request("async runtimes", None).cache_key(), request("async runtimes", Some("docs.rs")).cache_key());
+    }
+
+    #[test]
+    fn test_normalize_result() {
+        let result: SearxngResult = serde_json::from_value(serde_json::json!({
+            "url": " https://example.com/a ",
+            "title": "  A\n title ",
+            "content": "some\t snippet",
+        }))
+        .unwrap();
+        let normalized = normalize(result).unwrap();
+        assert_eq!(normalized.url, "https://example.com/a");
+        assert_eq!(normalized.title, "A title");
+        assert_eq!(normalized.snippet, "some snippet");
+
+        let result: SearxngResult =
+            serde_json::from_value(serde_json::json!({"url": "javascript:alert(1)"})).unwrap();
+        assert!(normalize(result).is_none());
+    }
 
-        // command.stdout.take().unwrap().read_to_string(&mut String::new()).await.unwrap();
-        // Optionally, you can capture and inspect stdout if needed:
-        let output = command.wait_with_output().await.expect("Failed to wait for output");
-        println!("Stdout: {}", String::from_utf8_lossy(&output.stdout));
-        println!("Stderr: {}", String::from_utf8_lossy(&output.stderr));
+    #[test]
+    fn test_url_key_only_folds_scheme_and_host_case() {
+        assert_eq!(url_key("HTTPS://Example.COM/Docs/#intro"), url_key("https://example.com/Docs"));
+        assert_ne!(url_key("https://example.com/Docs"), url_key("https://example.com/docs"));
+        assert_ne!(url_key("https://example.com/a?id=ABC"), url_key("https://example.com/a?id=abc"));
+        assert_eq!(url_key("https://example.com/a?id=1&utm_source=x"), url_key("https://example.com/a?id=1"));
     }
 }
@@ -0,0 +1,246 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::agents::search::TimeRange;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug)]
+pub enum SearxngError {
+    NotConfigured,
+    Http(reqwest::Error),
+    Status(reqwest::StatusCode, String),
+}
+
+impl std::fmt::Display for SearxngError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearxngError::NotConfigured => write!(f, "SEARXNG_API_BASE_URL is not set"),
+            SearxngError::Http(e) => write!(f, "SearxNG request failed: {}", e),
+            SearxngError::Status(status, body) => {
+                write!(f, "SearxNG responded with {}: {}", status, body)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SearxngError {}
+
+impl From<reqwest::Error> for SearxngError {
+    fn from(e: reqwest::Error) -> Self {
+        SearxngError::Http(e)
+    }
+}
+
+/// Parameters of a single SearxNG `/search` request.
+#[derive(Debug, Clone, Default)]
+pub struct SearxngQuery {
+    pub query: String,
+    pub categories: Vec<String>,
+    pub engines: Vec<String>,
+    /// 1-based result page.
+    pub page: u32,
+    pub time_range: Option<TimeRange>,
+    pub language: Option<String>,
+}
+
+impl SearxngQuery {
+    pub fn new(query: impl Into<String>) -> Self {
+        Self {
+            query: query.into(),
+            page: 1,
+            ..Default::default()
+        }
+    }
+
+    fn to_params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![
+            ("q", self.query.clone()),
+            ("format", "json".to_string()),
+            ("pageno", self.page.max(1).to_string()),
+        ];
+        if !self.categories.is_empty() {
+            params.push(("categories", self.categories.join(",")));
+        }
+        if !self.engines.is_empty() {
+            params.push(("engines", self.engines.join(",")));
+        }
+        if let Some(time_range) = self.time_range {
+            let time_range = match time_range {
+                TimeRange::Day => "day",
+                TimeRange::Week => "week",
+                TimeRange::Month => "month",
+                TimeRange::Year => "year",
+            };
+            params.push(("time_range", time_range.to_string()));
+        }
+        if let Some(language) = &self.language {
+            params.push(("language", language.clone()));
+        }
+        params
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearxngResult {
+    pub url: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub engine: Option<String>,
+    #[serde(default)]
+    pub engines: Vec<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default, rename = "publishedDate")]
+    pub published_date: Option<String>,
+    #[serde(default)]
+    pub score: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearxngResponse {
+    #[serde(default)]
+    pub query: String,
+    #[serde(default)]
+    pub results: Vec<SearxngResult>,
+    #[serde(default)]
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearxngClient {
+    http: reqwest::Client,
+    base_url: String,
+    password: Option<String>,
+}
+
+impl SearxngClient {
+    pub fn new(base_url: impl Into<String>, password: Option<String>) -> Result<Self, SearxngError> {
+        let http = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+        Ok(Self {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            password: password.filter(|p| !p.is_empty()),
+        })
+    }
+
+    /// Builds a client from `SEARXNG_API_BASE_URL` and `SEARXNG_PASSWORD`.
+    pub fn from_env() -> Result<Self, SearxngError> {
+        let base_url = std::env::var("SEARXNG_API_BASE_URL").unwrap_or_default();
+        if base_url.is_empty() {
+            return Err(SearxngError::NotConfigured);
+        }
+        Self::new(base_url, std::env::var("SEARXNG_PASSWORD").ok())
+    }
+
+    pub async fn search(&self, query: &SearxngQuery) -> Result<SearxngResponse, SearxngError> {
+        let mut request = self
+            .http
+            .get(format!("{}/search", self.base_url))
+            .query(&query.to_params());
+        // the bundled searxng image sits behind basic auth with the `admin` user
+        if let Some(password) = &self.password {
+            request = request.basic_auth("admin", Some(password));
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(SearxngError::Status(status, body));
+        }
+        Ok(response.json().await?)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use axum::extract::Query;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use std::collections::HashMap;
+
+    /// Serves canned SearxNG responses, echoing the query parameters back as results.
+    pub(crate) async fn mock_searxng(password: Option<&'static str>) -> String {
+        let app = Router::new().route(
+            "/search",
+            get(move |headers: HeaderMap, Query(params): Query<HashMap<String, String>>| async move {
+                if let Some(password) = password {
+                    let expected = format!(
+                        "Basic {}",
+                        crate::utils::base64::B64_ENCODER.b64_encode_payload(format!("admin:{}", password))
+                    );
+                    if headers.get("authorization").and_then(|h| h.to_str().ok()) != Some(expected.as_str()) {
+                        return Err(StatusCode::UNAUTHORIZED);
+                    }
+                }
+                let page: u32 = params.get("pageno").and_then(|p| p.parse().ok()).unwrap_or(1);
+                let query = params.get("q").cloned().unwrap_or_default();
+                let results: Vec<serde_json::Value> = (0..3)
+                    .map(|i| {
+                        let n = (page - 1) * 3 + i;
+                        serde_json::json!({
+                            "url": format!("https://example.com/{}", n),
+                            "title": format!("Result {} for {}", n, query),
+                            "content": format!("Snippet {}", n),
+                            "engine": "mock",
+                            "engines": ["mock"],
+                            "category": params.get("categories").cloned().unwrap_or_default(),
+                            "publishedDate": params.get("time_range").map(|_| "2025-06-01T00:00:00"),
+                        })
+                    })
+                    .collect();
+                Ok(Json(serde_json::json!({
+                    "query": query,
+                    "results": results,
+                    "suggestions": [],
+                    "echo": params,
+                })))
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_search_sends_params_and_auth() {
+        let base_url = mock_searxng(Some("secret")).await;
+        let client = SearxngClient::new(base_url, Some("secret".to_string())).unwrap();
+
+        let mut query = SearxngQuery::new("rust async");
+        query.categories = vec!["news".to_string()];
+        query.engines = vec!["bing".to_string(), "duckduckgo".to_string()];
+        query.page = 2;
+        query.time_range = Some(TimeRange::Week);
+        query.language = Some("en".to_string());
+
+        let response = client.search(&query).await.unwrap();
+        assert_eq!(response.query, "rust async");
+        assert_eq!(response.results.len(), 3);
+        assert_eq!(response.results[0].url, "https://example.com/3");
+        assert_eq!(response.results[0].category.as_deref(), Some("news"));
+        assert!(response.results[0].published_date.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_search_reports_auth_failures() {
+        let base_url = mock_searxng(Some("secret")).await;
+        let client = SearxngClient::new(base_url, Some("wrong".to_string())).unwrap();
+
+        match client.search(&SearxngQuery::new("rust")).await {
+            Err(SearxngError::Status(status, _)) => assert_eq!(status, StatusCode::UNAUTHORIZED),
+            other => panic!("expected an auth failure, got {:?}", other.map(|r| r.query)),
+        }
+    }
+}
@@ -14,9 +14,27 @@ impl Runtime {
         Self {
             env_vars: vec![
                 "OPENAI_API_KEY".to_string(),
+                "OPENAI_API_BASE".to_string(),
                 "GENAISCRIPT_MODEL_LARGE".to_string(),
                 "GENAISCRIPT_MODEL_SMALL".to_string(),
                 "SEARXNG_API_BASE_URL".to_string(),
+                "SEARXNG_PASSWORD".to_string(),
+                "MCP_MAX_SESSIONS".to_string(),
+                "MCP_SESSION_IDLE_TIMEOUT_SECS".to_string(),
+                "MCP_PERSIST_SESSIONS".to_string(),
+                "MCP_UPSTREAMS_CONFIG".to_string(),
+                "SCRAPE_TIMEOUT_SECS".to_string(),
+                "SCRAPE_MAX_REDIRECTS".to_string(),
+                "SCRAPE_MAX_BYTES".to_string(),
+                "SCRAPE_USER_AGENT".to_string(),
+                "SCRAPE_RESPECT_ROBOTS".to_string(),
+                "SCRAPE_MAX_CONCURRENCY_PER_HOST".to_string(),
+                "SCRAPE_ALLOWLIST".to_string(),
+                "SCRAPE_MAX_DOCUMENT_PAGES".to_string(),
+                "SCRAPE_MAX_UNPACKED_BYTES".to_string(),
+                "CACHE_TTL_SEARCH_SECS".to_string(),
+                "CACHE_TTL_NEWS_SECS".to_string(),
+                "CACHE_TTL_SCRAPE_SECS".to_string(),
             ],
         }
     }
@@ -1,28 +1,43 @@
 use axum::response::Response;
 use axum::{
-    body::Body, extract::Path, extract::Query, http::StatusCode, response::IntoResponse, Json,
+    body::Body, extract::Path, extract::Query, extract::State, http::StatusCode, response::IntoResponse, Json,
 };
 use bytes::Bytes;
 use futures::stream::{Stream, StreamExt};
 use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sled;
+use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::Mutex;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+use crate::agents::cache::{self, CacheKind};
+use crate::agents::crawl::{self, CrawlProgress, CrawlRequest};
+use crate::agents::deep_research::{self, Researcher, ResearchEvent, ResearchRequest};
+use crate::agents::news::{self, NewsRequest};
+use crate::agents::scrape::{self, ScrapeRequest};
+use crate::agents::search::{self, SearchRequest};
+use crate::agents::searxng::SearxngClient;
+use crate::scraping::Fetcher;
 
 // init sled
 lazy_static! {
-    static ref DB: Arc<Mutex<sled::Db>> = Arc::new(Mutex::new(
+    pub(crate) static ref DB: Arc<Mutex<sled::Db>> = Arc::new(Mutex::new(
         sled::open("./open-web-agent-rs/db/stream_store").expect("Failed to open sled database")
     ));
 }
 
-pub async fn use_agent(Path(agent_id): Path<String>) -> impl IntoResponse {
+/// Runs the agent registered under `agent_id`, streaming its output as
+/// server-sent events. Native agents scrape through `fetcher`.
+pub async fn use_agent(State(fetcher): State<Fetcher>, Path(agent_id): Path<String>) -> impl IntoResponse {
     let db = DB.lock().await;
     match db.get(&agent_id) {
         Ok(Some(data)) => {
@@ -83,6 +98,79 @@ pub async fn use_agent(Path(agent_id): Path<String>) -> impl IntoResponse {
             }
 
             let resource = info.resource;
+
+            // native agents stream typed events instead of a script's stdout
+            match resource.as_str() {
+                "web-search" | "news-search" => {
+                    let client = match SearxngClient::from_env() {
+                        Ok(client) => client,
+                        Err(e) => {
+                            tracing::error!("Search is not configured: {}", e);
+                            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                        }
+                    };
+                    let stream = match resource.as_str() {
+                        "web-search" => native_request::<SearchRequest>(&info.payload.input, "query").map(|request| {
+                            result_to_stream(async move {
+                                let search = search::native_agent(&client, &request);
+                                cache::cached(CacheKind::Search, &request.cache_key(), request.no_cache, search).await
+                            })
+                        }),
+                        _ => native_request::<NewsRequest>(&info.payload.input, "query").map(|request| {
+                            result_to_stream(async move {
+                                let news = news::native_agent(&client, &request);
+                                cache::cached(CacheKind::News, &request.cache_key(), request.no_cache, news).await
+                            })
+                        }),
+                    };
+                    return match stream {
+                        Ok(stream) => sse_response(stream),
+                        Err(e) => {
+                            tracing::error!("Invalid search request: {}", e);
+                            StatusCode::BAD_REQUEST.into_response()
+                        }
+                    };
+                }
+                "web-scrape" => {
+                    return match native_request::<ScrapeRequest>(&info.payload.input, "url") {
+                        Ok(request) => sse_response(result_to_stream(async move {
+                            let scrape = scrape::native_agent(&fetcher, &request);
+                            cache::cached(CacheKind::Scrape, &request.cache_key(), request.no_cache, scrape).await
+                        })),
+                        Err(e) => {
+                            tracing::error!("Invalid scrape request: {}", e);
+                            StatusCode::BAD_REQUEST.into_response()
+                        }
+                    };
+                }
+                "web-crawl" => {
+                    return match native_request::<CrawlRequest>(&info.payload.input, "url") {
+                        Ok(request) => sse_response(crawl_to_stream(fetcher, request)),
+                        Err(e) => {
+                            tracing::error!("Invalid crawl request: {}", e);
+                            StatusCode::BAD_REQUEST.into_response()
+                        }
+                    };
+                }
+                "deep-research" => {
+                    let request = match native_request::<ResearchRequest>(&info.payload.input, "topic") {
+                        Ok(request) => request,
+                        Err(e) => {
+                            tracing::error!("Invalid research request: {}", e);
+                            return StatusCode::BAD_REQUEST.into_response();
+                        }
+                    };
+                    return match Researcher::from_env() {
+                        Ok(researcher) => sse_response(research_to_stream(Researcher { fetcher, ..researcher }, request)),
+                        Err(e) => {
+                            tracing::error!("Deep research is not configured: {}", e);
+                            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                        }
+                    };
+                }
+                _ => {}
+            }
+
             let input = serde_json::to_string(&info.payload.input).unwrap_or_default();
 
             tracing::debug!(
@@ -92,15 +180,9 @@ pub async fn use_agent(Path(agent_id): Path<String>) -> impl IntoResponse {
             );
 
             let cmd = match resource.as_str() {
-                "web-search" => crate::agents::search::agent(agent_id.as_str(), &*input).await,
-                "news-search" => crate::agents::news::agent(agent_id.as_str(), &*input).await,
                 "image-generator" => {
                     crate::agents::image_generator::agent(agent_id.as_str(), &*input).await
                 }
-                "deep-research" => {
-                    crate::agents::deep_research::agent(agent_id.as_str(), &*input).await
-                }
-                "web-scrape" => crate::agents::scrape::agent(agent_id.as_str(), &*input).await,
                 _ => {
                     tracing::error!("Unsupported resource type: {}", resource);
                     return StatusCode::BAD_REQUEST.into_response();
@@ -123,16 +205,21 @@ pub async fn use_agent(Path(agent_id): Path<String>) -> impl IntoResponse {
                 }
             };
 
+            // stderr is piped; drain it so the agent never blocks on a full pipe
+            if let Some(stderr) = cmd.stderr.take() {
+                let stream_id = agent_id.clone();
+                tokio::spawn(async move {
+                    let mut lines = BufReader::new(stderr).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        tracing::debug!(target: "agent_stderr", stream_id = %stream_id, "{}", line);
+                    }
+                });
+            }
+
             let reader = BufReader::new(stdout);
             let sse_stream = reader_to_stream(reader, agent_id.clone());
 
-            return Response::builder()
-                .header("Content-Type", "text/event-stream")
-                .header("Cache-Control", "no-cache, no-transform")
-                .header("Connection", "keep-alive")
-                .header("X-Accel-Buffering", "yes")
-                .body(Body::from_stream(sse_stream))
-                .unwrap();
+            return sse_response(sse_stream);
         }
         Ok(None) => {
             tracing::error!("Stream ID not found: {}", agent_id);
@@ -145,6 +232,89 @@ pub async fn use_agent(Path(agent_id): Path<String>) -> impl IntoResponse {
     }
 }
 
+type SseStream = Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>;
+
+fn sse_response(stream: SseStream) -> Response {
+    Response::builder()
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache, no-transform")
+        .header("Connection", "keep-alive")
+        .header("X-Accel-Buffering", "yes")
+        .body(Body::from_stream(stream))
+        .unwrap()
+}
+
+fn sse_event<T: Serialize>(event: &str, data: &T) -> Bytes {
+    Bytes::from(format!(
+        "event: {}\ndata: {}\n\n",
+        event,
+        serde_json::to_string(data).unwrap_or_default()
+    ))
+}
+
+/// Accepts either a request object or a bare string for its `field`,
+/// e.g. a crawl's start url.
+fn native_request<T: DeserializeOwned>(input: &Value, field: &str) -> Result<T, serde_json::Error> {
+    match input {
+        Value::String(value) => serde_json::from_value(serde_json::json!({ field: value })),
+        input => serde_json::from_value(input.clone()),
+    }
+}
+
+/// Runs a native agent, emitting an event named by `event_name` for each
+/// progress update and a final `result` (or `error`) event with its output.
+fn progress_to_stream<P, O, E, F>(event_name: fn(&P) -> &'static str, run: impl FnOnce(UnboundedSender<P>) -> F) -> SseStream
+where
+    P: Serialize + Send + 'static,
+    O: Serialize + Send + 'static,
+    E: std::fmt::Display + Send + 'static,
+    F: Future<Output = Result<O, E>> + Send + 'static,
+{
+    let (progress, updates) = tokio::sync::mpsc::unbounded_channel();
+    let task = tokio::spawn(run(progress));
+
+    let updates = UnboundedReceiverStream::new(updates).map(move |update| Ok(sse_event(event_name(&update), &update)));
+    let result = futures::stream::once(async move {
+        let message = match task.await {
+            Ok(Ok(output)) => return Ok(sse_event("result", &output)),
+            Ok(Err(e)) => e.to_string(),
+            Err(e) => e.to_string(),
+        };
+        tracing::error!("Agent failed: {}", message);
+        Ok(sse_event("error", &serde_json::json!({ "message": message })))
+    });
+    let done = futures::stream::once(async { Ok(Bytes::from("data: [DONE]\n\n")) });
+
+    Box::pin(updates.chain(result).chain(done))
+}
+
+/// Runs a native agent that reports no progress, emitting only its `result`
+/// (or `error`) event.
+fn result_to_stream<O, E, F>(run: F) -> SseStream
+where
+    O: Serialize + Send + 'static,
+    E: std::fmt::Display + Send + 'static,
+    F: Future<Output = Result<O, E>> + Send + 'static,
+{
+    progress_to_stream(|_: &()| "progress", move |_| run)
+}
+
+/// Runs a crawl, emitting a `page` event per fetched page.
+fn crawl_to_stream(fetcher: Fetcher, request: CrawlRequest) -> SseStream {
+    progress_to_stream(
+        |_: &CrawlProgress| "page",
+        move |progress| async move { crawl::native_agent(&fetcher, &request, Some(&progress)).await },
+    )
+}
+
+/// Runs deep research, emitting a `plan`, `search`, `scrape`, `summarize`
+/// or `synthesize` event as each step completes.
+fn research_to_stream(researcher: Researcher, request: ResearchRequest) -> SseStream {
+    progress_to_stream(ResearchEvent::name, move |progress| async move {
+        deep_research::native_agent(&researcher, &request, Some(&progress)).await
+    })
+}
+
 fn reader_to_stream<R>(
     reader: BufReader<R>,
     stream_id: String,
@@ -260,3 +430,95 @@ pub async fn create_agent(Json(payload): Json<WebhookPostRequest>) -> impl IntoR
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraping::fetch::tests::local_options;
+    use axum::routing::get;
+    use axum::Router;
+
+    #[test]
+    fn test_native_request_accepts_a_bare_string() {
+        let request: CrawlRequest = native_request(&Value::from("https://docs.example.com/"), "url").unwrap();
+        assert_eq!(request.url, "https://docs.example.com/");
+        assert!(request.include.is_empty());
+
+        let request: CrawlRequest =
+            native_request(&serde_json::json!({ "url": "https://a.example", "max_pages": 3 }), "url").unwrap();
+        assert_eq!(request.max_pages, Some(3));
+        assert!(native_request::<CrawlRequest>(&serde_json::json!({ "max_pages": 3 }), "url").is_err());
+
+        let request: ResearchRequest = native_request(&Value::from("What is Rust?"), "topic").unwrap();
+        assert_eq!(request.topic, "What is Rust?");
+        assert_eq!(request.depth, None);
+    }
+
+    #[tokio::test]
+    async fn test_search_stream_emits_a_result_with_sources() {
+        let client = SearxngClient::new(crate::agents::searxng::tests::mock_searxng(None).await, None).unwrap();
+        let request: SearchRequest = native_request(&Value::from("async runtimes"), "query").unwrap();
+        let events: Vec<String> = result_to_stream(async move { search::native_agent(&client, &request).await })
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(events.len(), 2);
+        let data = events[0].strip_prefix("event: result\ndata: ").unwrap();
+        let output: Value = serde_json::from_str(data.trim_end()).unwrap();
+        assert_eq!(output["sources"].as_array().unwrap().len(), 5);
+        assert!(output["sources"][0]["retrieved_at"].as_u64().unwrap() > 0);
+        assert_eq!(events[1], "data: [DONE]\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_crawl_stream_emits_page_and_result_events() {
+        let app = Router::new()
+            .route("/", get(|| async { axum::response::Html(r#"<p><a href="/about">About</a></p>"#) }))
+            .route("/about", get(|| async { axum::response::Html("<p>About us</p>") }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let request = native_request(&Value::from(format!("http://{}/", addr)), "url").unwrap();
+        let fetcher = Fetcher::new(local_options());
+        let events: Vec<String> = crawl_to_stream(fetcher, request)
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect()
+            .await;
+
+        let names: Vec<&str> = events.iter().map(|e| e.lines().next().unwrap()).collect();
+        assert_eq!(names, ["event: page", "event: page", "event: result", "data: [DONE]"]);
+        assert!(events[1].contains(r#""index":2"#));
+        assert!(events[2].contains(r#""pages":["#));
+
+        let request = native_request(&Value::from("http://127.0.0.1:1/"), "url").unwrap();
+        let events: Vec<Bytes> = crawl_to_stream(Fetcher::new(local_options()), request)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert!(events[0].starts_with(b"event: error\n"));
+    }
+
+    #[tokio::test]
+    async fn test_research_stream_reports_failures_as_error_events() {
+        let researcher = Researcher {
+            llm: crate::agents::llm::ChatClient::new("http://127.0.0.1:1/v1", None),
+            searxng: crate::agents::searxng::SearxngClient::new("http://127.0.0.1:1", None).unwrap(),
+            fetcher: Fetcher::new(local_options()),
+            large_model: "large".to_string(),
+            small_model: "small".to_string(),
+        };
+        let request: ResearchRequest = native_request(&Value::from("What is Rust?"), "topic").unwrap();
+        let events: Vec<String> = research_to_stream(researcher, request)
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(events.len(), 2);
+        assert!(events[0].starts_with("event: error\ndata: {\"message\":\"Chat completion request failed"));
+        assert_eq!(events[1], "data: [DONE]\n\n");
+    }
+}
@@ -0,0 +1,28 @@
+use axum::{
+    extract::Path,
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+
+use crate::agents::image_generator::load_image;
+
+pub async fn get_generated_image(Path(image_id): Path<String>) -> impl IntoResponse {
+    match load_image(&image_id).await {
+        Ok(image) => (
+            [
+                (header::CONTENT_TYPE, image.mime_type),
+                (header::CACHE_CONTROL, "public, max-age=31536000, immutable".to_string()),
+            ],
+            image.data,
+        )
+            .into_response(),
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+            tracing::warn!("Rejected generated image request: {}", e);
+            StatusCode::BAD_REQUEST.into_response()
+        }
+        Err(e) => {
+            tracing::debug!("Generated image {} not available: {}", image_id, e);
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
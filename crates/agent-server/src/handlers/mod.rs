@@ -1,3 +1,6 @@
 pub mod not_found;
 pub mod ui;
-pub mod agents;
\ No newline at end of file
+pub mod agents;
+pub mod images;
+pub mod sessions;
+pub mod upstreams;
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use rmcp::transport::streamable_http_server::session::{SessionId, SessionManager};
+
+use crate::sessions::ManagedSessionManager;
+
+/// The token admin endpoints require, from `AGENT_SERVER_ADMIN_TOKEN`. Without
+/// one the admin endpoints are not mounted at all.
+pub fn admin_token_from_env() -> Option<Arc<str>> {
+    std::env::var("AGENT_SERVER_ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+        .map(Arc::from)
+}
+
+/// Middleware for the admin routes, requiring `Authorization: Bearer <token>`.
+pub async fn require_admin_token(State(token): State<Arc<str>>, request: Request, next: Next) -> Response {
+    if !authorized(request.headers(), &token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(request).await
+}
+
+fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(http::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()))
+}
+
+/// Compares without returning early on the first differing byte, so response
+/// times do not reveal how much of a guessed token was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+pub async fn list_sessions(State(manager): State<Arc<ManagedSessionManager>>) -> impl IntoResponse {
+    let sessions = manager.list().await;
+    let settings = manager.settings();
+    Json(serde_json::json!({
+        "sessions": sessions,
+        "max_sessions": settings.max_sessions,
+        "idle_timeout_secs": settings.idle_timeout.map(|d| d.as_secs()),
+        "persist": settings.persist,
+    }))
+    .into_response()
+}
+
+pub async fn close_session(
+    State(manager): State<Arc<ManagedSessionManager>>,
+    Path(session_id): Path<String>,
+) -> impl IntoResponse {
+    let id: SessionId = session_id.as_str().into();
+    match manager.has_session(&id).await {
+        Ok(true) => {}
+        Ok(false) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!("Failed to look up session {}: {}", session_id, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    match manager.close_session(&id).await {
+        Ok(_) => {
+            tracing::info!("Closed MCP session {} via admin endpoint", session_id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to close session {}: {}", session_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorized_requires_the_exact_token() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(http::header::AUTHORIZATION, value.parse().unwrap());
+            headers
+        };
+        assert!(authorized(&headers("Bearer s3cret"), "s3cret"));
+        assert!(!authorized(&headers("Bearer s3cre"), "s3cret"));
+        assert!(!authorized(&headers("Bearer s3creT"), "s3cret"));
+        assert!(!authorized(&headers("s3cret"), "s3cret"));
+        assert!(!authorized(&HeaderMap::new(), "s3cret"));
+    }
+}
@@ -0,0 +1,9 @@
+use axum::{response::IntoResponse, Json};
+
+use crate::upstream;
+
+pub async fn list_upstreams() -> impl IntoResponse {
+    Json(serde_json::json!({
+        "upstreams": upstream::registry().health().await,
+    }))
+}
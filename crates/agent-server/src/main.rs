@@ -9,6 +9,9 @@ mod handlers;
 mod agents;
 mod utils;
 mod counter;
+mod sessions;
+mod upstream;
+mod scraping;
 
 #[tokio::main]
 async fn main() {
@@ -16,6 +19,10 @@ async fn main() {
 
     Runtime::configure();
 
+    let upstreams = upstream::registry();
+    upstreams.spawn_connect_all(upstream::load_configs());
+    upstreams.spawn_health_checks(std::time::Duration::from_secs(30));
+
     let router = create_router();
 
     let addr = "0.0.0.0:3006";
@@ -1,15 +1,23 @@
 use axum::response::Response;
-use crate::handlers::{not_found::handle_not_found};
-use axum::routing::{get, Router};
+use crate::handlers::{
+    agents::{create_agent, use_agent},
+    images::get_generated_image,
+    not_found::handle_not_found,
+    sessions::{admin_token_from_env, close_session, list_sessions, require_admin_token},
+    upstreams::list_upstreams,
+};
+use axum::middleware;
+use axum::routing::{delete, get, post, Router};
 use http::StatusCode;
 use tower_http::trace::{self, TraceLayer};
 use tracing::Level;
 
-use rmcp::transport::streamable_http_server::{
-    StreamableHttpService, session::local::LocalSessionManager,
-};
+use rmcp::transport::streamable_http_server::StreamableHttpService;
 use rust_embed::Embed;
+use std::sync::Arc;
 use crate::agents::Agents;
+use crate::scraping::{self, Fetcher};
+use crate::sessions::{ManagedSessionManager, SessionSettings};
 
 
 #[derive(Embed)]
@@ -45,16 +53,43 @@ async fn static_handler(uri: http::Uri) -> impl axum::response::IntoResponse {
 }
 
 pub fn create_router() -> Router {
+    create_router_with_admin_token(admin_token_from_env())
+}
+
+pub fn create_router_with_admin_token(admin_token: Option<Arc<str>>) -> Router {
+    create_router_with_fetcher(admin_token, scraping::fetcher())
+}
+
+/// Builds the router, mounting the admin and agent endpoints only when an
+/// admin token is set. Agents run over `/agents` scrape through `fetcher`.
+pub fn create_router_with_fetcher(admin_token: Option<Arc<str>>, fetcher: Fetcher) -> Router {
+    let session_manager = Arc::new(ManagedSessionManager::new(SessionSettings::from_env()));
+    session_manager.spawn_reaper();
 
     let mcp_service = StreamableHttpService::new(
         Agents::new,
-        LocalSessionManager::default().into(),
+        session_manager.clone(),
         Default::default(),
     );
 
-    Router::new()
-        .nest_service("/mcp", mcp_service)
+    let mut router = Router::new().nest_service("/mcp", mcp_service);
+    if let Some(token) = admin_token {
+        let admin = Router::new()
+            .route("/admin/sessions", get(list_sessions))
+            .route("/admin/sessions/{session_id}", delete(close_session))
+            .with_state(session_manager)
+            .route("/admin/upstreams", get(list_upstreams))
+            // Agents spend the server's search, model and image API keys
+            .route("/agents", post(create_agent))
+            .route("/agents/{agent_id}", get(use_agent))
+            .with_state(fetcher)
+            .route_layer(middleware::from_fn_with_state(token, require_admin_token));
+        router = router.merge(admin);
+    }
+
+    router
         .route("/health", get(health))
+        .route("/generated/image/{image_id}", get(get_generated_image))
         .route("/", get(ui_index_handler))
         .route("/index.html", get(ui_index_handler))
         .route("/{*path}", get(static_handler))
@@ -109,6 +144,118 @@ mod tests {
         assert_eq!(&body[..], b"ok");
     }
 
+    #[tokio::test]
+    async fn test_generated_image_rejects_invalid_id() {
+        let app = create_router();
+
+        let request = Request::builder()
+            .uri("/generated/image/..%2F..%2Fetc%2Fpasswd")
+            .method("GET")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    fn admin_router() -> Router {
+        create_router_with_admin_token(Some(Arc::from("test-admin-token")))
+    }
+
+    fn admin_request(method: &str, uri: &str) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .method(method)
+            .header("Authorization", "Bearer test-admin-token")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_list_sessions_route() {
+        let request = Request::builder()
+            .uri("/admin/sessions")
+            .method("GET")
+            .body(Body::empty())
+            .unwrap();
+        let response = admin_router().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = admin_router().oneshot(admin_request("GET", "/admin/sessions")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response_body_bytes(response).await;
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["sessions"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_close_unknown_session() {
+        let request = admin_request("DELETE", "/admin/sessions/does-not-exist");
+        let response = admin_router().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes_need_a_configured_token() {
+        let app = create_router_with_admin_token(None);
+
+        let response = app.oneshot(admin_request("GET", "/admin/sessions")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_agent_route_streams_crawl_progress() {
+        let site = Router::new()
+            .route("/", get(|| async { axum::response::Html(r#"<p><a href="/about">About</a></p>"#) }))
+            .route("/about", get(|| async { axum::response::Html("<p>About us</p>") }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, site).await.unwrap();
+        });
+
+        let app = create_router_with_fetcher(
+            Some(Arc::from("test-admin-token")),
+            Fetcher::new(crate::scraping::fetch::tests::local_options()),
+        );
+        let agent_id = uuid::Uuid::new_v4().to_string();
+        let webhook = serde_json::json!({
+            "id": agent_id,
+            "resource": "web-crawl",
+            "payload": { "input": format!("http://{}/", addr) },
+            "parent": "",
+        });
+        let create = |authorization: Option<&str>| {
+            let mut request = Request::builder()
+                .uri("/agents")
+                .method("POST")
+                .header("Content-Type", "application/json");
+            if let Some(authorization) = authorization {
+                request = request.header("Authorization", authorization);
+            }
+            request.body(Body::from(webhook.to_string())).unwrap()
+        };
+
+        let response = app.clone().oneshot(create(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app.clone().oneshot(create(Some("Bearer test-admin-token"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let uri = format!("/agents/{}", agent_id);
+        let response = app.oneshot(admin_request("GET", &uri)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8(response_body_bytes(response).await.to_vec()).unwrap();
+        let events: Vec<&str> = body.split("\n\n").filter(|e| !e.is_empty()).collect();
+        assert!(events[0].starts_with("event: page\ndata: "));
+        assert!(events[0].contains(r#""index":1"#));
+        let result = events.iter().find_map(|e| e.strip_prefix("event: result\ndata: ")).unwrap();
+        let output: serde_json::Value = serde_json::from_str(result).unwrap();
+        assert_eq!(output["sources"].as_array().unwrap().len(), 2);
+        assert_eq!(events.last(), Some(&"data: [DONE]"));
+    }
+
     #[tokio::test]
     async fn test_not_found_route() {
         // Create the router
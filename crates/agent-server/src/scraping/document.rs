@@ -0,0 +1,800 @@
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read};
+
+use lopdf::content::Content;
+use lopdf::{Encoding, Object, ObjectId};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use super::fetch::{looks_like_html, Fetched, ScrapeError};
+
+const DEFAULT_MAX_PAGES: usize = 200;
+const DEFAULT_MAX_UNPACKED_BYTES: usize = 50 * 1024 * 1024;
+/// How much of an undeclared body to inspect when guessing whether it is text.
+const SNIFF_BYTES: usize = 1024;
+
+const DOCX_MIME: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+const PPTX_MIME: &str = "application/vnd.openxmlformats-officedocument.presentationml.presentation";
+
+/// What a fetched body contains, decided from its leading bytes before the
+/// declared Content-Type, since documents are often served as
+/// `application/octet-stream`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    Html,
+    Text,
+    Pdf,
+    Docx,
+    Pptx,
+    /// OpenDocument text, spreadsheets and presentations.
+    OpenDocument,
+}
+
+pub fn sniff(fetched: &Fetched) -> Option<ContentKind> {
+    let body = &fetched.body;
+    let head = &body[..body.len().min(SNIFF_BYTES)];
+    // the header may follow some junk bytes, which readers tolerate
+    if head.windows(5).any(|w| w == b"%PDF-") {
+        return Some(ContentKind::Pdf);
+    }
+    if body.starts_with(b"PK\x03\x04") {
+        return package_kind(body);
+    }
+
+    match fetched.mime_type().as_deref() {
+        Some("text/html") | Some("application/xhtml+xml") => Some(ContentKind::Html),
+        Some("application/pdf") => Some(ContentKind::Pdf),
+        Some(DOCX_MIME) => Some(ContentKind::Docx),
+        Some(PPTX_MIME) => Some(ContentKind::Pptx),
+        Some(mime) if mime.starts_with("application/vnd.oasis.opendocument.") => Some(ContentKind::OpenDocument),
+        Some(mime) if is_text_mime(mime) => Some(ContentKind::Text),
+        None | Some("application/octet-stream") | Some("binary/octet-stream") => {
+            if looks_like_html(body) {
+                Some(ContentKind::Html)
+            } else if !head.contains(&0) {
+                Some(ContentKind::Text)
+            } else {
+                None
+            }
+        }
+        Some(_) => None,
+    }
+}
+
+fn is_text_mime(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime,
+            "application/json"
+                | "application/xml"
+                | "application/javascript"
+                | "application/x-ndjson"
+                | "application/yaml"
+                | "application/x-yaml"
+        )
+}
+
+/// Tells office formats apart by the parts inside the zip package.
+fn package_kind(body: &[u8]) -> Option<ContentKind> {
+    let archive = ZipArchive::new(Cursor::new(body)).ok()?;
+    let has = |name: &str| archive.index_for_name(name).is_some();
+    if has("word/document.xml") {
+        Some(ContentKind::Docx)
+    } else if has("ppt/presentation.xml") {
+        Some(ContentKind::Pptx)
+    } else if has("mimetype") && has("content.xml") {
+        Some(ContentKind::OpenDocument)
+    } else {
+        None
+    }
+}
+
+/// Bounds on the work done extracting a single document.
+#[derive(Debug, Clone)]
+pub struct DocumentLimits {
+    /// PDF pages or slides to extract; the rest are noted as skipped.
+    pub max_pages: usize,
+    /// Total decompressed size of the parts read from an office package,
+    /// so a small zip cannot expand without bound.
+    pub max_unpacked_bytes: usize,
+}
+
+impl Default for DocumentLimits {
+    fn default() -> Self {
+        Self {
+            max_pages: DEFAULT_MAX_PAGES,
+            max_unpacked_bytes: DEFAULT_MAX_UNPACKED_BYTES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Heading(usize, String),
+    Paragraph(String),
+    Table(Vec<Vec<String>>),
+}
+
+/// A run of blocks, labelled with its page or slide when the format has them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub label: Option<String>,
+    pub blocks: Vec<Block>,
+}
+
+/// Text extracted from a document, ready to render in each scrape format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub title: Option<String>,
+    pub sections: Vec<Section>,
+}
+
+impl Document {
+    /// Uses the first heading as the title when the metadata has none.
+    fn new(title: Option<String>, sections: Vec<Section>) -> Self {
+        let title = title.filter(|t| !t.trim().is_empty()).or_else(|| {
+            sections.iter().flat_map(|s| &s.blocks).find_map(|block| match block {
+                Block::Heading(_, text) => Some(text.clone()),
+                _ => None,
+            })
+        });
+        Self { title, sections }
+    }
+
+    /// Adds a closing note when only `kept` of `total` pages were extracted.
+    fn note_skipped(&mut self, total: usize, kept: usize, unit: &str) {
+        if total > kept {
+            self.sections.push(Section {
+                label: None,
+                blocks: vec![Block::Paragraph(format!(
+                    "[Stopped after {} of {} {}]",
+                    kept, total, unit
+                ))],
+            });
+        }
+    }
+
+    /// Pages and slides become `## Page N` headings.
+    pub fn to_markdown(&self) -> String {
+        let mut parts = Vec::new();
+        for section in &self.sections {
+            if let Some(label) = &section.label {
+                parts.push(format!("## {}", label));
+            }
+            for block in &section.blocks {
+                parts.push(match block {
+                    Block::Heading(level, text) => format!("{} {}", "#".repeat(*level), text),
+                    Block::Paragraph(text) => text.clone(),
+                    Block::Table(rows) => markdown_table(rows),
+                });
+            }
+        }
+        parts.join("\n\n")
+    }
+
+    /// Pages and slides are marked with `[Page N]` lines.
+    pub fn to_text(&self) -> String {
+        let mut parts = Vec::new();
+        for section in &self.sections {
+            if let Some(label) = &section.label {
+                parts.push(format!("[{}]", label));
+            }
+            for block in &section.blocks {
+                parts.push(match block {
+                    Block::Heading(_, text) | Block::Paragraph(text) => text.clone(),
+                    Block::Table(rows) => rows.iter().map(|row| row.join("\t")).collect::<Vec<_>>().join("\n"),
+                });
+            }
+        }
+        parts.join("\n\n")
+    }
+
+    /// Pages and slides become `<section>` elements with an `<h2>` label.
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        for section in &self.sections {
+            html.push_str("<section>");
+            if let Some(label) = &section.label {
+                html.push_str(&format!("<h2>{}</h2>", escape_html(label)));
+            }
+            for block in &section.blocks {
+                match block {
+                    Block::Heading(level, text) => {
+                        html.push_str(&format!("<h{0}>{1}</h{0}>", level, escape_html(text)))
+                    }
+                    Block::Paragraph(text) => {
+                        html.push_str(&format!("<p>{}</p>", escape_html(text).replace('\n', "<br>")))
+                    }
+                    Block::Table(rows) => {
+                        html.push_str("<table>");
+                        for row in rows {
+                            html.push_str("<tr>");
+                            for cell in row {
+                                html.push_str(&format!("<td>{}</td>", escape_html(cell)));
+                            }
+                            html.push_str("</tr>");
+                        }
+                        html.push_str("</table>");
+                    }
+                }
+            }
+            html.push_str("</section>");
+        }
+        html
+    }
+}
+
+fn markdown_table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let row = |cells: &[String]| {
+        let cells: Vec<String> = (0..columns)
+            .map(|i| {
+                cells
+                    .get(i)
+                    .map(|c| c.replace('|', "\\|").replace('\n', " "))
+                    .unwrap_or_default()
+            })
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let mut lines = vec![row(&rows[0]), format!("|{}", " --- |".repeat(columns))];
+    lines.extend(rows[1..].iter().map(|cells| row(cells)));
+    lines.join("\n")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn invalid(format: &str, e: impl std::fmt::Display) -> ScrapeError {
+    ScrapeError::Document(format!("invalid {}: {}", format, e))
+}
+
+/// Extracts the text of each page of a PDF, up to `limits.max_pages`.
+pub fn read_pdf(body: &[u8], limits: &DocumentLimits) -> Result<Document, ScrapeError> {
+    let pdf = lopdf::Document::load_mem(body).map_err(|e| invalid("PDF", e))?;
+    let pages = pdf.get_pages();
+
+    let mut sections = Vec::new();
+    for (&number, &page_id) in pages.iter().take(limits.max_pages) {
+        let text = page_text(&pdf, page_id).unwrap_or_else(|e| {
+            tracing::debug!("Could not extract text from PDF page {}: {}", number, e);
+            String::new()
+        });
+        sections.push(Section {
+            label: Some(format!("Page {}", number)),
+            blocks: paragraphs(&text),
+        });
+    }
+
+    let mut document = Document::new(pdf_title(&pdf), sections);
+    document.note_skipped(pages.len(), limits.max_pages, "pages");
+    Ok(document)
+}
+
+/// Like `lopdf::Document::extract_text`, but starts a new line whenever the
+/// text position moves down instead of only at the end of a text object.
+fn page_text(pdf: &lopdf::Document, page_id: ObjectId) -> lopdf::Result<String> {
+    let encodings: BTreeMap<Vec<u8>, Encoding> = pdf
+        .get_page_fonts(page_id)?
+        .into_iter()
+        .filter_map(|(name, font)| font.get_font_encoding(pdf).ok().map(|encoding| (name, encoding)))
+        .collect();
+    let content = Content::decode(&pdf.get_page_content(page_id)?)?;
+
+    let mut text = String::new();
+    let mut encoding = None;
+    for operation in &content.operations {
+        let operands = &operation.operands;
+        match operation.operator.as_str() {
+            "Tf" => {
+                encoding = operands
+                    .first()
+                    .and_then(|name| name.as_name().ok())
+                    .and_then(|name| encodings.get(name))
+            }
+            "Tj" | "TJ" | "'" | "\"" => {
+                if matches!(operation.operator.as_str(), "'" | "\"") {
+                    new_line(&mut text);
+                }
+                if let Some(encoding) = encoding {
+                    show_text(&mut text, encoding, operands);
+                }
+            }
+            "Td" | "TD" if operands.get(1).and_then(|ty| ty.as_float().ok()).unwrap_or(0.0) != 0.0 => {
+                new_line(&mut text)
+            }
+            "T*" | "ET" => new_line(&mut text),
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+fn show_text(text: &mut String, encoding: &Encoding, operands: &[Object]) {
+    for operand in operands {
+        match operand {
+            Object::String(bytes, _) => {
+                if let Ok(decoded) = lopdf::Document::decode_text(encoding, bytes) {
+                    text.push_str(&decoded);
+                }
+            }
+            Object::Array(items) => show_text(text, encoding, items),
+            // large negative kerning in a TJ array is how most producers write a space
+            Object::Integer(_) | Object::Real(_)
+                if operand.as_float().unwrap_or(0.0) < -100.0 && !text.ends_with(' ') =>
+            {
+                text.push(' ')
+            }
+            _ => {}
+        }
+    }
+}
+
+fn new_line(text: &mut String) {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+/// Splits text into paragraphs at blank lines, trimming every line.
+fn paragraphs(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines().map(str::trim).chain([""]) {
+        if !line.is_empty() {
+            lines.push(line);
+        } else if !lines.is_empty() {
+            blocks.push(Block::Paragraph(lines.join("\n")));
+            lines.clear();
+        }
+    }
+    blocks
+}
+
+fn pdf_title(pdf: &lopdf::Document) -> Option<String> {
+    let (_, info) = pdf.dereference(pdf.trailer.get(b"Info").ok()?).ok()?;
+    let (_, title) = pdf.dereference(info.as_dict().ok()?.get(b"Title").ok()?).ok()?;
+    let title = lopdf::decode_text_string(title).ok()?;
+    Some(title.trim().to_string())
+}
+
+/// An office zip package whose parts are read within a shared size budget.
+struct Package<'a> {
+    archive: ZipArchive<Cursor<&'a [u8]>>,
+    limit: usize,
+    remaining: usize,
+}
+
+impl<'a> Package<'a> {
+    fn open(body: &'a [u8], limits: &DocumentLimits) -> Result<Self, ScrapeError> {
+        Ok(Self {
+            archive: ZipArchive::new(Cursor::new(body)).map_err(|e| invalid("document package", e))?,
+            limit: limits.max_unpacked_bytes,
+            remaining: limits.max_unpacked_bytes,
+        })
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.archive.file_names().map(str::to_string).collect()
+    }
+
+    /// Reads a part as text, or `None` when the package does not contain it.
+    fn read(&mut self, name: &str) -> Result<Option<String>, ScrapeError> {
+        let file = match self.archive.by_name(name) {
+            Ok(file) => file,
+            Err(ZipError::FileNotFound) => return Ok(None),
+            Err(e) => return Err(invalid("document package", e)),
+        };
+        let too_large = || ScrapeError::Document(format!("unpacks to more than {} bytes", self.limit));
+        // the declared size can lie, so the read is bounded as well
+        if file.size() > self.remaining as u64 {
+            return Err(too_large());
+        }
+        let mut part = String::new();
+        file.take(self.remaining as u64 + 1)
+            .read_to_string(&mut part)
+            .map_err(|e| invalid(name, e))?;
+        if part.len() > self.remaining {
+            return Err(too_large());
+        }
+        self.remaining -= part.len();
+        Ok(Some(part))
+    }
+
+    fn title(&mut self, part: &str) -> Result<Option<String>, ScrapeError> {
+        Ok(match self.read(part)? {
+            Some(xml) => element_text(&xml, b"dc:title")?,
+            None => None,
+        })
+    }
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    let value = element.try_get_attribute(name).ok()??;
+    value.unescape_value().ok().map(|v| v.into_owned())
+}
+
+/// The text of the first `name` element in `xml`.
+fn element_text(xml: &str, name: &[u8]) -> Result<Option<String>, ScrapeError> {
+    let mut reader = Reader::from_str(xml);
+    let mut inside = false;
+    let mut text = String::new();
+    loop {
+        match reader.read_event().map_err(|e| invalid("XML", e))? {
+            Event::Start(e) if e.name().as_ref() == name => inside = true,
+            Event::End(e) if e.name().as_ref() == name => break,
+            Event::Text(t) if inside => text.push_str(&t.unescape().map_err(|e| invalid("XML", e))?),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(Some(text.trim().to_string()).filter(|t| !t.is_empty()))
+}
+
+/// Heading level for a Word paragraph style such as `Title` or `Heading2`.
+fn heading_level(style: &str) -> Option<usize> {
+    if style.eq_ignore_ascii_case("title") {
+        return Some(1);
+    }
+    let level = style
+        .to_ascii_lowercase()
+        .strip_prefix("heading")?
+        .trim()
+        .parse::<usize>()
+        .ok()?;
+    Some(level.clamp(1, 6))
+}
+
+/// Extracts the body of a Word document, keeping heading styles and tables.
+pub fn read_docx(body: &[u8], limits: &DocumentLimits) -> Result<Document, ScrapeError> {
+    let mut package = Package::open(body, limits)?;
+    let xml = package
+        .read("word/document.xml")?
+        .ok_or_else(|| invalid("DOCX", "missing word/document.xml"))?;
+
+    let mut reader = Reader::from_str(&xml);
+    let mut blocks = Vec::new();
+    let mut paragraph = String::new();
+    let mut heading = None;
+    let mut in_text = false;
+    let mut table: Vec<Vec<String>> = Vec::new();
+    let mut row: Option<Vec<String>> = None;
+    let mut cell = String::new();
+    // tables nested in a cell are flattened into that cell
+    let mut table_depth = 0;
+
+    loop {
+        match reader.read_event().map_err(|e| invalid("DOCX", e))? {
+            Event::Start(e) => match e.name().as_ref() {
+                b"w:p" => {
+                    paragraph.clear();
+                    heading = None;
+                }
+                b"w:t" => in_text = true,
+                b"w:tbl" => table_depth += 1,
+                b"w:tr" if table_depth == 1 => row = Some(Vec::new()),
+                _ => {}
+            },
+            Event::Empty(e) => match e.name().as_ref() {
+                b"w:pStyle" => heading = attribute(&e, "w:val").as_deref().and_then(heading_level),
+                b"w:tab" => paragraph.push('\t'),
+                b"w:br" | b"w:cr" => paragraph.push('\n'),
+                _ => {}
+            },
+            Event::Text(t) if in_text => paragraph.push_str(&t.unescape().map_err(|e| invalid("DOCX", e))?),
+            Event::End(e) => match e.name().as_ref() {
+                b"w:t" => in_text = false,
+                b"w:p" => {
+                    let text = paragraph.trim();
+                    if !text.is_empty() && table_depth > 0 {
+                        if !cell.is_empty() {
+                            cell.push(' ');
+                        }
+                        cell.push_str(text);
+                    } else if !text.is_empty() {
+                        blocks.push(match heading {
+                            Some(level) => Block::Heading(level, text.to_string()),
+                            None => Block::Paragraph(text.to_string()),
+                        });
+                    }
+                }
+                b"w:tc" if table_depth == 1 => {
+                    if let Some(row) = row.as_mut() {
+                        row.push(std::mem::take(&mut cell));
+                    }
+                }
+                b"w:tr" if table_depth == 1 => table.extend(row.take()),
+                b"w:tbl" => {
+                    table_depth -= 1;
+                    if table_depth == 0 && !table.is_empty() {
+                        blocks.push(Block::Table(std::mem::take(&mut table)));
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let title = package.title("docProps/core.xml")?;
+    Ok(Document::new(
+        title,
+        vec![Section {
+            label: None,
+            blocks,
+        }],
+    ))
+}
+
+/// Extracts each slide of a presentation in order, up to `limits.max_pages`,
+/// using the title placeholder as the slide heading.
+pub fn read_pptx(body: &[u8], limits: &DocumentLimits) -> Result<Document, ScrapeError> {
+    let mut package = Package::open(body, limits)?;
+    let mut slides: Vec<(usize, String)> = package
+        .names()
+        .into_iter()
+        .filter_map(|name| {
+            let number = name
+                .strip_prefix("ppt/slides/slide")?
+                .strip_suffix(".xml")?
+                .parse()
+                .ok()?;
+            Some((number, name))
+        })
+        .collect();
+    slides.sort();
+
+    let mut sections = Vec::new();
+    for (number, name) in slides.iter().take(limits.max_pages) {
+        let Some(xml) = package.read(name)? else {
+            continue;
+        };
+        sections.push(Section {
+            label: Some(format!("Slide {}", number)),
+            blocks: slide_blocks(&xml)?,
+        });
+    }
+
+    let title = package.title("docProps/core.xml")?;
+    let mut document = Document::new(title, sections);
+    document.note_skipped(slides.len(), limits.max_pages, "slides");
+    Ok(document)
+}
+
+fn slide_blocks(xml: &str) -> Result<Vec<Block>, ScrapeError> {
+    let mut reader = Reader::from_str(xml);
+    let mut blocks = Vec::new();
+    let mut paragraph = String::new();
+    let mut in_text = false;
+    let mut title_shape = false;
+
+    loop {
+        match reader.read_event().map_err(|e| invalid("PPTX", e))? {
+            Event::Start(e) => match e.name().as_ref() {
+                b"p:sp" => title_shape = false,
+                b"a:p" => paragraph.clear(),
+                b"a:t" => in_text = true,
+                _ => {}
+            },
+            Event::Empty(e) => match e.name().as_ref() {
+                b"p:ph" => {
+                    title_shape = matches!(attribute(&e, "type").as_deref(), Some("title" | "ctrTitle"))
+                }
+                b"a:br" => paragraph.push('\n'),
+                _ => {}
+            },
+            Event::Text(t) if in_text => paragraph.push_str(&t.unescape().map_err(|e| invalid("PPTX", e))?),
+            Event::End(e) => match e.name().as_ref() {
+                b"a:t" => in_text = false,
+                b"a:p" => {
+                    let text = paragraph.trim().to_string();
+                    if !text.is_empty() {
+                        blocks.push(match title_shape {
+                            true => Block::Heading(3, text),
+                            false => Block::Paragraph(text),
+                        });
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(blocks)
+}
+
+/// Extracts the headings and paragraphs of an OpenDocument file.
+pub fn read_open_document(body: &[u8], limits: &DocumentLimits) -> Result<Document, ScrapeError> {
+    let mut package = Package::open(body, limits)?;
+    let xml = package
+        .read("content.xml")?
+        .ok_or_else(|| invalid("OpenDocument", "missing content.xml"))?;
+
+    let mut reader = Reader::from_str(&xml);
+    let mut blocks = Vec::new();
+    // text of the open paragraph, with its heading level for text:h
+    let mut current: Option<(Option<usize>, String)> = None;
+
+    loop {
+        match reader.read_event().map_err(|e| invalid("OpenDocument", e))? {
+            Event::Start(e) => match e.name().as_ref() {
+                b"text:p" if current.is_none() => current = Some((None, String::new())),
+                b"text:h" if current.is_none() => {
+                    let level = attribute(&e, "text:outline-level")
+                        .and_then(|l| l.parse::<usize>().ok())
+                        .unwrap_or(1)
+                        .clamp(1, 6);
+                    current = Some((Some(level), String::new()));
+                }
+                _ => {}
+            },
+            Event::Empty(e) => {
+                if let Some((_, text)) = current.as_mut() {
+                    match e.name().as_ref() {
+                        b"text:s" => {
+                            let count = attribute(&e, "text:c").and_then(|c| c.parse().ok()).unwrap_or(1);
+                            text.push_str(&" ".repeat(count));
+                        }
+                        b"text:tab" => text.push('\t'),
+                        b"text:line-break" => text.push('\n'),
+                        _ => {}
+                    }
+                }
+            }
+            Event::Text(t) => {
+                if let Some((_, text)) = current.as_mut() {
+                    text.push_str(&t.unescape().map_err(|e| invalid("OpenDocument", e))?);
+                }
+            }
+            Event::End(e) if matches!(e.name().as_ref(), b"text:p" | b"text:h") => {
+                if let Some((level, text)) = current.take() {
+                    let text = text.trim().to_string();
+                    if !text.is_empty() {
+                        blocks.push(match level {
+                            Some(level) => Block::Heading(level, text),
+                            None => Block::Paragraph(text),
+                        });
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let title = package.title("meta.xml")?;
+    Ok(Document::new(
+        title,
+        vec![Section {
+            label: None,
+            blocks,
+        }],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+    use url::Url;
+
+    const PDF: &[u8] = include_bytes!("../../fixtures/documents/report.pdf");
+    const DOCX: &[u8] = include_bytes!("../../fixtures/documents/guide.docx");
+    const PPTX: &[u8] = include_bytes!("../../fixtures/documents/deck.pptx");
+    const ODT: &[u8] = include_bytes!("../../fixtures/documents/notes.odt");
+
+    fn fetched(content_type: Option<&str>, body: &[u8]) -> Fetched {
+        Fetched {
+            url: Url::parse("https://example.com/file").unwrap(),
+            status: StatusCode::OK,
+            content_type: content_type.map(str::to_string),
+            body: body.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_sniff_prefers_content_over_declared_type() {
+        let kind = |content_type, body| sniff(&fetched(content_type, body));
+        assert_eq!(kind(Some("application/octet-stream"), PDF), Some(ContentKind::Pdf));
+        assert_eq!(kind(Some("text/html"), PDF), Some(ContentKind::Pdf));
+        assert_eq!(kind(None, DOCX), Some(ContentKind::Docx));
+        assert_eq!(kind(Some("application/zip"), PPTX), Some(ContentKind::Pptx));
+        assert_eq!(kind(None, ODT), Some(ContentKind::OpenDocument));
+
+        assert_eq!(kind(Some("text/html; charset=utf-8"), b"<p>hi</p>"), Some(ContentKind::Html));
+        assert_eq!(kind(None, b"<!DOCTYPE html><html></html>"), Some(ContentKind::Html));
+        assert_eq!(kind(Some("text/markdown"), b"# Notes"), Some(ContentKind::Text));
+        assert_eq!(kind(Some("application/ld+json"), b"{}"), Some(ContentKind::Text));
+        assert_eq!(kind(Some("application/octet-stream"), b"just text"), Some(ContentKind::Text));
+
+        assert_eq!(kind(Some("application/octet-stream"), b"\x00\x01binary"), None);
+        assert_eq!(kind(Some("image/png"), b"\x89PNG"), None);
+        assert_eq!(kind(Some("application/pdf"), b"not a pdf"), Some(ContentKind::Pdf));
+    }
+
+    #[test]
+    fn test_read_pdf_marks_pages() {
+        let document = read_pdf(PDF, &DocumentLimits::default()).unwrap();
+        assert_eq!(document.title.as_deref(), Some("Quarterly Report Q3"));
+        assert_eq!(
+            document.to_markdown(),
+            "## Page 1\n\n\
+             Quarterly Report\nRevenue grew by twelve percent compared to last year.\nCosts stayed flat.\n\n\
+             ## Page 2\n\n\
+             Outlook\nWe expect steady growth in the next quarter."
+        );
+        assert!(document.to_text().starts_with("[Page 1]\n\nQuarterly Report\n"));
+
+        let limited = read_pdf(PDF, &DocumentLimits { max_pages: 1, ..DocumentLimits::default() }).unwrap();
+        let markdown = limited.to_markdown();
+        assert!(!markdown.contains("## Page 2"));
+        assert!(markdown.ends_with("[Stopped after 1 of 2 pages]"));
+
+        assert!(matches!(read_pdf(b"%PDF-1.4 truncated", &DocumentLimits::default()), Err(ScrapeError::Document(_))));
+    }
+
+    #[test]
+    fn test_read_docx_keeps_headings_and_tables() {
+        let document = read_docx(DOCX, &DocumentLimits::default()).unwrap();
+        assert_eq!(document.title.as_deref(), Some("Onboarding Guide"));
+        assert_eq!(
+            document.to_markdown(),
+            "# Onboarding Guide\n\n\
+             # Getting started\n\n\
+             Install the agent and run it.\tTabbed\nNext line & more\n\n\
+             ## Configuration\n\n\
+             | Name | Default |\n| --- | --- |\n| PORT | 3006 |\n\n\
+             That is all."
+        );
+        assert!(document.to_text().contains("Name\tDefault\nPORT\t3006"));
+        assert!(document.to_html().contains("<p>Install the agent and run it.\tTabbed<br>Next line &amp; more</p>"));
+    }
+
+    #[test]
+    fn test_read_pptx_orders_slides() {
+        let document = read_pptx(PPTX, &DocumentLimits::default()).unwrap();
+        assert_eq!(document.title.as_deref(), Some("Launch plan"));
+        assert_eq!(
+            document.to_markdown(),
+            "## Slide 1\n\n### Launch plan\n\nAgenda for today\n\n\
+             ## Slide 2\n\n### Roadmap\n\nShip the crawler\n\nAdd document support\n\n\
+             ## Slide 10\n\nQuestions?"
+        );
+
+        let limited = read_pptx(PPTX, &DocumentLimits { max_pages: 2, ..DocumentLimits::default() }).unwrap();
+        assert!(limited.to_text().ends_with("[Stopped after 2 of 3 slides]"));
+    }
+
+    #[test]
+    fn test_read_open_document() {
+        let document = read_open_document(ODT, &DocumentLimits::default()).unwrap();
+        assert_eq!(document.title.as_deref(), Some("Meeting notes"));
+        assert_eq!(
+            document.to_markdown(),
+            "# Meeting notes\n\nDecided to  adopt the plan.\nOwner: Sam\n\nFollow up next week"
+        );
+    }
+
+    #[test]
+    fn test_unpacked_size_limit() {
+        let limits = DocumentLimits {
+            max_unpacked_bytes: 256,
+            ..DocumentLimits::default()
+        };
+        match read_docx(DOCX, &limits) {
+            Err(ScrapeError::Document(reason)) => assert!(reason.contains("more than 256 bytes")),
+            other => panic!("expected the size limit to stop extraction, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,551 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use encoding_rs::{Encoding, UTF_8};
+use reqwest::header::{CONTENT_TYPE, LOCATION};
+use reqwest::redirect::Policy;
+use reqwest::StatusCode;
+use url::Url;
+
+use super::document::DocumentLimits;
+use super::politeness::HostLimiter;
+use super::robots::RobotsCache;
+use super::ssrf::{GuardedResolver, UrlPolicy};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(20);
+const DEFAULT_MAX_REDIRECTS: usize = 5;
+const DEFAULT_MAX_BYTES: usize = 5 * 1024 * 1024;
+const DEFAULT_MAX_CONCURRENCY_PER_HOST: usize = 2;
+pub const DEFAULT_USER_AGENT: &str = concat!("open-web-agent-rs/", env!("CARGO_PKG_VERSION"));
+/// How far into a document to look for a `<meta charset>` declaration.
+const META_SNIFF_BYTES: usize = 1024;
+
+#[derive(Debug)]
+pub enum ScrapeError {
+    InvalidUrl(String),
+    Disallowed { url: String, user_agent: String },
+    Blocked { url: String, reason: String },
+    TooManyRedirects(usize),
+    Http(reqwest::Error),
+    Status(StatusCode),
+    TooLarge(usize),
+    UnsupportedContent(String),
+    Document(String),
+}
+
+impl std::fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScrapeError::InvalidUrl(url) => write!(f, "Invalid url: {}", url),
+            ScrapeError::Disallowed { url, user_agent } => {
+                write!(f, "robots.txt disallows fetching {} for user agent {}", url, user_agent)
+            }
+            ScrapeError::Blocked { url, reason } => write!(f, "Refusing to fetch {}: {}", url, reason),
+            ScrapeError::TooManyRedirects(limit) => write!(f, "Stopped after {} redirects", limit),
+            ScrapeError::Http(e) => write!(f, "Fetch failed: {}", e),
+            ScrapeError::Status(status) => write!(f, "Server responded with {}", status),
+            ScrapeError::TooLarge(limit) => write!(f, "Response is larger than {} bytes", limit),
+            ScrapeError::UnsupportedContent(mime) => write!(f, "Unsupported content type: {}", mime),
+            ScrapeError::Document(reason) => write!(f, "Could not read document: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ScrapeError {}
+
+impl From<reqwest::Error> for ScrapeError {
+    fn from(e: reqwest::Error) -> Self {
+        ScrapeError::Http(e)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FetchOptions {
+    pub timeout: Duration,
+    pub max_redirects: usize,
+    pub max_bytes: usize,
+    pub user_agent: String,
+    pub respect_robots: bool,
+    pub max_concurrency_per_host: usize,
+    pub url_policy: Arc<UrlPolicy>,
+    pub document_limits: DocumentLimits,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            max_bytes: DEFAULT_MAX_BYTES,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            respect_robots: true,
+            max_concurrency_per_host: DEFAULT_MAX_CONCURRENCY_PER_HOST,
+            url_policy: Arc::new(UrlPolicy::default()),
+            document_limits: DocumentLimits::default(),
+        }
+    }
+}
+
+impl FetchOptions {
+    /// Reads `SCRAPE_TIMEOUT_SECS`, `SCRAPE_MAX_REDIRECTS`, `SCRAPE_MAX_BYTES`,
+    /// `SCRAPE_USER_AGENT`, `SCRAPE_RESPECT_ROBOTS`, `SCRAPE_MAX_CONCURRENCY_PER_HOST`,
+    /// `SCRAPE_ALLOWLIST`, `SCRAPE_MAX_DOCUMENT_PAGES` and `SCRAPE_MAX_UNPACKED_BYTES`,
+    /// keeping the defaults for unset values.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let parse = |key: &str| std::env::var(key).ok().and_then(|v| v.trim().parse::<u64>().ok());
+        Self {
+            timeout: parse("SCRAPE_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.timeout),
+            max_redirects: parse("SCRAPE_MAX_REDIRECTS")
+                .map(|n| n as usize)
+                .unwrap_or(defaults.max_redirects),
+            max_bytes: parse("SCRAPE_MAX_BYTES")
+                .map(|n| n as usize)
+                .unwrap_or(defaults.max_bytes),
+            user_agent: std::env::var("SCRAPE_USER_AGENT")
+                .ok()
+                .filter(|ua| !ua.trim().is_empty())
+                .unwrap_or(defaults.user_agent),
+            respect_robots: std::env::var("SCRAPE_RESPECT_ROBOTS")
+                .map(|v| !matches!(v.trim().to_lowercase().as_str(), "false" | "0" | "no"))
+                .unwrap_or(defaults.respect_robots),
+            max_concurrency_per_host: parse("SCRAPE_MAX_CONCURRENCY_PER_HOST")
+                .map(|n| n as usize)
+                .unwrap_or(defaults.max_concurrency_per_host),
+            url_policy: Arc::new(UrlPolicy::from_env()),
+            document_limits: DocumentLimits {
+                max_pages: parse("SCRAPE_MAX_DOCUMENT_PAGES")
+                    .map(|n| n as usize)
+                    .unwrap_or(defaults.document_limits.max_pages),
+                max_unpacked_bytes: parse("SCRAPE_MAX_UNPACKED_BYTES")
+                    .map(|n| n as usize)
+                    .unwrap_or(defaults.document_limits.max_unpacked_bytes),
+            },
+        }
+    }
+}
+
+/// A fetched response body, after redirects.
+#[derive(Debug, Clone)]
+pub struct Fetched {
+    pub url: Url,
+    pub status: StatusCode,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl Fetched {
+    /// The lowercased media type without parameters, e.g. `text/html`.
+    pub fn mime_type(&self) -> Option<String> {
+        self.content_type
+            .as_deref()
+            .and_then(|ct| ct.split(';').next())
+            .map(|mime| mime.trim().to_lowercase())
+            .filter(|mime| !mime.is_empty())
+    }
+
+    pub fn is_html(&self) -> bool {
+        match self.mime_type().as_deref() {
+            Some("text/html") | Some("application/xhtml+xml") => true,
+            Some(_) => false,
+            None => looks_like_html(&self.body),
+        }
+    }
+
+    /// Decodes the body using the detected character encoding.
+    pub fn text(&self) -> String {
+        decode(&self.body, self.content_type.as_deref())
+    }
+}
+
+/// Whether an undeclared body starts like an HTML document.
+pub(crate) fn looks_like_html(body: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&body[..body.len().min(META_SNIFF_BYTES)]).to_lowercase();
+    head.contains("<html") || head.contains("<!doctype html")
+}
+
+/// HTTP client for the scraper. Clones share the robots.txt cache and the
+/// per-host limits.
+#[derive(Debug, Clone)]
+pub struct Fetcher {
+    http: reqwest::Client,
+    robots_http: reqwest::Client,
+    options: FetchOptions,
+    robots: Arc<RobotsCache>,
+    hosts: Arc<HostLimiter>,
+}
+
+impl Fetcher {
+    pub fn new(options: FetchOptions) -> Self {
+        let client = |redirects: Policy| {
+            reqwest::Client::builder()
+                .timeout(options.timeout)
+                .redirect(redirects)
+                .user_agent(options.user_agent.clone())
+                // A proxy would resolve the target itself, bypassing the guarded resolver
+                .no_proxy()
+                .dns_resolver(Arc::new(GuardedResolver::new(options.url_policy.clone())))
+                .build()
+                .unwrap_or_default()
+        };
+        let max_redirects = options.max_redirects;
+        let url_policy = options.url_policy.clone();
+        let robots_redirects = Policy::custom(move |attempt| {
+            if attempt.previous().len() > max_redirects {
+                attempt.error("too many redirects")
+            } else if parse_url(attempt.url().as_str()).is_err() || !url_policy.literal_allowed(attempt.url()) {
+                attempt.stop()
+            } else {
+                attempt.follow()
+            }
+        });
+        Self {
+            // page redirects are followed by hand so every hop is checked
+            // against the url policy and robots.txt
+            http: client(Policy::none()),
+            robots_http: client(robots_redirects),
+            robots: Arc::new(RobotsCache::default()),
+            hosts: Arc::new(HostLimiter::new(options.max_concurrency_per_host)),
+            options,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(FetchOptions::from_env())
+    }
+
+    pub fn options(&self) -> &FetchOptions {
+        &self.options
+    }
+
+    /// Downloads `url`, following redirects and enforcing the size limit
+    /// while the body streams in. Every hop must resolve to a permitted
+    /// address, be allowed by its host's robots.txt and wait for a per-host
+    /// request slot.
+    pub async fn fetch(&self, url: &str) -> Result<Fetched, ScrapeError> {
+        let mut url = parse_url(url)?;
+        let mut redirects = 0;
+
+        loop {
+            self.options
+                .url_policy
+                .check(&url)
+                .await
+                .map_err(|reason| ScrapeError::Blocked {
+                    url: url.to_string(),
+                    reason,
+                })?;
+            let crawl_delay = self.check_robots(&url).await?;
+            let _permit = self.hosts.acquire(&url, crawl_delay).await;
+            let response = self.http.get(url.clone()).send().await?;
+
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .filter(|_| response.status().is_redirection());
+            if let Some(location) = location {
+                redirects += 1;
+                if redirects > self.options.max_redirects {
+                    return Err(ScrapeError::TooManyRedirects(self.options.max_redirects));
+                }
+                let next = url
+                    .join(location)
+                    .map_err(|_| ScrapeError::InvalidUrl(location.to_string()))?;
+                url = parse_url(next.as_str())?;
+                continue;
+            }
+
+            return self.read(url, response).await;
+        }
+    }
+
+    /// Returns the crawl-delay for `url`, or an error when robots.txt disallows it.
+    async fn check_robots(&self, url: &Url) -> Result<Option<Duration>, ScrapeError> {
+        if !self.options.respect_robots {
+            return Ok(None);
+        }
+        let rules = self
+            .robots
+            .get(&self.robots_http, url)
+            .await
+            .rules_for(&self.options.user_agent);
+        if !rules.is_allowed(url) {
+            return Err(ScrapeError::Disallowed {
+                url: url.to_string(),
+                user_agent: self.options.user_agent.clone(),
+            });
+        }
+        Ok(rules.crawl_delay)
+    }
+
+    async fn read(&self, url: Url, mut response: reqwest::Response) -> Result<Fetched, ScrapeError> {
+        let status = response.status();
+        if !status.is_success() {
+            return Err(ScrapeError::Status(status));
+        }
+
+        let max_bytes = self.options.max_bytes;
+        if response.content_length().is_some_and(|len| len as usize > max_bytes) {
+            return Err(ScrapeError::TooLarge(max_bytes));
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > max_bytes {
+                return Err(ScrapeError::TooLarge(max_bytes));
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(Fetched {
+            url,
+            status,
+            content_type,
+            body,
+        })
+    }
+}
+
+/// Parses `url`, accepting only absolute http(s) urls.
+pub fn parse_url(url: &str) -> Result<Url, ScrapeError> {
+    let parsed = Url::parse(url.trim()).map_err(|_| ScrapeError::InvalidUrl(url.to_string()))?;
+    match parsed.scheme() {
+        "http" | "https" if parsed.host().is_some() => Ok(parsed),
+        _ => Err(ScrapeError::InvalidUrl(url.to_string())),
+    }
+}
+
+/// Decodes `body`, preferring a byte order mark, then the `charset` of the
+/// Content-Type header, then a `<meta>` declaration, then a statistical guess.
+pub fn decode(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = Encoding::for_bom(body)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(charset_param).and_then(label_encoding))
+        .or_else(|| meta_charset(body))
+        .unwrap_or_else(|| {
+            let mut detector = chardetng::EncodingDetector::new();
+            detector.feed(body, true);
+            detector.guess(None, true)
+        });
+    let (text, _, _) = encoding.decode(body);
+    text.into_owned()
+}
+
+fn label_encoding(label: &str) -> Option<&'static Encoding> {
+    let encoding = Encoding::for_label(label.trim().as_bytes())?;
+    // pages served as utf-16 without a bom are almost always mislabelled utf-8
+    if encoding.output_encoding() != encoding {
+        return Some(UTF_8);
+    }
+    Some(encoding)
+}
+
+fn charset_param(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches(['"', '\'']))
+    })
+}
+
+/// Finds `<meta charset=...>` or `<meta http-equiv content="...; charset=...">`
+/// near the start of the document.
+fn meta_charset(body: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&body[..body.len().min(META_SNIFF_BYTES)]).to_lowercase();
+    let mut rest = head.as_str();
+    while let Some(start) = rest.find("<meta") {
+        let tag = &rest[start..];
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+        if let Some(pos) = tag.find("charset=") {
+            let value = tag[pos + "charset=".len()..].trim_start_matches(['"', '\'']);
+            let end = value
+                .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.')))
+                .unwrap_or(value.len());
+            if let Some(encoding) = label_encoding(&value[..end]) {
+                return Some(encoding);
+            }
+        }
+        rest = &rest[start + "<meta".len()..];
+    }
+    None
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use axum::http::header;
+    use axum::response::{IntoResponse, Redirect};
+    use axum::routing::get;
+    use axum::Router;
+
+    /// Options that let tests reach mock servers on the loopback address.
+    pub(crate) fn local_options() -> FetchOptions {
+        FetchOptions {
+            url_policy: Arc::new(UrlPolicy::with_allowlist(["127.0.0.1"])),
+            ..FetchOptions::default()
+        }
+    }
+
+    const WINDOWS_1252: &[u8] = include_bytes!("../../fixtures/html/windows-1252.html");
+
+    async fn serve(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    #[test]
+    fn test_decode_uses_meta_charset() {
+        let text = decode(WINDOWS_1252, Some("text/html"));
+        assert!(text.contains("Un café crème coûte 3€"));
+    }
+
+    #[test]
+    fn test_decode_uses_bom_then_header_charset() {
+        let latin1 = b"caf\xe9 cr\xe8me";
+        assert_eq!(decode(latin1, Some("text/plain; charset=\"ISO-8859-1\"")), "café crème");
+
+        // a byte order mark wins over a conflicting header
+        let utf16 = "h\u{e9}llo".encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<_>>();
+        let body = [&[0xFF, 0xFE][..], &utf16].concat();
+        assert_eq!(decode(&body, Some("text/plain; charset=iso-8859-1")), "h\u{e9}llo");
+    }
+
+    #[test]
+    fn test_decode_guesses_undeclared_encoding() {
+        let body = "Ein Bär läuft über die Straße und schläft später im Gebüsch, größer als gewöhnlich."
+            .chars()
+            .map(|c| c as u32 as u8)
+            .collect::<Vec<_>>();
+        assert!(decode(&body, None).contains("Straße"));
+        assert_eq!(decode("plain ascii ✓".as_bytes(), None), "plain ascii ✓");
+    }
+
+    #[test]
+    fn test_parse_url_accepts_only_http() {
+        assert!(parse_url("https://example.com/a").is_ok());
+        assert!(matches!(parse_url("file:///etc/passwd"), Err(ScrapeError::InvalidUrl(_))));
+        assert!(matches!(parse_url("example.com"), Err(ScrapeError::InvalidUrl(_))));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_follows_redirects_and_limits_size() {
+        let app = Router::new()
+            .route("/start", get(|| async { Redirect::temporary("/page") }))
+            .route(
+                "/page",
+                get(|| async {
+                    ([(header::CONTENT_TYPE, "text/html; charset=windows-1252")], WINDOWS_1252).into_response()
+                }),
+            )
+            .route("/loop", get(|| async { Redirect::temporary("/loop") }))
+            .route("/large", get(|| async { "x".repeat(64 * 1024) }));
+        let base_url = serve(app).await;
+
+        let fetcher = Fetcher::new(FetchOptions {
+            max_bytes: 16 * 1024,
+            ..local_options()
+        });
+
+        let page = fetcher.fetch(&format!("{}/start", base_url)).await.unwrap();
+        assert_eq!(page.url.path(), "/page");
+        assert!(page.is_html());
+        assert!(page.text().contains("café"));
+
+        assert!(matches!(
+            fetcher.fetch(&format!("{}/large", base_url)).await,
+            Err(ScrapeError::TooLarge(_))
+        ));
+        assert!(matches!(
+            fetcher.fetch(&format!("{}/loop", base_url)).await,
+            Err(ScrapeError::TooManyRedirects(5))
+        ));
+        assert!(matches!(
+            fetcher.fetch(&format!("{}/missing", base_url)).await,
+            Err(ScrapeError::Status(StatusCode::NOT_FOUND))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_respects_robots_on_every_hop() {
+        let app = Router::new()
+            .route(
+                "/robots.txt",
+                get(|| async { "User-agent: *\nDisallow: /private\n\nUser-agent: friendly-bot\nAllow: /\n" }),
+            )
+            .route("/open", get(|| async { Redirect::temporary("/private/page") }))
+            .route("/private/page", get(|| async { "secret" }));
+        let base_url = serve(app).await;
+
+        let fetcher = Fetcher::new(local_options());
+        for path in ["/private/page", "/open"] {
+            match fetcher.fetch(&format!("{}{}", base_url, path)).await {
+                Err(ScrapeError::Disallowed { url, user_agent }) => {
+                    assert_eq!(url, format!("{}/private/page", base_url));
+                    assert_eq!(user_agent, DEFAULT_USER_AGENT);
+                }
+                other => panic!("expected robots.txt to block {}, got {:?}", path, other.map(|f| f.url)),
+            }
+        }
+
+        let friendly = Fetcher::new(FetchOptions {
+            user_agent: "friendly-bot/1.0".to_string(),
+            ..local_options()
+        });
+        assert_eq!(friendly.fetch(&format!("{}/open", base_url)).await.unwrap().body, b"secret");
+
+        let ignoring = Fetcher::new(FetchOptions {
+            respect_robots: false,
+            ..local_options()
+        });
+        assert!(ignoring.fetch(&format!("{}/private/page", base_url)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_blocks_internal_addresses_on_every_hop() {
+        let app = Router::new().route(
+            "/hop",
+            get(|axum::extract::Query(q): axum::extract::Query<std::collections::HashMap<String, String>>| async move {
+                Redirect::temporary(&q["to"])
+            }),
+        );
+        let base_url = serve(app).await;
+
+        match Fetcher::new(FetchOptions::default()).fetch(&format!("{}/hop", base_url)).await {
+            Err(ScrapeError::Blocked { reason, .. }) => assert!(reason.contains("127.0.0.1")),
+            other => panic!("expected loopback to be blocked, got {:?}", other.map(|f| f.url)),
+        }
+
+        let fetcher = Fetcher::new(local_options());
+        let port = base_url.rsplit(':').next().unwrap();
+        for target in [
+            format!("http://127.0.0.2:{}/", port),
+            "http://169.254.169.254/latest/meta-data/".to_string(),
+            "http://[::ffff:10.0.0.1]/".to_string(),
+        ] {
+            let url = format!("{}/hop?to={}", base_url, target);
+            assert!(
+                matches!(fetcher.fetch(&url).await, Err(ScrapeError::Blocked { .. })),
+                "redirect to {} should be blocked",
+                target
+            );
+        }
+        assert!(matches!(
+            fetcher.fetch(&format!("{}/hop?to=file:///etc/passwd", base_url)).await,
+            Err(ScrapeError::InvalidUrl(_))
+        ));
+    }
+}
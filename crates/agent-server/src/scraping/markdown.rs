@@ -0,0 +1,341 @@
+use ego_tree::NodeRef;
+use scraper::{ElementRef, Node};
+use url::Url;
+
+use super::readability::{collapse_whitespace, is_unlikely};
+
+/// Elements rendered as their own block rather than inline with their siblings.
+const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "blockquote", "dd", "details", "div", "dl", "dt", "figcaption",
+    "figure", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li", "main", "ol", "p",
+    "pre", "section", "summary", "table", "ul",
+];
+
+/// Renders the content under `root` as Markdown, resolving relative links
+/// against `base_url`.
+pub fn to_markdown(root: ElementRef, base_url: Option<&Url>) -> String {
+    Renderer { base_url, plain: false }.render(root)
+}
+
+/// Renders the content under `root` as plain text with paragraph breaks.
+pub fn to_text(root: ElementRef) -> String {
+    Renderer { base_url: None, plain: true }.render(root)
+}
+
+struct Renderer<'a> {
+    base_url: Option<&'a Url>,
+    plain: bool,
+}
+
+impl Renderer<'_> {
+    fn render(&self, root: ElementRef) -> String {
+        let mut blocks = Vec::new();
+        self.blocks(root, &mut blocks);
+        blocks.join("\n\n")
+    }
+
+    /// Renders the children of `element`, grouping inline runs into paragraphs.
+    fn blocks(&self, element: ElementRef, out: &mut Vec<String>) {
+        let mut paragraph = String::new();
+        for child in element.children() {
+            match ElementRef::wrap(child) {
+                Some(child_element) if BLOCK_TAGS.contains(&child_element.value().name()) => {
+                    flush(&mut paragraph, out);
+                    if !is_unlikely(child_element) {
+                        self.block(child_element, out);
+                    }
+                }
+                _ => self.inline(child, &mut paragraph),
+            }
+        }
+        flush(&mut paragraph, out);
+    }
+
+    fn block(&self, element: ElementRef, out: &mut Vec<String>) {
+        let tag = element.value().name();
+        match tag {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let text = self.inline_text(element);
+                if !text.is_empty() {
+                    let level = tag[1..].parse::<usize>().unwrap_or(1);
+                    out.push(match self.plain {
+                        true => text,
+                        false => format!("{} {}", "#".repeat(level), text),
+                    });
+                }
+            }
+            "p" | "dt" | "summary" | "figcaption" => {
+                let text = self.inline_text(element);
+                if !text.is_empty() {
+                    out.push(text);
+                }
+            }
+            "ul" | "ol" => {
+                let list = self.list(element, tag == "ol");
+                if !list.is_empty() {
+                    out.push(list);
+                }
+            }
+            "pre" => out.push(self.preformatted(element)),
+            "blockquote" => {
+                let mut inner = Vec::new();
+                self.blocks(element, &mut inner);
+                if !inner.is_empty() {
+                    let quote = inner.join("\n\n");
+                    out.push(match self.plain {
+                        true => quote,
+                        false => prefix_lines(&quote, "> ", "> "),
+                    });
+                }
+            }
+            "table" => {
+                let table = self.table(element);
+                if !table.is_empty() {
+                    out.push(table);
+                }
+            }
+            "hr" if !self.plain => out.push("---".to_string()),
+            "hr" => {}
+            _ => self.blocks(element, out),
+        }
+    }
+
+    fn list(&self, element: ElementRef, ordered: bool) -> String {
+        let items = element
+            .children()
+            .filter_map(ElementRef::wrap)
+            .filter(|child| child.value().name() == "li" && !is_unlikely(*child));
+
+        let mut lines = Vec::new();
+        for (index, item) in items.enumerate() {
+            let mut inner = Vec::new();
+            self.blocks(item, &mut inner);
+            if inner.is_empty() {
+                continue;
+            }
+            let marker = match ordered {
+                true => format!("{}. ", index + 1),
+                false => "- ".to_string(),
+            };
+            let indent = " ".repeat(marker.len());
+            lines.push(prefix_lines(&inner.join("\n"), &marker, &indent));
+        }
+        lines.join("\n")
+    }
+
+    fn preformatted(&self, element: ElementRef) -> String {
+        let code = element.text().collect::<String>();
+        let code = code.trim_matches('\n');
+        if self.plain {
+            return code.to_string();
+        }
+        let language = element
+            .select(&scraper::Selector::parse("code").expect("valid selector"))
+            .next()
+            .and_then(|code| code.value().classes().find_map(|c| c.strip_prefix("language-")))
+            .unwrap_or_default();
+        format!("```{}\n{}\n```", language, code)
+    }
+
+    fn table(&self, element: ElementRef) -> String {
+        let rows: Vec<Vec<String>> = element
+            .descendants()
+            .filter_map(ElementRef::wrap)
+            .filter(|row| row.value().name() == "tr")
+            // rows of nested tables are rendered with their own table
+            .filter(|row| {
+                row.ancestors()
+                    .filter_map(ElementRef::wrap)
+                    .find(|a| a.value().name() == "table")
+                    .is_some_and(|table| table.id() == element.id())
+            })
+            .map(|row| {
+                row.children()
+                    .filter_map(ElementRef::wrap)
+                    .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+                    .map(|cell| self.inline_text(cell).replace("  \n", " ").replace('|', "\\|"))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|cells| !cells.is_empty())
+            .collect();
+
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return String::new();
+        }
+        if self.plain {
+            return rows.iter().map(|row| row.join("\t")).collect::<Vec<_>>().join("\n");
+        }
+
+        let line = |cells: &[String]| {
+            let mut cells = cells.to_vec();
+            cells.resize(columns, String::new());
+            format!("| {} |", cells.join(" | "))
+        };
+        let mut lines = vec![line(&rows[0]), line(&vec!["---".to_string(); columns])];
+        lines.extend(rows[1..].iter().map(|row| line(row)));
+        lines.join("\n")
+    }
+
+    /// Renders the children of `element` as a single line of inline content.
+    fn inline_text(&self, element: ElementRef) -> String {
+        let mut text = String::new();
+        for child in element.children() {
+            self.inline(child, &mut text);
+        }
+        finish_inline(&text)
+    }
+
+    fn inline(&self, node: NodeRef<Node>, out: &mut String) {
+        let element = match node.value() {
+            Node::Text(text) => {
+                push_text(out, text);
+                return;
+            }
+            Node::Element(_) => ElementRef::wrap(node).expect("element node"),
+            _ => return,
+        };
+        if is_unlikely(element) {
+            return;
+        }
+
+        match element.value().name() {
+            "br" => out.push('\n'),
+            "a" if !self.plain => {
+                let text = self.inline_text(element);
+                match element.value().attr("href").and_then(|href| self.resolve(href)) {
+                    Some(url) if !text.is_empty() => out.push_str(&format!("[{}]({})", text, url)),
+                    _ => out.push_str(&text),
+                }
+            }
+            "img" if !self.plain => {
+                if let Some(src) = element.value().attr("src").and_then(|src| self.resolve(src)) {
+                    let alt = collapse_whitespace(element.value().attr("alt").unwrap_or_default());
+                    out.push_str(&format!("![{}]({})", alt, src));
+                }
+            }
+            "strong" | "b" if !self.plain => wrap(out, &self.inline_text(element), "**"),
+            "em" | "i" if !self.plain => wrap(out, &self.inline_text(element), "*"),
+            "del" | "s" if !self.plain => wrap(out, &self.inline_text(element), "~~"),
+            "code" if !self.plain => {
+                wrap(out, &collapse_whitespace(&element.text().collect::<String>()), "`")
+            }
+            _ => {
+                for child in element.children() {
+                    self.inline(child, out);
+                }
+            }
+        }
+    }
+
+    /// Resolves a link target to an absolute http(s) url, dropping fragments and scripts.
+    fn resolve(&self, href: &str) -> Option<String> {
+        let href = href.trim();
+        if href.is_empty() || href.starts_with('#') {
+            return None;
+        }
+        let url = match self.base_url {
+            Some(base) => base.join(href).ok()?,
+            None => Url::parse(href).ok()?,
+        };
+        matches!(url.scheme(), "http" | "https").then(|| url.to_string())
+    }
+}
+
+/// Appends text-node content, keeping a single space where the source had whitespace.
+fn push_text(out: &mut String, text: &str) {
+    let words = collapse_whitespace(text);
+    if text.starts_with(char::is_whitespace) {
+        out.push(' ');
+    }
+    out.push_str(&words);
+    if text.ends_with(char::is_whitespace) && !words.is_empty() {
+        out.push(' ');
+    }
+}
+
+fn wrap(out: &mut String, text: &str, marker: &str) {
+    if text.is_empty() {
+        return;
+    }
+    out.push_str(marker);
+    out.push_str(text);
+    out.push_str(marker);
+}
+
+/// Tidies an inline run: collapses repeated spaces and keeps `<br>` as hard breaks.
+fn finish_inline(text: &str) -> String {
+    text.split('\n')
+        .map(collapse_whitespace)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("  \n")
+}
+
+fn flush(paragraph: &mut String, out: &mut Vec<String>) {
+    let text = finish_inline(paragraph);
+    if !text.is_empty() {
+        out.push(text);
+    }
+    paragraph.clear();
+}
+
+fn prefix_lines(text: &str, first: &str, rest: &str) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| match (i, line.is_empty()) {
+            (0, _) => format!("{}{}", first, line),
+            (_, true) => rest.trim_end().to_string(),
+            _ => format!("{}{}", rest, line),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scraper::Html;
+
+    fn markdown(html: &str) -> String {
+        let document = Html::parse_fragment(html);
+        let base = Url::parse("https://example.com/docs/page").unwrap();
+        to_markdown(document.root_element(), Some(&base))
+    }
+
+    #[test]
+    fn test_inline_formatting_and_links() {
+        assert_eq!(
+            markdown(r##"<p>Read  the <a href="../guide">guide</a>, <strong>now</strong> or <a href="#top">later</a>.<br>Thanks <code>x  y</code></p>"##),
+            "Read the [guide](https://example.com/guide), **now** or later.  \nThanks `x y`"
+        );
+        assert_eq!(
+            markdown(r#"<p><a href="javascript:void(0)">Menu</a> <img src="/a.png" alt="A chart"></p>"#),
+            "Menu ![A chart](https://example.com/a.png)"
+        );
+    }
+
+    #[test]
+    fn test_nested_lists() {
+        assert_eq!(
+            markdown("<ul><li>One</li><li>Two<ol><li>a</li><li>b</li></ol></li></ul>"),
+            "- One\n- Two\n  1. a\n  2. b"
+        );
+    }
+
+    #[test]
+    fn test_tables_are_padded_and_escaped() {
+        assert_eq!(
+            markdown("<table><tr><th>A</th><th>B</th></tr><tr><td>1 | 2</td></tr></table>"),
+            "| A | B |\n| --- | --- |\n| 1 \\| 2 |  |"
+        );
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let document = Html::parse_fragment(
+            r#"<h2>Title</h2><p>Some <a href="/x">linked</a> <em>text</em>.</p><blockquote><p>Quoted</p></blockquote>"#,
+        );
+        assert_eq!(to_text(document.root_element()), "Title\n\nSome linked text.\n\nQuoted");
+    }
+}
@@ -0,0 +1,136 @@
+pub mod document;
+pub mod fetch;
+pub mod markdown;
+pub mod politeness;
+pub mod readability;
+pub mod robots;
+pub mod ssrf;
+
+use std::sync::OnceLock;
+
+use scraper::{Html, Selector};
+use url::Url;
+
+pub use fetch::{FetchOptions, Fetched, Fetcher, ScrapeError};
+
+/// The process-wide fetcher, so the robots.txt cache and per-host limits are
+/// shared by every scrape.
+pub fn fetcher() -> Fetcher {
+    static FETCHER: OnceLock<Fetcher> = OnceLock::new();
+    FETCHER.get_or_init(Fetcher::from_env).clone()
+}
+
+/// The main content of an HTML page in each output format the scrape tool offers.
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub title: Option<String>,
+    pub markdown: String,
+    pub text: String,
+    pub html: String,
+    /// Every http(s) link in the document, including navigation outside the main content.
+    pub links: Vec<Url>,
+}
+
+/// Extracts the readable content of `html`, resolving links against `base_url`.
+pub fn extract(html: &str, base_url: &Url) -> Page {
+    let document = Html::parse_document(html);
+    let content = readability::main_content(&document);
+    Page {
+        title: readability::title(&document),
+        markdown: markdown::to_markdown(content, Some(base_url)),
+        text: markdown::to_text(content),
+        html: content.html(),
+        links: links(&document, base_url),
+    }
+}
+
+/// Absolute http(s) links in `document`, without fragments or duplicates, in document order.
+pub fn links(document: &Html, base_url: &Url) -> Vec<Url> {
+    let anchors = Selector::parse("a[href]").expect("valid selector");
+    let mut links: Vec<Url> = Vec::new();
+    for href in document.select(&anchors).filter_map(|a| a.value().attr("href")) {
+        let Ok(mut url) = base_url.join(href.trim()) else {
+            continue;
+        };
+        if !matches!(url.scheme(), "http" | "https") {
+            continue;
+        }
+        url.set_fragment(None);
+        if !links.contains(&url) {
+            links.push(url);
+        }
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARTICLE: &str = include_str!("../../fixtures/html/article.html");
+    const BLOG: &str = include_str!("../../fixtures/html/blog.html");
+
+    #[test]
+    fn test_extract_article_fixture() {
+        let base = Url::parse("https://news.example.com/tech/rust-1-80").unwrap();
+        let page = extract(ARTICLE, &base);
+        assert_eq!(page.title.as_deref(), Some("Rust 1.80 Released"));
+
+        let expected = "\
+# Rust 1.80 Released
+
+By [Ferris](https://news.example.com/authors/ferris)
+
+The Rust team has published version 1.80 of the language, bringing **lazy cells** to the standard library and a new set of [LazyCell](https://doc.rust-lang.org/std/cell/struct.LazyCell.html) and [LazyLock](https://news.example.com/docs/lazylock) types.
+
+Exclusive ranges in patterns are now stable, which makes matching on numeric ranges considerably more pleasant, and the release also checks cfg names and values at compile time.
+
+## Highlights
+
+- Lazy initialization types
+- Exclusive range patterns
+  1. in `match` arms
+  2. in *let* statements
+
+| Version | Date | Notes |
+| --- | --- | --- |
+| 1.79 | 2024-06-13 | Inline const |
+| 1.80 | 2024-07-25 | Lazy cells \\| ranges |
+
+```rust
+use std::sync::LazyLock;
+
+static CONFIG: LazyLock<String> = LazyLock::new(|| load());
+```
+
+> Upgrade with `rustup update stable`.";
+        assert_eq!(page.markdown, expected);
+
+        assert!(page.text.starts_with("Rust 1.80 Released\n\nBy Ferris\n\n"));
+        assert!(!page.text.contains("]("));
+        assert!(page.html.starts_with("<article class=\"story\">"));
+    }
+
+    #[test]
+    fn test_extract_div_layout_fixture() {
+        let base = Url::parse("https://blog.example.com/posts/async/").unwrap();
+        let page = extract(BLOG, &base);
+        assert_eq!(page.title.as_deref(), Some("Notes on async runtimes"));
+        assert!(page.markdown.starts_with("Async runtimes schedule futures"));
+        assert!(page.markdown.ends_with(
+            "See the [tutorial](https://blog.example.com/posts/tokio/tutorial) for details.  \nUpdated weekly."
+        ));
+        assert!(!page.markdown.contains("Subscribe"));
+
+        let links: Vec<&str> = page.links.iter().map(Url::as_str).collect();
+        assert_eq!(
+            links,
+            [
+                "https://blog.example.com/",
+                "https://blog.example.com/about",
+                "https://blog.example.com/archive",
+                "https://blog.example.com/posts/tokio/tutorial",
+            ]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+use url::Url;
+
+/// Longest crawl-delay honoured; larger values are clamped so a single
+/// tool call cannot stall for minutes.
+pub const MAX_CRAWL_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+struct HostSlot {
+    permits: Arc<Semaphore>,
+    next_request: Mutex<Instant>,
+}
+
+/// Limits concurrent requests per host and spaces them by the host's crawl-delay.
+#[derive(Debug)]
+pub struct HostLimiter {
+    max_per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<HostSlot>>>,
+}
+
+impl HostLimiter {
+    pub fn new(max_per_host: usize) -> Self {
+        Self {
+            max_per_host: max_per_host.max(1),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits for a free request slot on the host of `url`, then for
+    /// `crawl_delay` to pass since the previous request there started.
+    /// The slot is held until the returned permit is dropped.
+    pub async fn acquire(&self, url: &Url, crawl_delay: Option<Duration>) -> OwnedSemaphorePermit {
+        let slot = self
+            .hosts
+            .lock()
+            .await
+            .entry(url.host_str().unwrap_or_default().to_lowercase())
+            .or_insert_with(|| {
+                Arc::new(HostSlot {
+                    permits: Arc::new(Semaphore::new(self.max_per_host)),
+                    next_request: Mutex::new(Instant::now()),
+                })
+            })
+            .clone();
+
+        let permit = slot
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("host semaphore is never closed");
+
+        if let Some(delay) = crawl_delay.filter(|d| !d.is_zero()) {
+            let mut next_request = slot.next_request.lock().await;
+            tokio::time::sleep_until(*next_request).await;
+            *next_request = Instant::now() + delay.min(MAX_CRAWL_DELAY);
+        }
+        permit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_crawl_delay_spaces_requests_per_host() {
+        let limiter = HostLimiter::new(4);
+        let delay = Some(Duration::from_millis(200));
+
+        let start = Instant::now();
+        drop(limiter.acquire(&url("https://a.example/1"), delay).await);
+        drop(limiter.acquire(&url("https://a.example/2"), delay).await);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        // other hosts are not delayed
+        let start = Instant::now();
+        drop(limiter.acquire(&url("https://b.example/1"), delay).await);
+        assert!(start.elapsed() < Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_per_host() {
+        let limiter = HostLimiter::new(2);
+        let first = limiter.acquire(&url("https://a.example/1"), None).await;
+        let _second = limiter.acquire(&url("https://a.example/2"), None).await;
+
+        let third_url = url("https://a.example/3");
+        let third = limiter.acquire(&third_url, None);
+        tokio::pin!(third);
+        assert!(tokio::time::timeout(Duration::from_millis(20), &mut third).await.is_err());
+
+        // a different host has its own slots
+        let _other = limiter.acquire(&url("https://b.example/1"), None).await;
+
+        drop(first);
+        let _third = tokio::time::timeout(Duration::from_millis(100), third).await.unwrap();
+    }
+}
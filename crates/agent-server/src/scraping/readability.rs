@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use scraper::{ElementRef, Html, Selector};
+
+/// Elements that never carry article content.
+const SKIP_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "iframe", "svg", "canvas", "form", "button",
+    "input", "select", "textarea", "nav", "footer", "aside",
+];
+/// Class or id fragments of page chrome such as menus, sidebars and comment threads.
+const UNLIKELY_HINTS: &[&str] = &[
+    "nav", "menu", "footer", "header", "sidebar", "comment", "share", "social", "related",
+    "promo", "advert", "banner", "cookie", "newsletter", "subscribe", "popup", "breadcrumb",
+    "pagination", "masthead", "widget",
+];
+/// Class or id fragments that mark an element as content despite an unlikely hint.
+const POSITIVE_HINTS: &[&str] = &["article", "content", "entry", "main", "post", "story", "body", "text"];
+/// Containers that are never dropped for their class names alone.
+const STRUCTURAL_TAGS: &[&str] = &["html", "body", "article", "main"];
+/// Minimum paragraph length, in characters, for it to count towards a container.
+const MIN_PARAGRAPH_CHARS: usize = 25;
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).expect("valid selector")
+}
+
+/// Whether `element` is page chrome that should be left out of the extracted content.
+pub fn is_unlikely(element: ElementRef) -> bool {
+    let tag = element.value().name();
+    if SKIP_TAGS.contains(&tag) || element.value().attr("aria-hidden") == Some("true") {
+        return true;
+    }
+    if STRUCTURAL_TAGS.contains(&tag) {
+        return false;
+    }
+    let hints = format!(
+        "{} {}",
+        element.value().attr("class").unwrap_or_default(),
+        element.value().id().unwrap_or_default()
+    )
+    .to_lowercase();
+    UNLIKELY_HINTS.iter().any(|hint| hints.contains(hint))
+        && !POSITIVE_HINTS.iter().any(|hint| hints.contains(hint))
+}
+
+fn has_unlikely_ancestor(element: ElementRef) -> bool {
+    element
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .any(is_unlikely)
+}
+
+/// The document title, preferring `og:title` over `<title>` and the first `<h1>`.
+pub fn title(document: &Html) -> Option<String> {
+    let og_title = document
+        .select(&selector(r#"meta[property="og:title"]"#))
+        .filter_map(|meta| meta.value().attr("content"))
+        .map(collapse_whitespace)
+        .find(|t| !t.is_empty());
+    og_title.or_else(|| {
+        ["title", "h1"].iter().find_map(|css| {
+            document
+                .select(&selector(css))
+                .map(|el| collapse_whitespace(&el.text().collect::<String>()))
+                .find(|t| !t.is_empty())
+        })
+    })
+}
+
+/// Picks the element holding the main content of `document`.
+///
+/// Paragraphs score their parent and, at half weight, their grandparent by
+/// length and comma count; the best container after discounting link-heavy
+/// ones wins. An enclosing `<article>` or `<main>` is preferred so headings
+/// and tables next to the paragraphs are kept.
+pub fn main_content(document: &Html) -> ElementRef<'_> {
+    let mut scores: HashMap<_, f64> = HashMap::new();
+
+    for paragraph in document.select(&selector("p, pre, td")) {
+        if has_unlikely_ancestor(paragraph) {
+            continue;
+        }
+        let text = collapse_whitespace(&paragraph.text().collect::<String>());
+        let chars = text.chars().count();
+        if chars < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f64 + (chars as f64 / 100.0).min(3.0);
+
+        let mut ancestors = paragraph.ancestors().filter_map(ElementRef::wrap);
+        if let Some(parent) = ancestors.next() {
+            *scores.entry(parent.id()).or_default() += score;
+        }
+        if let Some(grandparent) = ancestors.next() {
+            *scores.entry(grandparent.id()).or_default() += score / 2.0;
+        }
+    }
+
+    let best = scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let element = document.tree.get(id).and_then(ElementRef::wrap)?;
+            Some((element, score * (1.0 - link_density(element))))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(element, _)| element);
+
+    match best {
+        Some(element) => semantic_container(element).unwrap_or(element),
+        None => ["article", "main", "[role=main]", "body"]
+            .iter()
+            .find_map(|css| document.select(&selector(css)).next())
+            .unwrap_or_else(|| document.root_element()),
+    }
+}
+
+/// The closest `<article>`, `<main>` or `role="main"` element around `element`, if any.
+fn semantic_container(element: ElementRef) -> Option<ElementRef> {
+    std::iter::once(element)
+        .chain(element.ancestors().filter_map(ElementRef::wrap))
+        .take(4)
+        .find(|el| {
+            matches!(el.value().name(), "article" | "main") || el.value().attr("role") == Some("main")
+        })
+}
+
+/// Share of the element's text that sits inside links.
+fn link_density(element: ElementRef) -> f64 {
+    let total = text_len(element);
+    if total == 0 {
+        return 0.0;
+    }
+    let linked: usize = element.select(&selector("a")).map(text_len).sum();
+    linked as f64 / total as f64
+}
+
+fn text_len(element: ElementRef) -> usize {
+    element.text().map(|t| t.trim().chars().count()).sum()
+}
+
+pub(crate) fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARTICLE: &str = include_str!("../../fixtures/html/article.html");
+    const BLOG: &str = include_str!("../../fixtures/html/blog.html");
+
+    #[test]
+    fn test_title_prefers_open_graph() {
+        assert_eq!(title(&Html::parse_document(ARTICLE)).as_deref(), Some("Rust 1.80 Released"));
+        assert_eq!(
+            title(&Html::parse_document(BLOG)).as_deref(),
+            Some("Notes on async runtimes")
+        );
+    }
+
+    #[test]
+    fn test_main_content_prefers_enclosing_article() {
+        let document = Html::parse_document(ARTICLE);
+        let content = main_content(&document);
+        assert_eq!(content.value().name(), "article");
+
+        let text = content.text().collect::<String>();
+        assert!(text.contains("Exclusive ranges in patterns"));
+        assert!(!text.contains("Most read"));
+        assert!(!text.contains("First! This comment"));
+    }
+
+    #[test]
+    fn test_main_content_scores_div_layouts() {
+        let document = Html::parse_document(BLOG);
+        let content = main_content(&document);
+        assert_eq!(content.value().attr("class"), Some("entry-text"));
+    }
+
+    #[test]
+    fn test_unlikely_elements() {
+        let document = Html::parse_fragment(
+            r#"<div class="sidebar-widget">a</div><div class="post-content comments-open">b</div>"#,
+        );
+        let divs: Vec<_> = document.select(&selector("div")).collect();
+        assert!(is_unlikely(divs[0]));
+        assert!(!is_unlikely(divs[1]));
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+use url::Url;
+
+/// How long a fetched robots.txt is trusted before it is requested again.
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Robots files are only parsed up to this size, as RFC 9309 allows.
+const MAX_ROBOTS_BYTES: usize = 512 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+/// A parsed robots.txt file.
+#[derive(Debug, Clone, Default)]
+pub struct Robots {
+    groups: Vec<Group>,
+}
+
+/// The rules of a robots.txt file that apply to one user agent.
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+    pub crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    /// A robots.txt that allows everything, used when a host has none.
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// A robots.txt that disallows everything, used when a host's is unreachable.
+    pub fn disallow_all() -> Self {
+        Self {
+            groups: vec![Group {
+                agents: vec!["*".to_string()],
+                rules: vec![Rule {
+                    allow: false,
+                    pattern: "/".to_string(),
+                }],
+                crawl_delay: None,
+            }],
+        }
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        // consecutive user-agent lines share one group
+        let mut in_agent_lines = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().to_lowercase();
+            let value = value.trim();
+
+            if key == "user-agent" {
+                if !in_agent_lines {
+                    groups.push(Group::default());
+                }
+                in_agent_lines = true;
+                if let Some(group) = groups.last_mut() {
+                    group.agents.push(value.to_lowercase());
+                }
+                continue;
+            }
+
+            in_agent_lines = false;
+            let Some(group) = groups.last_mut() else {
+                continue;
+            };
+            match key.as_str() {
+                "allow" | "disallow" if !value.is_empty() => group.rules.push(Rule {
+                    allow: key == "allow",
+                    pattern: value.to_string(),
+                }),
+                "crawl-delay" => {
+                    group.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|d| d.is_finite() && *d >= 0.0)
+                        .map(Duration::from_secs_f64);
+                }
+                _ => {}
+            }
+        }
+
+        Self { groups }
+    }
+
+    /// Rules for `user_agent`, merging every group naming its product token,
+    /// or the `*` groups when none do. As RFC 9309 requires, a group only
+    /// applies when it names the whole product token, ignoring case.
+    pub fn rules_for(&self, user_agent: &str) -> Rules {
+        let product = user_agent
+            .trim()
+            .split(|c: char| c == '/' || c.is_whitespace())
+            .next()
+            .unwrap_or_default();
+
+        let named: Vec<&Group> = self
+            .groups
+            .iter()
+            .filter(|g| g.agents.iter().any(|a| a != "*" && !product.is_empty() && a.eq_ignore_ascii_case(product)))
+            .collect();
+        let groups = match named.is_empty() {
+            true => self.groups.iter().filter(|g| g.agents.iter().any(|a| a == "*")).collect(),
+            false => named,
+        };
+
+        Rules {
+            rules: groups.iter().flat_map(|g| g.rules.iter().cloned()).collect(),
+            crawl_delay: groups.iter().filter_map(|g| g.crawl_delay).max(),
+        }
+    }
+}
+
+impl Rules {
+    /// Whether `url` may be fetched: the longest matching rule wins and
+    /// `Allow` wins ties.
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        if url.path() == "/robots.txt" {
+            return true;
+        }
+        let target = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+
+        self.rules
+            .iter()
+            .filter(|rule| pattern_matches(&rule.pattern, &target))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+/// Matches a robots path pattern, supporting `*` wildcards and a trailing `$` anchor.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        let is_last = i == parts.len() - 1;
+        if is_last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// Per-host cache of robots.txt files.
+#[derive(Debug, Default)]
+pub struct RobotsCache {
+    entries: Mutex<HashMap<String, (Instant, Arc<Robots>)>>,
+}
+
+impl RobotsCache {
+    /// Returns the robots.txt for the origin of `url`, fetching it when it is
+    /// missing or stale.
+    pub async fn get(&self, http: &reqwest::Client, url: &Url) -> Arc<Robots> {
+        let origin = url.origin().ascii_serialization();
+        if let Some((fetched_at, robots)) = self.entries.lock().await.get(&origin) {
+            if fetched_at.elapsed() < CACHE_TTL {
+                return robots.clone();
+            }
+        }
+
+        let robots = Arc::new(fetch_robots(http, &origin).await);
+        self.entries
+            .lock()
+            .await
+            .insert(origin, (Instant::now(), robots.clone()));
+        robots
+    }
+}
+
+/// Fetches `{origin}/robots.txt`. Missing files allow everything; server
+/// errors and unreachable hosts disallow everything, as RFC 9309 asks.
+async fn fetch_robots(http: &reqwest::Client, origin: &str) -> Robots {
+    let response = match http.get(format!("{}/robots.txt", origin)).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!("Failed to fetch robots.txt for {}: {}", origin, e);
+            return Robots::disallow_all();
+        }
+    };
+
+    let status = response.status();
+    if status.is_client_error() {
+        return Robots::allow_all();
+    }
+    if !status.is_success() {
+        tracing::warn!("robots.txt for {} responded with {}", origin, status);
+        return Robots::disallow_all();
+    }
+
+    let mut response = response;
+    let mut body = Vec::new();
+    while let Ok(Some(chunk)) = response.chunk().await {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_ROBOTS_BYTES {
+            body.truncate(MAX_ROBOTS_BYTES);
+            break;
+        }
+    }
+    Robots::parse(&String::from_utf8_lossy(&body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "\
+# example robots.txt
+User-agent: *
+Disallow: /private/
+Allow: /private/public-*.html$
+Crawl-delay: 2
+
+User-agent: open-web-agent-rs
+User-agent: OtherBot
+Disallow: /search
+Disallow: /*.pdf$
+Allow: /search/about
+Crawl-delay: 0.5
+
+Sitemap: https://example.com/sitemap.xml
+";
+
+    fn url(path: &str) -> Url {
+        Url::parse("https://example.com").unwrap().join(path).unwrap()
+    }
+
+    #[test]
+    fn test_named_group_wins_over_wildcard() {
+        let robots = Robots::parse(ROBOTS);
+
+        let ours = robots.rules_for("open-web-agent-rs/0.1.0");
+        assert_eq!(ours.crawl_delay, Some(Duration::from_millis(500)));
+        assert!(ours.is_allowed(&url("/private/page")));
+        assert!(!ours.is_allowed(&url("/search?q=rust")));
+        assert!(ours.is_allowed(&url("/search/about")));
+        assert!(!ours.is_allowed(&url("/docs/report.pdf")));
+        assert!(ours.is_allowed(&url("/docs/report.pdf?download=1")));
+
+        let others = robots.rules_for("SomeCrawler/2.0");
+        assert_eq!(others.crawl_delay, Some(Duration::from_secs(2)));
+        assert!(!others.is_allowed(&url("/private/page")));
+        assert!(others.is_allowed(&url("/private/public-notes.html")));
+        assert!(!others.is_allowed(&url("/private/public-notes.html.bak")));
+        assert!(others.is_allowed(&url("/search")));
+    }
+
+    #[test]
+    fn test_groups_must_name_the_whole_product_token() {
+        let robots = Robots::parse("User-agent: rs\nUser-agent: agent\nDisallow: /\n\nUser-agent: *\nAllow: /\n");
+        assert!(robots.rules_for("open-web-agent-rs/0.1.0").is_allowed(&url("/page")));
+        assert!(!robots.rules_for("Agent/1.0").is_allowed(&url("/page")));
+        assert!(!robots.rules_for("RS").is_allowed(&url("/page")));
+    }
+
+    #[test]
+    fn test_fallbacks() {
+        assert!(Robots::allow_all().rules_for("any").is_allowed(&url("/anything")));
+
+        let blocked = Robots::disallow_all().rules_for("any");
+        assert!(!blocked.is_allowed(&url("/")));
+        assert!(blocked.is_allowed(&url("/robots.txt")));
+    }
+
+    #[test]
+    fn test_pattern_matching() {
+        assert!(pattern_matches("/", "/a"));
+        assert!(pattern_matches("/a*c", "/abbbc/d"));
+        assert!(pattern_matches("/a*c$", "/abbbc"));
+        assert!(!pattern_matches("/a*c$", "/abbbc/d"));
+        assert!(pattern_matches("/exact$", "/exact"));
+        assert!(!pattern_matches("/exact$", "/exactly"));
+        assert!(!pattern_matches("/b", "/a/b"));
+    }
+}
@@ -0,0 +1,284 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, LazyLock};
+
+use ipnet::IpNet;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use url::{Host, Url};
+
+/// Address ranges that scraped urls may never reach: private networks,
+/// loopback, link-local (including the 169.254.169.254 cloud metadata
+/// service), carrier-grade NAT, multicast and reserved space.
+static BLOCKED_RANGES: LazyLock<Vec<IpNet>> = LazyLock::new(|| {
+    [
+        "0.0.0.0/8",
+        "10.0.0.0/8",
+        "100.64.0.0/10",
+        "127.0.0.0/8",
+        "169.254.0.0/16",
+        "172.16.0.0/12",
+        "192.0.0.0/24",
+        "192.0.2.0/24",
+        "192.168.0.0/16",
+        "198.18.0.0/15",
+        "198.51.100.0/24",
+        "203.0.113.0/24",
+        "224.0.0.0/4",
+        "240.0.0.0/4",
+        // Azure's wire server answers metadata-style requests outside link-local space
+        "168.63.129.16/32",
+        "::/128",
+        "::1/128",
+        "2001:db8::/32",
+        "fc00::/7",
+        "fe80::/10",
+        "fec0::/10",
+        "ff00::/8",
+    ]
+    .iter()
+    .map(|net| net.parse().expect("valid blocked range"))
+    .collect()
+});
+
+/// Decides which hosts and addresses the scraper may connect to.
+#[derive(Debug, Clone, Default)]
+pub struct UrlPolicy {
+    allowed_nets: Vec<IpNet>,
+    allowed_hosts: Vec<String>,
+}
+
+impl UrlPolicy {
+    /// Builds a policy whose allowlist holds `entries`: CIDR ranges, single
+    /// addresses, or host names (a leading `.` matches every subdomain).
+    pub fn with_allowlist<'a>(entries: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut policy = Self::default();
+        for entry in entries.into_iter().map(str::trim).filter(|e| !e.is_empty()) {
+            if let Ok(net) = entry.parse::<IpNet>() {
+                policy.allowed_nets.push(net);
+            } else if let Ok(ip) = entry.parse::<IpAddr>() {
+                policy.allowed_nets.push(IpNet::from(ip));
+            } else {
+                policy.allowed_hosts.push(entry.to_lowercase());
+            }
+        }
+        policy
+    }
+
+    /// Reads the comma-separated `SCRAPE_ALLOWLIST`.
+    pub fn from_env() -> Self {
+        let allowlist = std::env::var("SCRAPE_ALLOWLIST").unwrap_or_default();
+        Self::with_allowlist(allowlist.split(','))
+    }
+
+    fn host_allowed(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_lowercase();
+        self.allowed_hosts.iter().any(|allowed| match allowed.strip_prefix('.') {
+            Some(domain) => host == domain || host.ends_with(allowed.as_str()),
+            None => host == *allowed,
+        })
+    }
+
+    /// Whether connecting to `ip` is permitted. IPv6 addresses embedding an
+    /// IPv4 one are checked in both forms.
+    pub fn ip_allowed(&self, ip: IpAddr) -> bool {
+        let canonical = canonical_ip(ip);
+        let allowed = |ip: &IpAddr| self.allowed_nets.iter().any(|net| net.contains(ip));
+        let blocked = |ip: &IpAddr| BLOCKED_RANGES.iter().any(|net| net.contains(ip));
+        allowed(&ip) || allowed(&canonical) || !(blocked(&ip) || blocked(&canonical))
+    }
+
+    /// Resolves the host of `url` and fails unless every address it resolves
+    /// to is permitted.
+    pub async fn check(&self, url: &Url) -> Result<(), String> {
+        let host = match url.host() {
+            Some(Host::Domain(domain)) if self.host_allowed(domain) => return Ok(()),
+            Some(Host::Domain(domain)) => domain.to_string(),
+            Some(Host::Ipv4(ip)) => return self.check_ip(IpAddr::V4(ip)),
+            Some(Host::Ipv6(ip)) => return self.check_ip(IpAddr::V6(ip)),
+            None => return Err("url has no host".to_string()),
+        };
+
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addrs = tokio::net::lookup_host((host.as_str(), port))
+            .await
+            .map_err(|e| format!("could not resolve {}: {}", host, e))?;
+        let mut resolved = false;
+        for addr in addrs {
+            self.check_ip(addr.ip())?;
+            resolved = true;
+        }
+        match resolved {
+            true => Ok(()),
+            false => Err(format!("{} did not resolve to any address", host)),
+        }
+    }
+
+    fn check_ip(&self, ip: IpAddr) -> Result<(), String> {
+        match self.ip_allowed(ip) {
+            true => Ok(()),
+            false => Err(format!("{} is a private or reserved address", ip)),
+        }
+    }
+
+    /// Whether a redirect to `url` can be followed without resolving it:
+    /// literal addresses must be permitted, names are checked by [`GuardedResolver`].
+    pub fn literal_allowed(&self, url: &Url) -> bool {
+        match url.host() {
+            Some(Host::Ipv4(ip)) => self.ip_allowed(IpAddr::V4(ip)),
+            Some(Host::Ipv6(ip)) => self.ip_allowed(IpAddr::V6(ip)),
+            Some(Host::Domain(_)) => true,
+            None => false,
+        }
+    }
+}
+
+/// Unwraps IPv4 addresses embedded in IPv6 (mapped, compatible, NAT64 and
+/// 6to4) so they are checked against the IPv4 ranges.
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    let IpAddr::V6(v6) = ip else {
+        return ip;
+    };
+    // `::ffff:a.b.c.d` and the deprecated `::a.b.c.d`
+    if let Some(v4) = v6.to_ipv4() {
+        return IpAddr::V4(v4);
+    }
+    let v4 = |high: u16, low: u16| {
+        let [a, b] = high.to_be_bytes();
+        let [c, d] = low.to_be_bytes();
+        IpAddr::V4([a, b, c, d].into())
+    };
+    let segments = v6.segments();
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        return v4(segments[6], segments[7]);
+    }
+    // 6to4 carries the IPv4 address right after the 2002::/16 prefix
+    if segments[0] == 0x2002 {
+        return v4(segments[1], segments[2]);
+    }
+    ip
+}
+
+/// DNS resolver that drops blocked addresses at connect time, so a host
+/// cannot pass [`UrlPolicy::check`] and then rebind to an internal address.
+#[derive(Debug, Clone)]
+pub struct GuardedResolver {
+    policy: Arc<UrlPolicy>,
+}
+
+impl GuardedResolver {
+    pub fn new(policy: Arc<UrlPolicy>) -> Self {
+        Self { policy }
+    }
+}
+
+impl Resolve for GuardedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.policy.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let resolved: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if policy.host_allowed(&host) {
+                return Ok(Box::new(resolved.into_iter()) as Addrs);
+            }
+            let allowed: Vec<SocketAddr> = resolved.into_iter().filter(|a| policy.ip_allowed(a.ip())).collect();
+            if allowed.is_empty() {
+                return Err(format!("{} resolves only to blocked addresses", host).into());
+            }
+            Ok(Box::new(allowed.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_blocks_internal_ranges() {
+        let policy = UrlPolicy::default();
+        for blocked in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.20.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "0.0.0.0",
+            "::1",
+            "fe80::1",
+            "fd00:ec2::254",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!policy.ip_allowed(ip(blocked)), "{} should be blocked", blocked);
+        }
+        for allowed in ["93.184.215.14", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(policy.ip_allowed(ip(allowed)), "{} should be allowed", allowed);
+        }
+    }
+
+    #[test]
+    fn test_blocks_ipv4_compatible_and_6to4_forms() {
+        let policy = UrlPolicy::default();
+        for blocked in [
+            // IPv4-compatible loopback, link-local and metadata addresses
+            "::127.0.0.1",
+            "::169.254.1.1",
+            "::a9fe:a9fe",
+            // 6to4 addresses embedding the same
+            "2002:7f00:1::",
+            "2002:a9fe:101::1",
+            "2002:a9fe:a9fe::",
+            "2002:c0a8:101::1",
+        ] {
+            assert!(!policy.ip_allowed(ip(blocked)), "{} should be blocked", blocked);
+        }
+        // 6to4 for a public address
+        assert!(policy.ip_allowed(ip("2002:5db8:d70e::1")));
+    }
+
+    #[test]
+    fn test_allowlist() {
+        let policy = UrlPolicy::with_allowlist("10.0.0.0/8, 127.0.0.1 ,intranet.example,.corp.example".split(','));
+        assert!(policy.ip_allowed(ip("10.9.9.9")));
+        assert!(policy.ip_allowed(ip("127.0.0.1")));
+        assert!(!policy.ip_allowed(ip("127.0.0.2")));
+        assert!(!policy.ip_allowed(ip("192.168.0.1")));
+
+        assert!(policy.host_allowed("intranet.example"));
+        assert!(policy.host_allowed("wiki.corp.example"));
+        assert!(policy.host_allowed("corp.example."));
+        assert!(!policy.host_allowed("notcorp.example"));
+        assert!(!policy.host_allowed("intranet.example.evil"));
+    }
+
+    #[tokio::test]
+    async fn test_check_resolves_names() {
+        let policy = UrlPolicy::default();
+        let check = |url: &str| {
+            let url = Url::parse(url).unwrap();
+            let policy = policy.clone();
+            async move { policy.check(&url).await }
+        };
+        assert!(check("http://169.254.169.254/latest/meta-data/").await.is_err());
+        assert!(check("http://[::1]:3006/").await.is_err());
+        assert!(check("http://localhost:3006/mcp").await.is_err());
+
+        let allowed = UrlPolicy::with_allowlist(["localhost"]);
+        assert!(allowed.check(&Url::parse("http://localhost:3006/").unwrap()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_resolver_filters_blocked_addresses() {
+        let resolver = GuardedResolver::new(Arc::new(UrlPolicy::default()));
+        let error = resolver.resolve("localhost".parse().unwrap()).await.err().unwrap();
+        assert!(error.to_string().contains("blocked"));
+
+        let resolver = GuardedResolver::new(Arc::new(UrlPolicy::with_allowlist(["127.0.0.0/8", "::1"])));
+        let addrs: Vec<_> = resolver.resolve("localhost".parse().unwrap()).await.unwrap().collect();
+        assert!(!addrs.is_empty());
+    }
+}
@@ -0,0 +1,635 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::Stream;
+use rmcp::model::{ClientJsonRpcMessage, ServerJsonRpcMessage};
+use rmcp::transport::common::server_side_http::ServerSseMessage;
+use rmcp::transport::streamable_http_server::session::local::{
+    create_local_session, LocalSessionManager, LocalSessionManagerError, SessionError,
+};
+use rmcp::transport::streamable_http_server::session::{SessionId, SessionManager};
+use rmcp::transport::WorkerTransport;
+use rmcp::ServiceExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, OnceCell, RwLock};
+use tokio_util::sync::CancellationToken;
+
+use crate::agents::Agents;
+
+/// Limits applied to MCP sessions, read from the environment.
+#[derive(Debug, Clone)]
+pub struct SessionSettings {
+    /// Maximum number of concurrently open sessions.
+    pub max_sessions: Option<usize>,
+    /// Sessions without any traffic for this long are closed.
+    pub idle_timeout: Option<Duration>,
+    /// Persist sessions in the job store so they survive a restart.
+    pub persist: bool,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            max_sessions: Some(256),
+            idle_timeout: Some(Duration::from_secs(60 * 60)),
+            persist: false,
+        }
+    }
+}
+
+impl SessionSettings {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let parse = |key: &str| std::env::var(key).ok().and_then(|v| v.parse::<u64>().ok());
+
+        Self {
+            // 0 disables the limit
+            max_sessions: match parse("MCP_MAX_SESSIONS") {
+                Some(0) => None,
+                Some(n) => Some(n as usize),
+                None => defaults.max_sessions,
+            },
+            idle_timeout: match parse("MCP_SESSION_IDLE_TIMEOUT_SECS") {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => defaults.idle_timeout,
+            },
+            persist: std::env::var("MCP_PERSIST_SESSIONS")
+                .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+                .unwrap_or(defaults.persist),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub created_at: u64,
+    pub last_active: u64,
+}
+
+/// A session as persisted in the job store: its metadata and the
+/// initialize request needed to replay the handshake after a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredSession {
+    info: SessionInfo,
+    initialize: serde_json::Value,
+}
+
+struct SessionStore {
+    tree: sled::Tree,
+}
+
+impl SessionStore {
+    async fn open() -> Result<Self, SessionManagerError> {
+        let db = crate::handlers::agents::DB.lock().await;
+        Ok(Self {
+            tree: db.open_tree("mcp_sessions")?,
+        })
+    }
+
+    fn load(&self, id: &str) -> Result<Option<StoredSession>, SessionManagerError> {
+        match self.tree.get(id)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, session: &StoredSession) -> Result<(), SessionManagerError> {
+        self.tree
+            .insert(session.info.id.as_str(), serde_json::to_vec(session)?)?;
+        Ok(())
+    }
+
+    fn remove(&self, id: &str) -> Result<(), SessionManagerError> {
+        self.tree.remove(id)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum SessionManagerError {
+    Local(LocalSessionManagerError),
+    Session(SessionError),
+    LimitReached(usize),
+    Store(String),
+}
+
+impl std::fmt::Display for SessionManagerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionManagerError::Local(e) => write!(f, "{}", e),
+            SessionManagerError::Session(e) => write!(f, "{}", e),
+            SessionManagerError::LimitReached(max) => {
+                write!(f, "Session limit of {} reached", max)
+            }
+            SessionManagerError::Store(e) => write!(f, "Session store error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SessionManagerError {}
+
+impl From<LocalSessionManagerError> for SessionManagerError {
+    fn from(e: LocalSessionManagerError) -> Self {
+        SessionManagerError::Local(e)
+    }
+}
+
+impl From<SessionError> for SessionManagerError {
+    fn from(e: SessionError) -> Self {
+        SessionManagerError::Session(e)
+    }
+}
+
+impl From<sled::Error> for SessionManagerError {
+    fn from(e: sled::Error) -> Self {
+        SessionManagerError::Store(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for SessionManagerError {
+    fn from(e: serde_json::Error) -> Self {
+        SessionManagerError::Store(e.to_string())
+    }
+}
+
+/// Wraps [`LocalSessionManager`] with a session cap, idle expiry and
+/// optional persistence in the job store.
+///
+/// Restoring a session reaches into `LocalSessionManager`'s session map and
+/// config, which is why rmcp is pinned to a fixed revision.
+pub struct ManagedSessionManager {
+    inner: LocalSessionManager,
+    settings: SessionSettings,
+    sessions: Arc<RwLock<HashMap<SessionId, SessionInfo>>>,
+    /// Sessions whose activity has not been written to the job store yet
+    dirty: Mutex<HashSet<SessionId>>,
+    store: OnceCell<Option<SessionStore>>,
+    restoring: Mutex<()>,
+}
+
+impl ManagedSessionManager {
+    pub fn new(settings: SessionSettings) -> Self {
+        Self {
+            inner: LocalSessionManager::default(),
+            settings,
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+            dirty: Mutex::new(HashSet::new()),
+            store: OnceCell::new(),
+            restoring: Mutex::new(()),
+        }
+    }
+
+    pub fn settings(&self) -> &SessionSettings {
+        &self.settings
+    }
+
+    /// Lists the sessions currently open on this server.
+    pub async fn list(&self) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self.sessions.read().await.values().cloned().collect();
+        sessions.sort_by_key(|s| s.created_at);
+        sessions
+    }
+
+    /// Closes every session that has been idle for longer than the configured timeout.
+    pub async fn expire_idle(&self) -> usize {
+        let expired: Vec<SessionId> = self
+            .sessions
+            .read()
+            .await
+            .iter()
+            .filter(|(_, info)| self.is_expired(info))
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in &expired {
+            tracing::info!(session_id = %id, "closing idle MCP session");
+            if let Err(e) = self.close_session(id).await {
+                tracing::warn!(session_id = %id, "Failed to close idle session: {}", e);
+            }
+        }
+        expired.len()
+    }
+
+    /// Writes the last activity of sessions used since the previous call to the
+    /// job store, so a restart does not restore them with stale timestamps.
+    pub async fn persist_activity(&self) {
+        let Some(store) = self.store().await else {
+            return;
+        };
+        let dirty = std::mem::take(&mut *self.dirty.lock().await);
+        let sessions = self.sessions.read().await;
+        for id in dirty {
+            // Sessions closed in the meantime are already gone from the store
+            let Some(info) = sessions.get(&id) else {
+                continue;
+            };
+            let result = store.load(&id).and_then(|stored| match stored {
+                Some(mut stored) => {
+                    stored.info.last_active = info.last_active;
+                    store.save(&stored)
+                }
+                None => Ok(()),
+            });
+            if let Err(e) = result {
+                tracing::warn!(session_id = %id, "Failed to persist session activity: {}", e);
+            }
+        }
+    }
+
+    /// Periodically persists session activity and expires idle sessions until
+    /// the manager is dropped.
+    pub fn spawn_reaper(self: &Arc<Self>) {
+        let period = match self.settings.idle_timeout {
+            Some(timeout) => (timeout / 2).clamp(Duration::from_secs(1), Duration::from_secs(60)),
+            // activity still has to reach the store when sessions never expire
+            None if self.settings.persist => Duration::from_secs(60),
+            None => return,
+        };
+        let manager: Weak<Self> = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                match manager.upgrade() {
+                    Some(manager) => {
+                        manager.persist_activity().await;
+                        manager.expire_idle().await;
+                    }
+                    None => break,
+                }
+            }
+        });
+    }
+
+    fn is_expired(&self, info: &SessionInfo) -> bool {
+        match self.settings.idle_timeout {
+            Some(timeout) => now_secs().saturating_sub(info.last_active) >= timeout.as_secs(),
+            None => false,
+        }
+    }
+
+    async fn store(&self) -> Option<&SessionStore> {
+        self.store
+            .get_or_init(|| async {
+                if !self.settings.persist {
+                    return None;
+                }
+                match SessionStore::open().await {
+                    Ok(store) => Some(store),
+                    Err(e) => {
+                        tracing::error!("Failed to open MCP session store: {}", e);
+                        None
+                    }
+                }
+            })
+            .await
+            .as_ref()
+    }
+
+    /// Records activity in memory only, [`Self::persist_activity`] writes it to the store in batches.
+    async fn touch(&self, id: &SessionId) {
+        if let Some(info) = self.sessions.write().await.get_mut(id) {
+            info.last_active = now_secs();
+            if self.settings.persist {
+                self.dirty.lock().await.insert(id.clone());
+            }
+        }
+    }
+
+    /// Drops a session from the list once its worker has ended, e.g. because
+    /// the client went away without closing it.
+    fn forget_when_ended(&self, id: SessionId, worker: CancellationToken) {
+        let sessions = Arc::downgrade(&self.sessions);
+        tokio::spawn(async move {
+            worker.cancelled().await;
+            if let Some(sessions) = sessions.upgrade() {
+                if sessions.write().await.remove(&id).is_some() {
+                    tracing::debug!(session_id = %id, "MCP session worker ended");
+                }
+            }
+        });
+    }
+
+    /// Recreates a session from the job store and replays its initialize handshake.
+    async fn restore(&self, id: &SessionId) -> Result<bool, SessionManagerError> {
+        let Some(store) = self.store().await else {
+            return Ok(false);
+        };
+        let _guard = self.restoring.lock().await;
+        if self.inner.has_session(id).await? {
+            return Ok(true);
+        }
+        let Some(stored) = store.load(id)? else {
+            return Ok(false);
+        };
+        if self.is_expired(&stored.info) {
+            store.remove(id)?;
+            return Ok(false);
+        }
+
+        let initialize: ClientJsonRpcMessage = serde_json::from_value(stored.initialize.clone())?;
+        let (handle, worker) = create_local_session(id.clone(), self.inner.session_config.clone());
+        self.inner
+            .sessions
+            .write()
+            .await
+            .insert(id.clone(), handle.clone());
+
+        let transport = WorkerTransport::spawn(worker);
+        let worker_ended = transport.cancel_token();
+        tokio::spawn(async move {
+            match Agents::new().serve(transport).await {
+                Ok(service) => {
+                    let _ = service.waiting().await;
+                }
+                Err(e) => tracing::error!("Failed to restore MCP service: {}", e),
+            }
+        });
+
+        handle.initialize(initialize).await?;
+        let initialized: ClientJsonRpcMessage = serde_json::from_value(serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/initialized"
+        }))?;
+        self.inner.accept_message(id, initialized).await?;
+
+        let mut info = stored.info;
+        info.last_active = now_secs();
+        self.sessions.write().await.insert(id.clone(), info);
+        self.forget_when_ended(id.clone(), worker_ended);
+        tracing::info!(session_id = %id, "restored MCP session from the job store");
+        Ok(true)
+    }
+}
+
+impl SessionManager for ManagedSessionManager {
+    type Error = SessionManagerError;
+    type Transport = <LocalSessionManager as SessionManager>::Transport;
+
+    async fn create_session(&self) -> Result<(SessionId, Self::Transport), Self::Error> {
+        if let Some(max) = self.settings.max_sessions {
+            if self.sessions.read().await.len() >= max {
+                self.expire_idle().await;
+            }
+        }
+
+        // The limit is checked and the session inserted under one lock, so
+        // concurrent creates cannot overshoot it
+        let mut sessions = self.sessions.write().await;
+        if let Some(max) = self.settings.max_sessions {
+            if sessions.len() >= max {
+                tracing::warn!("Rejecting new MCP session: limit of {} reached", max);
+                return Err(SessionManagerError::LimitReached(max));
+            }
+        }
+        let (id, transport) = self.inner.create_session().await?;
+        let now = now_secs();
+        sessions.insert(
+            id.clone(),
+            SessionInfo {
+                id: id.to_string(),
+                created_at: now,
+                last_active: now,
+            },
+        );
+        self.forget_when_ended(id.clone(), transport.cancel_token());
+        Ok((id, transport))
+    }
+
+    async fn initialize_session(
+        &self,
+        id: &SessionId,
+        message: ClientJsonRpcMessage,
+    ) -> Result<ServerJsonRpcMessage, Self::Error> {
+        let initialize = serde_json::to_value(&message)?;
+        let response = self.inner.initialize_session(id, message).await?;
+
+        if let Some(store) = self.store().await {
+            if let Some(info) = self.sessions.read().await.get(id).cloned() {
+                store.save(&StoredSession { info, initialize })?;
+            }
+        }
+        Ok(response)
+    }
+
+    async fn has_session(&self, id: &SessionId) -> Result<bool, Self::Error> {
+        if self.inner.has_session(id).await? {
+            return Ok(true);
+        }
+        self.restore(id).await
+    }
+
+    async fn close_session(&self, id: &SessionId) -> Result<(), Self::Error> {
+        self.sessions.write().await.remove(id);
+        self.dirty.lock().await.remove(id);
+        if let Some(store) = self.store().await {
+            store.remove(id)?;
+        }
+        self.inner.close_session(id).await?;
+        Ok(())
+    }
+
+    async fn create_stream(
+        &self,
+        id: &SessionId,
+        message: ClientJsonRpcMessage,
+    ) -> Result<impl Stream<Item = ServerSseMessage> + Send + Sync + 'static, Self::Error> {
+        self.touch(id).await;
+        Ok(self.inner.create_stream(id, message).await?)
+    }
+
+    async fn accept_message(
+        &self,
+        id: &SessionId,
+        message: ClientJsonRpcMessage,
+    ) -> Result<(), Self::Error> {
+        self.touch(id).await;
+        Ok(self.inner.accept_message(id, message).await?)
+    }
+
+    async fn create_standalone_stream(
+        &self,
+        id: &SessionId,
+    ) -> Result<impl Stream<Item = ServerSseMessage> + Send + Sync + 'static, Self::Error> {
+        self.touch(id).await;
+        Ok(self.inner.create_standalone_stream(id).await?)
+    }
+
+    async fn resume(
+        &self,
+        id: &SessionId,
+        last_event_id: String,
+    ) -> Result<impl Stream<Item = ServerSseMessage> + Send + Sync + 'static, Self::Error> {
+        self.touch(id).await;
+        Ok(self.inner.resume(id, last_event_id).await?)
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    fn settings(max_sessions: Option<usize>, idle_timeout: Option<Duration>) -> SessionSettings {
+        SessionSettings {
+            max_sessions,
+            idle_timeout,
+            persist: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_limit() {
+        let manager = ManagedSessionManager::new(settings(Some(1), None));
+
+        let (id, _transport) = manager.create_session().await.unwrap();
+        assert!(matches!(
+            manager.create_session().await,
+            Err(SessionManagerError::LimitReached(1))
+        ));
+
+        manager.close_session(&id).await.unwrap();
+        assert!(manager.create_session().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_creates_respect_the_limit() {
+        let manager = Arc::new(ManagedSessionManager::new(settings(Some(2), None)));
+
+        let creates: Vec<_> = (0..8)
+            .map(|_| {
+                let manager = manager.clone();
+                tokio::spawn(async move { manager.create_session().await.map(|(_, transport)| transport) })
+            })
+            .collect();
+        // the transports are kept, a dropped one would end its session
+        let mut transports = Vec::new();
+        for create in creates {
+            if let Ok(transport) = create.await.unwrap() {
+                transports.push(transport);
+            }
+        }
+        assert_eq!(transports.len(), 2);
+        assert_eq!(manager.list().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_touch_keeps_activity_in_memory() {
+        let manager = ManagedSessionManager::new(settings(None, None));
+        let (id, _transport) = manager.create_session().await.unwrap();
+
+        manager.touch(&id).await;
+        // Nothing is queued for the store when sessions are not persisted
+        assert!(manager.dirty.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_idle_sessions_expire() {
+        let manager = ManagedSessionManager::new(settings(None, Some(Duration::ZERO)));
+
+        let (id, _transport) = manager.create_session().await.unwrap();
+        assert_eq!(manager.list().await.len(), 1);
+
+        assert_eq!(manager.expire_idle().await, 1);
+        assert!(manager.list().await.is_empty());
+        assert!(!manager.has_session(&id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_list_sessions() {
+        let manager = ManagedSessionManager::new(settings(None, None));
+
+        let (first, _t1) = manager.create_session().await.unwrap();
+        let (second, _t2) = manager.create_session().await.unwrap();
+
+        let ids: Vec<String> = manager.list().await.into_iter().map(|s| s.id).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&first.to_string()));
+        assert!(ids.contains(&second.to_string()));
+        assert_eq!(manager.expire_idle().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_ended_sessions_are_forgotten() {
+        let manager = ManagedSessionManager::new(settings(None, None));
+        let (_, transport) = manager.create_session().await.unwrap();
+        assert_eq!(manager.list().await.len(), 1);
+
+        drop(transport);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !manager.list().await.is_empty() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    fn persisted(tree: &sled::Tree) -> ManagedSessionManager {
+        let manager = ManagedSessionManager::new(SessionSettings {
+            persist: true,
+            ..settings(None, None)
+        });
+        assert!(manager.store.set(Some(SessionStore { tree: tree.clone() })).is_ok());
+        manager
+    }
+
+    fn client_message(message: serde_json::Value) -> ClientJsonRpcMessage {
+        serde_json::from_value(message).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_restores_persisted_session() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let tree = db.open_tree("mcp_sessions").unwrap();
+
+        let first = persisted(&tree);
+        let (id, transport) = first.create_session().await.unwrap();
+        tokio::spawn(async move {
+            let service = Agents::new().serve(transport).await.unwrap();
+            let _ = service.waiting().await;
+        });
+        let initialize = client_message(serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": { "name": "test", "version": "0.1.0" }
+            }
+        }));
+        first.initialize_session(&id, initialize).await.unwrap();
+        assert!(tree.contains_key(id.as_bytes()).unwrap());
+
+        // A restarted server only knows the session from the store
+        let second = persisted(&tree);
+        assert!(second.list().await.is_empty());
+        assert!(second.has_session(&id).await.unwrap());
+        assert_eq!(second.list().await[0].id, id.to_string());
+
+        let list_tools = client_message(serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "tools/list"
+        }));
+        let mut responses = second.create_stream(&id, list_tools).await.unwrap();
+        let response = tokio::time::timeout(Duration::from_secs(5), responses.next()).await.unwrap();
+        assert!(response.is_some());
+
+        // Unknown sessions are not made up
+        let unknown = SessionId::from("unknown");
+        assert!(!second.has_session(&unknown).await.unwrap());
+    }
+}
@@ -0,0 +1,500 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rmcp::model::{CallToolRequestParam, CallToolResult, JsonObject, Tool};
+use rmcp::service::{Peer, RunningService};
+use rmcp::transport::{StreamableHttpClientTransport, TokioChildProcess};
+use rmcp::{Error as McpError, RoleClient, ServiceExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+/// Separator between the upstream name and the tool name, e.g. `github.search_issues`.
+pub const NAMESPACE_SEPARATOR: char = '.';
+
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Kept below the health check period, so a hanging upstream is given up on
+/// before the next check would try to reconnect it.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum UpstreamTransport {
+    Stdio {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        env: HashMap<String, String>,
+    },
+    Http {
+        url: String,
+    },
+}
+
+/// An upstream MCP server as configured in the `MCP_UPSTREAMS_CONFIG` file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpstreamConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub transport: UpstreamTransport,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpstreamsFile {
+    upstreams: Vec<UpstreamConfig>,
+}
+
+/// Reads the upstream servers from the JSON file named by `MCP_UPSTREAMS_CONFIG`.
+pub fn load_configs() -> Vec<UpstreamConfig> {
+    let path = match std::env::var("MCP_UPSTREAMS_CONFIG") {
+        Ok(path) if !path.is_empty() => path,
+        _ => return Vec::new(),
+    };
+
+    let configs = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|json| parse_configs(&json));
+    match configs {
+        Ok(configs) => configs,
+        Err(e) => {
+            tracing::error!("Failed to load upstream MCP servers from {}: {}", path, e);
+            Vec::new()
+        }
+    }
+}
+
+fn parse_configs(json: &str) -> Result<Vec<UpstreamConfig>, String> {
+    let file: UpstreamsFile = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    for config in &file.upstreams {
+        if config.name.is_empty() || config.name.contains(NAMESPACE_SEPARATOR) {
+            return Err(format!("Invalid upstream name: {:?}", config.name));
+        }
+    }
+    Ok(file.upstreams)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamStatus {
+    Connecting,
+    Healthy,
+    Unhealthy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamHealth {
+    pub name: String,
+    pub status: UpstreamStatus,
+    pub tools: usize,
+    pub last_error: Option<String>,
+    pub last_checked: u64,
+    pub consecutive_failures: u32,
+}
+
+struct Upstream {
+    /// `None` for upstreams registered with an already running client; those cannot be reconnected.
+    config: Option<UpstreamConfig>,
+    client: Option<RunningService<RoleClient, ()>>,
+    tools: Vec<Tool>,
+    health: UpstreamHealth,
+}
+
+impl Upstream {
+    fn connecting(name: &str) -> Self {
+        Self {
+            config: None,
+            client: None,
+            tools: Vec::new(),
+            health: UpstreamHealth {
+                name: name.to_string(),
+                status: UpstreamStatus::Connecting,
+                tools: 0,
+                last_error: None,
+                last_checked: now_secs(),
+                consecutive_failures: 0,
+            },
+        }
+    }
+
+    fn peer(&self) -> Option<Peer<RoleClient>> {
+        self.client.as_ref().map(|client| client.peer().clone())
+    }
+
+    fn mark_healthy(&mut self, tools: Vec<Tool>) {
+        self.health.status = UpstreamStatus::Healthy;
+        self.health.tools = tools.len();
+        self.health.last_error = None;
+        self.health.last_checked = now_secs();
+        self.health.consecutive_failures = 0;
+        self.tools = tools;
+    }
+
+    fn mark_unhealthy(&mut self, error: String) {
+        tracing::warn!("Upstream MCP server {} is unhealthy: {}", self.health.name, error);
+        self.health.status = UpstreamStatus::Unhealthy;
+        self.health.last_error = Some(error);
+        self.health.last_checked = now_secs();
+        self.health.consecutive_failures += 1;
+    }
+}
+
+/// Upstream MCP servers whose tools are re-exported by [`crate::agents::Agents`].
+#[derive(Default)]
+pub struct UpstreamRegistry {
+    upstreams: RwLock<HashMap<String, Upstream>>,
+}
+
+/// The registry shared by every MCP session.
+pub fn registry() -> Arc<UpstreamRegistry> {
+    static REGISTRY: OnceLock<Arc<UpstreamRegistry>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default).clone()
+}
+
+impl UpstreamRegistry {
+    /// Connects to every configured upstream concurrently.
+    pub async fn connect_all(&self, configs: Vec<UpstreamConfig>) {
+        futures::future::join_all(configs.into_iter().map(|config| self.connect(config))).await;
+    }
+
+    /// Connects to the upstreams in the background, so a slow upstream does
+    /// not hold up the server start.
+    pub fn spawn_connect_all(self: &Arc<Self>, configs: Vec<UpstreamConfig>) {
+        let registry = self.clone();
+        tokio::spawn(async move { registry.connect_all(configs).await });
+    }
+
+    /// Connects to a configured upstream, recording its health either way.
+    ///
+    /// A reconnect updates the existing entry, so its failure count carries over.
+    pub async fn connect(&self, config: UpstreamConfig) {
+        let name = config.name.clone();
+        self.upstreams
+            .write()
+            .await
+            .entry(name.clone())
+            .or_insert_with(|| Upstream::connecting(&name))
+            .config = Some(config.clone());
+
+        let client = tokio::time::timeout(CONNECT_TIMEOUT, start_client(&config.transport))
+            .await
+            .map_err(|_| "connect timed out".to_string())
+            .and_then(|client| client);
+        match client {
+            Ok(client) => {
+                if let Err(e) = self.register(&name, Some(config), client).await {
+                    tracing::error!("Failed to list tools of upstream {}: {}", name, e);
+                }
+            }
+            Err(e) => {
+                if let Some(upstream) = self.upstreams.write().await.get_mut(&name) {
+                    upstream.mark_unhealthy(e);
+                }
+            }
+        }
+    }
+
+    /// Registers an already running client under `name` and fetches its tools.
+    pub async fn register(
+        &self,
+        name: &str,
+        config: Option<UpstreamConfig>,
+        client: RunningService<RoleClient, ()>,
+    ) -> Result<usize, String> {
+        let tools = tokio::time::timeout(CONNECT_TIMEOUT, client.list_all_tools())
+            .await
+            .map_err(|_| "listing tools timed out".to_string())
+            .and_then(|tools| tools.map_err(|e| e.to_string()));
+        let mut upstreams = self.upstreams.write().await;
+        let upstream = upstreams
+            .entry(name.to_string())
+            .or_insert_with(|| Upstream::connecting(name));
+        upstream.config = config.or(upstream.config.take());
+        upstream.client = Some(client);
+
+        match tools {
+            Ok(tools) => {
+                let count = tools.len();
+                tracing::info!("Connected to upstream MCP server {} with {} tools", name, count);
+                upstream.mark_healthy(tools);
+                Ok(count)
+            }
+            Err(e) => {
+                upstream.mark_unhealthy(e.clone());
+                Err(e)
+            }
+        }
+    }
+
+    /// Tools of all healthy upstreams, namespaced as `<upstream>.<tool>`.
+    pub async fn tools(&self) -> Vec<Tool> {
+        let upstreams = self.upstreams.read().await;
+        let mut names: Vec<&String> = upstreams.keys().collect();
+        names.sort();
+
+        names
+            .into_iter()
+            .filter_map(|name| upstreams.get(name).map(|upstream| (name, upstream)))
+            .filter(|(_, upstream)| upstream.health.status == UpstreamStatus::Healthy)
+            .flat_map(|(name, upstream)| {
+                upstream.tools.iter().map(move |tool| {
+                    let mut tool = tool.clone();
+                    tool.name = format!("{}{}{}", name, NAMESPACE_SEPARATOR, tool.name).into();
+                    tool
+                })
+            })
+            .collect()
+    }
+
+    /// Calls a namespaced upstream tool, or returns `None` if `name` does not belong to an upstream.
+    pub async fn call_tool(
+        &self,
+        name: &str,
+        arguments: Option<JsonObject>,
+    ) -> Option<Result<CallToolResult, McpError>> {
+        let (upstream_name, tool_name) = name.split_once(NAMESPACE_SEPARATOR)?;
+
+        let peer = {
+            let upstreams = self.upstreams.read().await;
+            let upstream = upstreams.get(upstream_name)?;
+            match upstream.peer() {
+                Some(peer) if upstream.health.status == UpstreamStatus::Healthy => peer,
+                _ => {
+                    return Some(Err(McpError::internal_error(
+                        format!("Upstream {} is unavailable", upstream_name),
+                        upstream.health.last_error.clone().map(|e| serde_json::json!({ "reason": e })),
+                    )))
+                }
+            }
+        };
+
+        let result = peer
+            .call_tool(CallToolRequestParam {
+                name: tool_name.to_string().into(),
+                arguments,
+            })
+            .await;
+
+        Some(match result {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                if let Some(upstream) = self.upstreams.write().await.get_mut(upstream_name) {
+                    upstream.mark_unhealthy(e.to_string());
+                }
+                Err(McpError::internal_error(
+                    format!("Upstream {} failed to call {}: {}", upstream_name, tool_name, e),
+                    None,
+                ))
+            }
+        })
+    }
+
+    pub async fn health(&self) -> Vec<UpstreamHealth> {
+        let mut health: Vec<UpstreamHealth> = self
+            .upstreams
+            .read()
+            .await
+            .values()
+            .map(|upstream| upstream.health.clone())
+            .collect();
+        health.sort_by(|a, b| a.name.cmp(&b.name));
+        health
+    }
+
+    /// Refreshes the tool list of every upstream and reconnects the unhealthy ones.
+    pub async fn check_health(&self) {
+        let targets: Vec<(String, Option<Peer<RoleClient>>, Option<UpstreamConfig>)> = self
+            .upstreams
+            .read()
+            .await
+            .iter()
+            // the first connect is still running and bounded by its own timeout
+            .filter(|(_, upstream)| upstream.health.status != UpstreamStatus::Connecting)
+            .map(|(name, upstream)| (name.clone(), upstream.peer(), upstream.config.clone()))
+            .collect();
+
+        for (name, peer, config) in targets {
+            let tools = match peer {
+                Some(peer) => tokio::time::timeout(HEALTH_CHECK_TIMEOUT, peer.list_all_tools())
+                    .await
+                    .map_err(|_| "health check timed out".to_string())
+                    .and_then(|tools| tools.map_err(|e| e.to_string())),
+                None => Err("not connected".to_string()),
+            };
+
+            match tools {
+                Ok(tools) => {
+                    if let Some(upstream) = self.upstreams.write().await.get_mut(&name) {
+                        upstream.mark_healthy(tools);
+                    }
+                }
+                Err(e) => {
+                    if let Some(upstream) = self.upstreams.write().await.get_mut(&name) {
+                        upstream.mark_unhealthy(e);
+                    }
+                    if let Some(config) = config {
+                        tracing::info!("Reconnecting to upstream MCP server {}", name);
+                        self.connect(config).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs [`Self::check_health`] every `period` until the registry is dropped.
+    pub fn spawn_health_checks(self: &Arc<Self>, period: Duration) {
+        let registry: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match registry.upgrade() {
+                    Some(registry) => registry.check_health().await,
+                    None => break,
+                }
+            }
+        });
+    }
+}
+
+async fn start_client(transport: &UpstreamTransport) -> Result<RunningService<RoleClient, ()>, String> {
+    match transport {
+        UpstreamTransport::Stdio { command, args, env } => {
+            let mut cmd = tokio::process::Command::new(command);
+            cmd.args(args).envs(env);
+            let process = TokioChildProcess::new(&mut cmd).map_err(|e| e.to_string())?;
+            ().serve(process).await.map_err(|e| e.to_string())
+        }
+        UpstreamTransport::Http { url } => {
+            let transport = StreamableHttpClientTransport::from_uri(url.as_str());
+            ().serve(transport).await.map_err(|e| e.to_string())
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counter::Counter;
+
+    /// Serves [`Counter`] over an in-memory pipe, standing in for a stdio upstream.
+    async fn counter_client() -> RunningService<RoleClient, ()> {
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            let server = Counter::new().serve(server_io).await.unwrap();
+            let _ = server.waiting().await;
+        });
+        ().serve(client_io).await.unwrap()
+    }
+
+    /// A minimal MCP server in shell, answering one JSON-RPC request per line.
+    const STDIO_SERVER: &str = r#"
+while read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"method":"initialize"'*) printf '{"jsonrpc":"2.0","id":%s,"result":{"protocolVersion":"2025-03-26","capabilities":{"tools":{}},"serverInfo":{"name":"echo","version":"0.1.0"}}}\n' "$id" ;;
+    *'"method":"tools/list"'*) printf '{"jsonrpc":"2.0","id":%s,"result":{"tools":[{"name":"ping","description":"Replies with pong","inputSchema":{"type":"object"}}]}}\n' "$id" ;;
+    *'"method":"tools/call"'*) printf '{"jsonrpc":"2.0","id":%s,"result":{"content":[{"type":"text","text":"pong"}]}}\n' "$id" ;;
+  esac
+done
+"#;
+
+    fn stdio_config(name: &str, command: &str, args: &[&str]) -> UpstreamConfig {
+        UpstreamConfig {
+            name: name.to_string(),
+            transport: UpstreamTransport::Stdio {
+                command: command.to_string(),
+                args: args.iter().map(|a| a.to_string()).collect(),
+                env: HashMap::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_parse_configs() {
+        let configs = parse_configs(
+            r#"{"upstreams": [
+                {"name": "github", "command": "npx", "args": ["-y", "@modelcontextprotocol/server-github"]},
+                {"name": "docs", "url": "http://localhost:9000/mcp"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(configs.len(), 2);
+        assert!(matches!(&configs[0].transport, UpstreamTransport::Stdio { args, .. } if args.len() == 2));
+        assert!(matches!(&configs[1].transport, UpstreamTransport::Http { url } if url.ends_with("/mcp")));
+
+        assert!(parse_configs(r#"{"upstreams": [{"name": "a.b", "url": "http://x"}]}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_proxies_namespaced_tools() {
+        let registry = UpstreamRegistry::default();
+        registry.register("counter", None, counter_client().await).await.unwrap();
+
+        let names: Vec<String> = registry.tools().await.iter().map(|t| t.name.to_string()).collect();
+        assert!(names.contains(&"counter.increment".to_string()));
+        assert!(names.contains(&"counter.sum".to_string()));
+
+        let result = registry.call_tool("counter.increment", None).await.unwrap().unwrap();
+        assert_eq!(result.content[0].as_text().unwrap().text, "1");
+
+        let mut arguments = JsonObject::new();
+        arguments.insert("a".to_string(), 2.into());
+        arguments.insert("b".to_string(), 3.into());
+        let result = registry.call_tool("counter.sum", Some(arguments)).await.unwrap().unwrap();
+        assert_eq!(result.content[0].as_text().unwrap().text, "5");
+
+        // built-in tools and unknown upstreams are not handled by the registry
+        assert!(registry.call_tool("search", None).await.is_none());
+        assert!(registry.call_tool("missing.tool", None).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_health_tracking() {
+        let registry = UpstreamRegistry::default();
+        registry.register("counter", None, counter_client().await).await.unwrap();
+
+        registry.check_health().await;
+        let health = registry.health().await;
+        assert_eq!(health.len(), 1);
+        assert_eq!(health[0].status, UpstreamStatus::Healthy);
+        assert_eq!(health[0].consecutive_failures, 0);
+        assert!(health[0].tools > 0);
+    }
+
+    #[tokio::test]
+    async fn test_connects_to_stdio_upstream() {
+        let registry = UpstreamRegistry::default();
+        registry.connect(stdio_config("echo", "sh", &["-c", STDIO_SERVER])).await;
+
+        let health = registry.health().await;
+        assert_eq!(health[0].status, UpstreamStatus::Healthy);
+        assert_eq!(health[0].tools, 1);
+
+        let result = registry.call_tool("echo.ping", None).await.unwrap().unwrap();
+        assert_eq!(result.content[0].as_text().unwrap().text, "pong");
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_keeps_failure_count() {
+        let registry = UpstreamRegistry::default();
+        registry
+            .connect(stdio_config("missing", "/nonexistent/mcp-server", &[]))
+            .await;
+        assert_eq!(registry.health().await[0].consecutive_failures, 1);
+
+        // the failed health check and the failed reconnect both count
+        registry.check_health().await;
+        let health = registry.health().await;
+        assert_eq!(health[0].status, UpstreamStatus::Unhealthy);
+        assert_eq!(health[0].consecutive_failures, 3);
+    }
+}
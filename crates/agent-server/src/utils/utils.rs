@@ -43,7 +43,7 @@ impl ShimBinding {
             .env("SEARXNG_API_BASE_URL", &self.searxng_api_base_url)
             .env("SEARXNG_PASSWORD", &self.searxng_password)
             .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::inherit());
+            .stderr(std::process::Stdio::piped());
 
         command.spawn()
     }
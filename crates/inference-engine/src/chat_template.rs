@@ -0,0 +1,178 @@
+use anyhow::{Error as E, Result};
+use minijinja::{context, Environment, ErrorKind};
+use serde::Serialize;
+use std::path::Path;
+
+use crate::model::Which;
+use crate::openai_types::Message;
+
+/// Renders chat messages into the prompt format a model was trained on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatTemplate {
+    /// `System:`/`User:`/`Assistant:` transcript for base models without a chat format
+    Plain,
+    /// Gemma turn markers, shared by Gemma 1, 2 and 3 instruction-tuned models
+    Gemma,
+    /// A Jinja `chat_template` as found in `tokenizer_config.json`, along with
+    /// the model's EOS token from the same file, which templates may emit
+    Jinja { template: String, eos_token: String },
+}
+
+/// A message as handed to a Jinja chat template
+#[derive(Debug, Serialize)]
+struct TemplateMessage {
+    role: String,
+    content: String,
+}
+
+impl ChatTemplate {
+    /// The built-in template for a model variant
+    pub fn for_model(which: Which) -> Self {
+        if which.is_instruct_model() {
+            Self::Gemma
+        } else {
+            Self::Plain
+        }
+    }
+
+    /// Uses the `chat_template` from a `tokenizer_config.json` when it has a
+    /// usable one, falling back to the built-in template for `which`.
+    pub fn load(which: Which, tokenizer_config: Option<&Path>) -> Self {
+        let template = match tokenizer_config.map(Self::from_tokenizer_config) {
+            Some(Ok(Some(template))) => template,
+            Some(Ok(None)) | None => return Self::for_model(which),
+            Some(Err(e)) => {
+                tracing::warn!("Ignoring the chat template in tokenizer_config.json: {}", e);
+                return Self::for_model(which);
+            }
+        };
+        // Render a probe conversation so a broken template is caught at startup
+        match template.render(&[Message::text("user", "Hello")]) {
+            Ok(_) => template,
+            Err(e) => {
+                tracing::warn!("Ignoring the chat template in tokenizer_config.json: {}", e);
+                Self::for_model(which)
+            }
+        }
+    }
+
+    /// Reads the `chat_template` from a `tokenizer_config.json`, which holds
+    /// either a single template or a list of named ones, and its `eos_token`.
+    pub fn from_tokenizer_config(path: &Path) -> Result<Option<Self>> {
+        let config: serde_json::Value = serde_json::from_reader(std::fs::File::open(path)?)?;
+        let template = match &config["chat_template"] {
+            serde_json::Value::String(template) => Some(template.clone()),
+            serde_json::Value::Array(templates) => templates
+                .iter()
+                .find(|t| t["name"] == "default")
+                .and_then(|t| t["template"].as_str())
+                .map(str::to_string),
+            _ => None,
+        };
+        Ok(template.map(|template| Self::Jinja {
+            template,
+            eos_token: eos_token(&config).unwrap_or_default().to_string(),
+        }))
+    }
+
+    /// Renders `messages` followed by the prompt for the assistant's reply.
+    ///
+    /// Rendered prompts leave out the BOS token, which the tokenizer adds when
+    /// the prompt is encoded.
+    pub fn render(&self, messages: &[Message]) -> Result<String> {
+        match self {
+            Self::Plain => Ok(render_plain(messages)),
+            Self::Gemma => Ok(render_gemma(messages)),
+            Self::Jinja { template, eos_token } => render_jinja(template, eos_token, messages),
+        }
+    }
+}
+
+/// Reads the `eos_token` from a `tokenizer_config.json`, which generation
+/// stops on.
+pub fn eos_token_from_tokenizer_config(path: &Path) -> Result<Option<String>> {
+    let config: serde_json::Value = serde_json::from_reader(std::fs::File::open(path)?)?;
+    Ok(eos_token(&config).map(str::to_string))
+}
+
+/// The token is either a plain string or an added-token object
+fn eos_token(config: &serde_json::Value) -> Option<&str> {
+    match &config["eos_token"] {
+        serde_json::Value::String(token) => Some(token.as_str()),
+        token => token["content"].as_str(),
+    }
+}
+
+fn render_plain(messages: &[Message]) -> String {
+    let mut prompt = String::new();
+    for message in messages {
+        let content = message.text_content();
+        match message.role.as_str() {
+            "system" => prompt.push_str(&format!("System: {}\n", content)),
+            "user" => prompt.push_str(&format!("User: {}\n", content)),
+            "assistant" => prompt.push_str(&format!("Assistant: {}\n", content)),
+            role => prompt.push_str(&format!("{}: {}\n", role, content)),
+        }
+    }
+    prompt.push_str("Assistant: ");
+    prompt
+}
+
+/// Gemma has no system role, so system messages are folded into the next
+/// user turn, as the official templates do.
+fn render_gemma(messages: &[Message]) -> String {
+    let mut prompt = String::new();
+    let mut system = Vec::new();
+    for message in messages {
+        let content = message.text_content();
+        let role = match message.role.as_str() {
+            "system" => {
+                system.push(content.trim().to_string());
+                continue;
+            }
+            "assistant" => "model",
+            _ => "user",
+        };
+        let content = if role == "user" && !system.is_empty() {
+            format!("{}\n\n{}", std::mem::take(&mut system).join("\n\n"), content.trim())
+        } else {
+            content.trim().to_string()
+        };
+        prompt.push_str(&format!("<start_of_turn>{}\n{}<end_of_turn>\n", role, content));
+    }
+    // A system prompt with no user turn after it still reaches the model
+    if !system.is_empty() {
+        prompt.push_str(&format!("<start_of_turn>user\n{}<end_of_turn>\n", system.join("\n\n")));
+    }
+    prompt.push_str("<start_of_turn>model\n");
+    prompt
+}
+
+fn render_jinja(template: &str, eos_token: &str, messages: &[Message]) -> Result<String> {
+    let mut env = Environment::new();
+    minijinja_contrib::add_to_environment(&mut env);
+    // Hugging Face templates lean on Python string methods such as `strip()`
+    env.set_unknown_method_callback(minijinja_contrib::pycompat::unknown_method_callback);
+    env.add_function("raise_exception", |message: String| -> Result<String, minijinja::Error> {
+        Err(minijinja::Error::new(ErrorKind::InvalidOperation, message))
+    });
+    env.add_template("chat", template).map_err(E::msg)?;
+
+    let messages: Vec<TemplateMessage> = messages
+        .iter()
+        .map(|message| TemplateMessage {
+            role: message.role.clone(),
+            content: message.text_content(),
+        })
+        .collect();
+    env.get_template("chat")
+        .and_then(|template| {
+            template.render(context! {
+                messages => messages,
+                add_generation_prompt => true,
+                bos_token => "",
+                eos_token => eos_token,
+            })
+        })
+        .map_err(E::msg)
+}
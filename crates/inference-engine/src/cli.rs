@@ -20,6 +20,10 @@ pub struct Args {
     #[arg(long, default_value_t = 3777)]
     pub port: u16,
 
+    /// Most choices (`n`) a single chat completion request may ask for
+    #[arg(long, default_value_t = 4)]
+    pub max_choices: usize,
+
     /// Prompt for text generation (not used in server mode)
     #[arg(long)]
     pub prompt: Option<String>,
@@ -1,10 +1,12 @@
 // Expose modules for testing and library usage
 pub mod token_output_stream;
 pub mod model;
+pub mod logits_pipeline;
 pub mod text_generation;
 pub mod utilities_lib;
 pub mod openai_types;
 pub mod cli;
+pub mod chat_template;
 pub mod server;
 
 // Re-export key components for easier access
@@ -0,0 +1,229 @@
+use anyhow::Result;
+use candle_core::Tensor;
+use std::collections::HashMap;
+
+/// Tokens seen so far in a run, handed to every transform
+#[derive(Debug, Clone, Copy)]
+pub struct History<'a> {
+    /// The prompt followed by the tokens generated so far
+    pub tokens: &'a [u32],
+    pub prompt_len: usize,
+}
+
+impl<'a> History<'a> {
+    pub fn new(tokens: &'a [u32], prompt_len: usize) -> Self {
+        Self { tokens, prompt_len }
+    }
+
+    pub fn generated(&self) -> &'a [u32] {
+        &self.tokens[self.prompt_len.min(self.tokens.len())..]
+    }
+}
+
+/// One step of a [`LogitsPipeline`]. Transforms that rule a token out set its
+/// logit to negative infinity, so later steps and the sampler never pick it.
+pub trait LogitsTransform: Send + Sync {
+    fn apply(&self, logits: &mut [f32], history: History<'_>);
+}
+
+/// Makes tokens seen in the last `last_n` tokens, prompt included, less likely
+#[derive(Debug, Clone)]
+pub struct RepeatPenalty {
+    pub penalty: f32,
+    pub last_n: usize,
+}
+
+impl LogitsTransform for RepeatPenalty {
+    fn apply(&self, logits: &mut [f32], history: History<'_>) {
+        let start_at = history.tokens.len().saturating_sub(self.last_n);
+        for &token_id in &history.tokens[start_at..] {
+            // Scaling a negative logit by the penalty makes the token less likely too
+            if let Some(score) = logits.get_mut(token_id as usize) {
+                if *score < 0.0 {
+                    *score *= self.penalty;
+                } else {
+                    *score /= self.penalty;
+                }
+            }
+        }
+    }
+}
+
+/// OpenAI's presence and frequency penalties, which only count generated tokens
+#[derive(Debug, Clone)]
+pub struct OccurrencePenalty {
+    pub presence: f32,
+    pub frequency: f32,
+}
+
+impl LogitsTransform for OccurrencePenalty {
+    fn apply(&self, logits: &mut [f32], history: History<'_>) {
+        let mut counts: HashMap<u32, usize> = HashMap::new();
+        for &token_id in history.generated() {
+            *counts.entry(token_id).or_default() += 1;
+        }
+        for (token_id, count) in counts {
+            if let Some(score) = logits.get_mut(token_id as usize) {
+                *score -= self.presence + self.frequency * count as f32;
+            }
+        }
+    }
+}
+
+/// Adds a fixed bias to the logits of single tokens
+#[derive(Debug, Clone)]
+pub struct LogitBias(pub HashMap<u32, f32>);
+
+impl LogitsTransform for LogitBias {
+    fn apply(&self, logits: &mut [f32], _history: History<'_>) {
+        for (&token_id, &bias) in &self.0 {
+            if let Some(score) = logits.get_mut(token_id as usize) {
+                *score += bias;
+            }
+        }
+    }
+}
+
+/// Keeps the `k` most likely tokens, along with any tied with the last of them
+#[derive(Debug, Clone)]
+pub struct TopK(pub usize);
+
+impl LogitsTransform for TopK {
+    fn apply(&self, logits: &mut [f32], _history: History<'_>) {
+        if self.0 == 0 || self.0 >= logits.len() {
+            return;
+        }
+        let mut sorted = logits.to_vec();
+        sorted.select_nth_unstable_by(self.0 - 1, |a, b| b.total_cmp(a));
+        let threshold = sorted[self.0 - 1];
+        mask(logits, |_, score| score < threshold);
+    }
+}
+
+/// Keeps the tokens at least `min_p` times as likely as the most likely one
+#[derive(Debug, Clone)]
+pub struct MinP(pub f32);
+
+impl LogitsTransform for MinP {
+    fn apply(&self, logits: &mut [f32], _history: History<'_>) {
+        if self.0 <= 0.0 {
+            return;
+        }
+        // p >= min_p * p_max holds exactly when logit >= max_logit + ln(min_p)
+        let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let threshold = max + self.0.ln();
+        mask(logits, |_, score| score < threshold);
+    }
+}
+
+/// Locally typical sampling: keeps the tokens whose surprise is closest to
+/// the entropy of the distribution, until they cover `typical_p` of it.
+#[derive(Debug, Clone)]
+pub struct TypicalP(pub f32);
+
+impl LogitsTransform for TypicalP {
+    fn apply(&self, logits: &mut [f32], _history: History<'_>) {
+        if self.0 >= 1.0 {
+            return;
+        }
+        let probs = softmax(logits);
+        let entropy: f32 = probs.iter().filter(|&&p| p > 0.0).map(|&p| -p * p.ln()).sum();
+
+        let mut order: Vec<usize> = (0..probs.len()).filter(|&i| probs[i] > 0.0).collect();
+        order.sort_by(|&a, &b| {
+            let distance = |i: usize| (-probs[i].ln() - entropy).abs();
+            distance(a).total_cmp(&distance(b))
+        });
+
+        let mut keep = vec![false; probs.len()];
+        let mut cumulative = 0.0;
+        for i in order {
+            keep[i] = true;
+            cumulative += probs[i];
+            if cumulative >= self.0 {
+                break;
+            }
+        }
+        mask(logits, |i, _| !keep[i]);
+    }
+}
+
+/// Transforms applied in order to the logits of every step before sampling
+#[derive(Default)]
+pub struct LogitsPipeline {
+    transforms: Vec<Box<dyn LogitsTransform>>,
+}
+
+impl LogitsPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, transform: impl LogitsTransform + 'static) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    pub fn apply_slice(&self, logits: &mut [f32], history: History<'_>) {
+        for transform in &self.transforms {
+            transform.apply(logits, history);
+        }
+    }
+
+    /// Applies the pipeline to a 1D logits tensor
+    pub fn apply(&self, logits: Tensor, history: History<'_>) -> Result<Tensor> {
+        if self.is_empty() {
+            return Ok(logits);
+        }
+        let mut logits_vec = logits.to_vec1::<f32>()?;
+        self.apply_slice(&mut logits_vec, history);
+        Ok(Tensor::new(&logits_vec[..], logits.device())?.reshape(logits.shape())?)
+    }
+}
+
+/// Rules out the tokens `masked` picks by index and logit
+fn mask(logits: &mut [f32], masked: impl Fn(usize, f32) -> bool) {
+    for (i, score) in logits.iter_mut().enumerate() {
+        if masked(i, *score) {
+            *score = f32::NEG_INFINITY;
+        }
+    }
+}
+
+/// Probabilities of the logits, with masked tokens at 0
+pub fn softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    if max == f32::NEG_INFINITY {
+        return vec![0.0; logits.len()];
+    }
+    let exps: Vec<f32> = logits.iter().map(|&score| (score - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.into_iter().map(|e| e / sum).collect()
+}
+
+/// Log probabilities of the logits, computed without underflow for unlikely tokens
+pub fn log_softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    if max == f32::NEG_INFINITY {
+        return vec![f32::NEG_INFINITY; logits.len()];
+    }
+    let log_sum = logits.iter().map(|&score| (score - max).exp()).sum::<f32>().ln() + max;
+    logits.iter().map(|&score| score - log_sum).collect()
+}
+
+/// The `n` highest scoring tokens, best first
+pub fn top_tokens(scores: &[f32], n: usize) -> Vec<(u32, f32)> {
+    let mut ranked: Vec<(u32, f32)> = scores.iter().enumerate().map(|(i, &score)| (i as u32, score)).collect();
+    let n = n.min(ranked.len());
+    if n == 0 {
+        return Vec::new();
+    }
+    ranked.select_nth_unstable_by(n - 1, |a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked.truncate(n);
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked
+}
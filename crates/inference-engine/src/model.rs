@@ -53,6 +53,15 @@ impl Model {
             Self::V3(m) => m.forward(input_ids, pos),
         }
     }
+
+    /// Drops the cached keys and values so the next forward pass starts a new sequence
+    pub fn clear_kv_cache(&mut self) {
+        match self {
+            Self::V1(m) => m.clear_kv_cache(),
+            Self::V2(m) => m.clear_kv_cache(),
+            Self::V3(m) => m.clear_kv_cache(),
+        }
+    }
 }
 
 impl Which {
@@ -89,6 +89,33 @@ pub struct Message {
     pub name: Option<String>,
 }
 
+impl Message {
+    /// A message with plain text content
+    pub fn text(role: &str, content: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: Some(MessageContent(Either::Left(content.to_string()))),
+            name: None,
+        }
+    }
+
+    /// The text of the message, joining the text parts of structured content
+    pub fn text_content(&self) -> String {
+        match self.content.as_ref().map(|content| &content.0) {
+            Some(Either::Left(text)) => text.clone(),
+            Some(Either::Right(parts)) => parts
+                .iter()
+                .filter_map(|part| match part.get("text").map(|text| &text.0) {
+                    Some(Either::Left(text)) => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => String::new(),
+        }
+    }
+}
+
 /// Stop token configuration for generation
 #[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
 #[serde(untagged)]
@@ -99,6 +126,15 @@ pub enum StopTokens {
     Single(String),
 }
 
+impl StopTokens {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            StopTokens::Multi(stop) => stop,
+            StopTokens::Single(stop) => vec![stop],
+        }
+    }
+}
+
 /// Default value helper
 pub fn default_false() -> bool {
     false
@@ -125,6 +161,9 @@ pub struct ChatCompletionRequest {
     #[serde(default = "default_false")]
     #[schema(example = false)]
     pub logprobs: bool,
+    /// Number of most likely alternatives to report for each token, up to 20, requires `logprobs`
+    #[schema(example = 2)]
+    pub top_logprobs: Option<usize>,
     #[schema(example = 256)]
     pub max_tokens: Option<usize>,
     #[serde(rename = "n")]
@@ -135,8 +174,127 @@ pub struct ChatCompletionRequest {
     pub temperature: Option<f64>,
     #[schema(example = 0.9)]
     pub top_p: Option<f64>,
+    /// Only sample from the `top_k` most likely tokens
+    #[schema(example = 40)]
+    pub top_k: Option<usize>,
+    /// Only sample from tokens at least `min_p` times as likely as the most likely one
+    #[schema(example = 0.05)]
+    pub min_p: Option<f32>,
+    /// Locally typical sampling over the tokens covering `typical_p` of the distribution
+    #[schema(example = 0.95)]
+    pub typical_p: Option<f32>,
+    /// Maps token ids to a bias between -100 and 100 added to their logits
+    #[schema(example = json!({"107": -100}))]
+    pub logit_bias: Option<HashMap<u32, f32>>,
+    /// Makes sampling reproducible
+    #[schema(example = 42)]
+    pub seed: Option<u64>,
+    /// Penalizes recently seen tokens, 1.0 disables it
+    #[schema(example = 1.1)]
+    pub repeat_penalty: Option<f32>,
+    /// Between -2.0 and 2.0, positive values penalize tokens that were generated before
+    #[schema(example = 0.0)]
+    pub presence_penalty: Option<f32>,
+    /// Between -2.0 and 2.0, positive values penalize tokens by how often they were generated
+    #[schema(example = 0.0)]
+    pub frequency_penalty: Option<f32>,
     #[schema(example = false)]
     pub stream: Option<bool>,
+    /// Up to 4 sequences where generation stops, left out of the returned text
+    #[schema(example = json!(["\n\n"]))]
+    pub stop: Option<StopTokens>,
+}
+
+/// Legacy text completion request, which continues a raw prompt without a chat template
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct CompletionRequest {
+    #[schema(example = "The capital of France is")]
+    pub prompt: String,
+    #[schema(example = "gemma-3-1b")]
+    #[serde(default = "default_model")]
+    pub model: String,
+    #[schema(example = 16)]
+    pub max_tokens: Option<usize>,
+    #[schema(example = 0.7)]
+    pub temperature: Option<f64>,
+    #[schema(example = 0.9)]
+    pub top_p: Option<f64>,
+    /// Only sample from the `top_k` most likely tokens
+    #[schema(example = 40)]
+    pub top_k: Option<usize>,
+    /// Only sample from tokens at least `min_p` times as likely as the most likely one
+    #[schema(example = 0.05)]
+    pub min_p: Option<f32>,
+    /// Locally typical sampling over the tokens covering `typical_p` of the distribution
+    #[schema(example = 0.95)]
+    pub typical_p: Option<f32>,
+    /// Maps token ids to a bias between -100 and 100 added to their logits
+    #[schema(example = json!({"107": -100}))]
+    pub logit_bias: Option<HashMap<u32, f32>>,
+    /// Makes sampling reproducible
+    #[schema(example = 42)]
+    pub seed: Option<u64>,
+    /// Penalizes recently seen tokens, 1.0 disables it
+    #[schema(example = 1.1)]
+    pub repeat_penalty: Option<f32>,
+    /// Between -2.0 and 2.0, positive values penalize tokens that were generated before
+    #[schema(example = 0.0)]
+    pub presence_penalty: Option<f32>,
+    /// Between -2.0 and 2.0, positive values penalize tokens by how often they were generated
+    #[schema(example = 0.0)]
+    pub frequency_penalty: Option<f32>,
+    /// Up to 4 sequences where generation stops, left out of the returned text
+    #[schema(example = json!(["\n\n"]))]
+    pub stop: Option<StopTokens>,
+    /// Return the prompt ahead of the generated text
+    #[serde(default = "default_false")]
+    #[schema(example = false)]
+    pub echo: bool,
+}
+
+/// Text completion choice
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CompletionChoice {
+    pub index: usize,
+    pub text: String,
+    pub finish_reason: String,
+}
+
+/// Text completion response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CompletionResponse {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<CompletionChoice>,
+    pub usage: Usage,
+    pub timing: Timing,
+}
+
+/// Log probability of a token the model considered
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f32,
+    /// UTF-8 bytes of the token, which can be part of a character
+    pub bytes: Vec<u8>,
+}
+
+/// Log probability of a generated token and its most likely alternatives
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LogprobContent {
+    pub token: String,
+    pub logprob: f32,
+    /// UTF-8 bytes of the token, which can be part of a character
+    pub bytes: Vec<u8>,
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+/// Log probabilities of the tokens of a choice, requested with `logprobs`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChoiceLogprobs {
+    pub content: Vec<LogprobContent>,
 }
 
 /// Chat completion choice
@@ -144,6 +302,7 @@ pub struct ChatCompletionRequest {
 pub struct ChatCompletionChoice {
     pub index: usize,
     pub message: Message,
+    pub logprobs: Option<ChoiceLogprobs>,
     pub finish_reason: String,
 }
 
@@ -156,6 +315,7 @@ pub struct ChatCompletionResponse {
     pub model: String,
     pub choices: Vec<ChatCompletionChoice>,
     pub usage: Usage,
+    pub timing: Timing,
 }
 
 /// Token usage information
@@ -164,4 +324,49 @@ pub struct Usage {
     pub prompt_tokens: usize,
     pub completion_tokens: usize,
     pub total_tokens: usize,
-}
\ No newline at end of file
+}
+
+/// Generation speed, reported next to `usage` as an extension to OpenAI's format
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Timing {
+    /// Time until the first generated token, including the prompt prefill
+    pub time_to_first_token_ms: f64,
+    /// Decoding speed after the first token
+    pub tokens_per_second: f64,
+}
+
+/// Incremental message content carried by a streamed chunk
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct Delta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+/// Chat completion chunk choice
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChatCompletionChunkChoice {
+    pub index: usize,
+    pub delta: Delta,
+    /// Log probabilities of the tokens in this chunk, requested with `logprobs`
+    pub logprobs: Option<ChoiceLogprobs>,
+    /// Set on the last chunk of the choice, `null` before that
+    pub finish_reason: Option<String>,
+}
+
+/// Chat completion chunk sent as a server-sent event when `stream` is true
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChatCompletionChunk {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<ChatCompletionChunkChoice>,
+    /// Only set on the last chunk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// Only set on the last chunk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timing: Option<Timing>,
+}
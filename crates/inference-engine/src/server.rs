@@ -1,16 +1,28 @@
 use axum::{
     extract::State,
     http::StatusCode,
-    routing::{get, post},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::post,
     Json, Router,
 };
-use std::{net::SocketAddr, sync::Arc};
-use tokio::sync::Mutex;
+use futures::stream::{self, Stream, StreamExt};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio_stream::wrappers::UnboundedReceiverStream;
 use tower_http::cors::{Any, CorsLayer};
 use uuid::Uuid;
 
-use crate::openai_types::{ChatCompletionChoice, ChatCompletionRequest, ChatCompletionResponse, Message, MessageContent, Usage};
-use crate::text_generation::TextGeneration;
+use crate::chat_template::ChatTemplate;
+use crate::openai_types::{
+    ChatCompletionChoice, ChatCompletionChunk, ChatCompletionChunkChoice, ChatCompletionRequest,
+    ChatCompletionResponse, ChoiceLogprobs, CompletionChoice, CompletionRequest, CompletionResponse, Delta,
+    LogprobContent, Message, MessageContent, StopTokens, Timing, TopLogprob, Usage,
+};
+use crate::text_generation::{FinishReason, GenerationParams, GenerationStats, TextGeneration, TokenLogprob};
 use either::Either;
 
 // Application state shared between handlers
@@ -18,95 +30,405 @@ use either::Either;
 pub struct AppState {
     pub text_generation: Arc<Mutex<TextGeneration>>,
     pub model_id: String,
+    pub chat_template: Arc<ChatTemplate>,
+    /// Most choices a chat completion request may ask for
+    pub max_choices: usize,
 }
 
 // Chat completions endpoint handler
 pub async fn chat_completions(
     State(state): State<AppState>,
     Json(request): Json<ChatCompletionRequest>,
-) -> Result<Json<ChatCompletionResponse>, (StatusCode, Json<serde_json::Value>)> {
-    let mut prompt = String::new();
-
-    // Convert messages to a prompt string
-    for message in &request.messages {
-        let role = &message.role;
-        let content = match &message.content {
-            Some(content) => match &content.0 {
-                Either::Left(text) => text.clone(),
-                Either::Right(_) => "".to_string(), // Handle complex content if needed
-            },
-            None => "".to_string(),
-        };
-
-        // Format based on role
-        match role.as_str() {
-            "system" => prompt.push_str(&format!("System: {}\n", content)),
-            "user" => prompt.push_str(&format!("User: {}\n", content)),
-            "assistant" => prompt.push_str(&format!("Assistant: {}\n", content)),
-            _ => prompt.push_str(&format!("{}: {}\n", role, content)),
-        }
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let prompt = state.chat_template.render(&request.messages).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": {
+                    "message": format!("Failed to apply the chat template: {}", e),
+                    "type": "invalid_request_error"
+                }
+            })),
+        )
+    })?;
+
+    let params = generation_params(GenerationParams::from(&request))?;
+    if request.n_choices == 0 || request.n_choices > state.max_choices {
+        return Err(invalid_request(format!("n must be between 1 and {}", state.max_choices)));
     }
 
-    // Add the assistant prefix for the response
-    prompt.push_str("Assistant: ");
+    if request.stream.unwrap_or(false) {
+        return Ok(stream_chat_completion(state, request, prompt, params).into_response());
+    }
 
-    // Capture the output
-    let mut output = Vec::new();
+    let mut choices = Vec::with_capacity(request.n_choices);
+    let mut stats = Vec::with_capacity(request.n_choices);
     {
         let mut text_gen = state.text_generation.lock().await;
-
-        // Buffer to capture the output
-        let mut buffer = Vec::new();
-
-        // Run text generation
         let max_tokens = request.max_tokens.unwrap_or(1000);
-        let result = text_gen.run_with_output(&prompt, max_tokens, &mut buffer);
 
-        if let Err(e) = result {
-            return Err((
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({
-                    "error": {
-                        "message": "The OpenAI API is currently not supported due to compatibility issues with the tensor operations. Please use the CLI mode instead with: cargo run --bin inference-engine -- --prompt \"Your prompt here\"",
-                        "type": "unsupported_api"
-                    }
-                })),
-            ));
-        }
+        // The model's KV cache cannot be forked, so every choice is sampled on its own after a fresh prefill
+        for index in 0..request.n_choices {
+            let choice_params = params.for_choice(index);
+            let mut output = String::new();
+            let mut logprobs = Vec::new();
+            let result = text_gen.run_with_callback(&prompt, max_tokens, &choice_params, |text, text_logprobs| {
+                output.push_str(text);
+                logprobs.extend_from_slice(text_logprobs);
+                ControlFlow::Continue(())
+            });
 
-        // Convert buffer to string
-        if let Ok(text) = String::from_utf8(buffer) {
-            output.push(text);
+            let choice_stats = result.map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(serde_json::json!({
+                        "error": { "message": e.to_string(), "type": "server_error" }
+                    })),
+                )
+            })?;
+            choices.push(ChatCompletionChoice {
+                index,
+                message: Message {
+                    role: "assistant".to_string(),
+                    content: Some(MessageContent(Either::Left(output))),
+                    name: None,
+                },
+                logprobs: choice_params.logprobs.map(|_| choice_logprobs(&logprobs)),
+                finish_reason: choice_stats.finish_reason.as_openai_str().to_string(),
+            });
+            stats.push(choice_stats);
         }
     }
 
     // Create response
     let response = ChatCompletionResponse {
-        id: format!("chatcmpl-{}", Uuid::new_v4().to_string().replace("-", "")),
+        id: completion_id("chatcmpl"),
         object: "chat.completion".to_string(),
-        created: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs(),
+        created: now_secs(),
+        model: request.model,
+        choices,
+        usage: usage(&stats),
+        timing: timing(&stats),
+    };
+
+    // Return the response as JSON
+    Ok(Json(response).into_response())
+}
+
+// Legacy text completions endpoint handler
+pub async fn completions(
+    State(state): State<AppState>,
+    Json(request): Json<CompletionRequest>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let params = generation_params(GenerationParams::from(&request))?;
+    let mut buffer = Vec::new();
+    let stats = {
+        let mut text_gen = state.text_generation.lock().await;
+        let max_tokens = request.max_tokens.unwrap_or(16);
+        text_gen.run_with_output(&request.prompt, max_tokens, &params, &mut buffer).map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "error": { "message": e.to_string(), "type": "server_error" }
+                })),
+            )
+        })?
+    };
+    let generated = String::from_utf8_lossy(&buffer).into_owned();
+
+    // Only echo the prompt when asked to, the generated text never includes it
+    let text = if request.echo {
+        format!("{}{}", request.prompt, generated)
+    } else {
+        generated
+    };
+
+    let response = CompletionResponse {
+        id: completion_id("cmpl"),
+        object: "text_completion".to_string(),
+        created: now_secs(),
         model: request.model,
-        choices: vec![ChatCompletionChoice {
+        choices: vec![CompletionChoice {
             index: 0,
-            message: Message {
-                role: "assistant".to_string(),
-                content: Some(MessageContent(Either::Left(output.join("")))),
-                name: None,
-            },
-            finish_reason: "stop".to_string(),
+            text,
+            finish_reason: stats.finish_reason.as_openai_str().to_string(),
         }],
-        usage: Usage {
-            prompt_tokens: prompt.len() / 4, // Rough estimate
-            completion_tokens: output.join("").len() / 4, // Rough estimate
-            total_tokens: (prompt.len() + output.join("").len()) / 4, // Rough estimate
-        },
+        usage: usage(std::slice::from_ref(&stats)),
+        timing: timing(std::slice::from_ref(&stats)),
     };
 
-    // Return the response as JSON
-    Ok(Json(response))
+    Ok(Json(response).into_response())
+}
+
+/// Progress of a streamed generation, sent from the blocking generation task.
+enum StreamUpdate {
+    Started(usize),
+    Text(usize, String, Vec<TokenLogprob>),
+    /// A choice finished, the last one also carries the stats of every choice
+    Finished(usize, FinishReason, Option<Vec<GenerationStats>>),
+    Failed(String),
+}
+
+/// Fields repeated on every chunk of one streamed completion.
+struct ChunkHeader {
+    id: String,
+    created: u64,
+    model: String,
+}
+
+impl ChunkHeader {
+    fn chunk(&self, index: usize, delta: Delta, finish_reason: Option<&str>) -> ChatCompletionChunk {
+        ChatCompletionChunk {
+            id: self.id.clone(),
+            object: "chat.completion.chunk".to_string(),
+            created: self.created,
+            model: self.model.clone(),
+            choices: vec![ChatCompletionChunkChoice {
+                index,
+                delta,
+                logprobs: None,
+                finish_reason: finish_reason.map(str::to_string),
+            }],
+            usage: None,
+            timing: None,
+        }
+    }
+}
+
+/// Streams a completion as `chat.completion.chunk` events while the tokens are
+/// generated. The choices stream one after another, each opening with its role
+/// and closing with its finish reason, and the stream ends with `[DONE]`.
+fn stream_chat_completion(
+    state: AppState,
+    request: ChatCompletionRequest,
+    prompt: String,
+    params: GenerationParams,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let header = ChunkHeader {
+        id: completion_id("chatcmpl"),
+        created: now_secs(),
+        model: request.model,
+    };
+    let max_tokens = request.max_tokens.unwrap_or(1000);
+    let with_logprobs = params.logprobs.is_some();
+
+    let n_choices = request.n_choices;
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let text_generation = state.text_generation.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut text_gen = text_generation.blocking_lock();
+        let mut stats = Vec::with_capacity(n_choices);
+        for index in 0..n_choices {
+            // A failed send means the client disconnected, which stops generation
+            if tx.send(StreamUpdate::Started(index)).is_err() {
+                return;
+            }
+            let result = text_gen.run_with_callback(&prompt, max_tokens, &params.for_choice(index), |text, logprobs| {
+                match tx.send(StreamUpdate::Text(index, text.to_string(), logprobs.to_vec())) {
+                    Ok(()) => ControlFlow::Continue(()),
+                    Err(_) => ControlFlow::Break(()),
+                }
+            });
+            let choice_stats = match result {
+                Ok(stats) => stats,
+                Err(e) => {
+                    let _ = tx.send(StreamUpdate::Failed(e.to_string()));
+                    return;
+                }
+            };
+            let finish_reason = choice_stats.finish_reason;
+            stats.push(choice_stats);
+            let all_stats = (index + 1 == n_choices).then(|| std::mem::take(&mut stats));
+            if tx.send(StreamUpdate::Finished(index, finish_reason, all_stats)).is_err() {
+                return;
+            }
+        }
+    });
+
+    let updates = UnboundedReceiverStream::new(rx).map(move |update| match update {
+        StreamUpdate::Started(index) => Event::default().json_data(header.chunk(
+            index,
+            Delta {
+                role: Some("assistant".to_string()),
+                content: Some(String::new()),
+            },
+            None,
+        )),
+        StreamUpdate::Text(index, text, logprobs) => {
+            let mut chunk = header.chunk(
+                index,
+                Delta {
+                    content: Some(text),
+                    ..Delta::default()
+                },
+                None,
+            );
+            chunk.choices[0].logprobs = with_logprobs.then(|| choice_logprobs(&logprobs));
+            Event::default().json_data(chunk)
+        }
+        // The last chunk also reports usage, as OpenAI does with `include_usage`
+        StreamUpdate::Finished(index, finish_reason, all_stats) => Event::default().json_data(ChatCompletionChunk {
+            usage: all_stats.as_deref().map(usage),
+            timing: all_stats.as_deref().map(timing),
+            ..header.chunk(index, Delta::default(), Some(finish_reason.as_openai_str()))
+        }),
+        StreamUpdate::Failed(message) => {
+            tracing::error!("Streaming generation failed: {}", message);
+            Event::default().json_data(serde_json::json!({
+                "error": { "message": message, "type": "server_error" }
+            }))
+        }
+    });
+    let done = stream::once(async { Ok(Event::default().data("[DONE]")) });
+
+    Sse::new(updates.chain(done)).keep_alive(KeepAlive::default())
+}
+
+/// Most stop sequences a request may set, as in OpenAI's API
+const MAX_STOP_SEQUENCES: usize = 4;
+/// Most alternatives a request may ask for per token, as in OpenAI's API
+const MAX_TOP_LOGPROBS: usize = 20;
+
+impl From<&ChatCompletionRequest> for GenerationParams {
+    fn from(request: &ChatCompletionRequest) -> Self {
+        Self {
+            stop: request.stop.clone().map(StopTokens::into_vec).unwrap_or_default(),
+            temperature: request.temperature,
+            top_p: request.top_p,
+            top_k: request.top_k,
+            min_p: request.min_p,
+            typical_p: request.typical_p,
+            seed: request.seed,
+            repeat_penalty: request.repeat_penalty,
+            presence_penalty: request.presence_penalty,
+            frequency_penalty: request.frequency_penalty,
+            logit_bias: request.logit_bias.clone().unwrap_or_default(),
+            logprobs: request.logprobs.then(|| request.top_logprobs.unwrap_or(0)),
+        }
+    }
+}
+
+impl From<&CompletionRequest> for GenerationParams {
+    fn from(request: &CompletionRequest) -> Self {
+        Self {
+            stop: request.stop.clone().map(StopTokens::into_vec).unwrap_or_default(),
+            temperature: request.temperature,
+            top_p: request.top_p,
+            top_k: request.top_k,
+            min_p: request.min_p,
+            typical_p: request.typical_p,
+            seed: request.seed,
+            repeat_penalty: request.repeat_penalty,
+            presence_penalty: request.presence_penalty,
+            frequency_penalty: request.frequency_penalty,
+            logit_bias: request.logit_bias.clone().unwrap_or_default(),
+            logprobs: None,
+        }
+    }
+}
+
+/// Rejects request settings outside the ranges OpenAI accepts
+fn generation_params(params: GenerationParams) -> Result<GenerationParams, (StatusCode, Json<serde_json::Value>)> {
+    let in_range = |value: Option<f32>, min: f32, max: f32| value.is_none_or(|v| (min..=max).contains(&v));
+    let problem = if params.stop.len() > MAX_STOP_SEQUENCES {
+        Some(format!("At most {} stop sequences are supported", MAX_STOP_SEQUENCES))
+    } else if params.logprobs.is_some_and(|n| n > MAX_TOP_LOGPROBS) {
+        Some(format!("top_logprobs must be at most {}", MAX_TOP_LOGPROBS))
+    } else if params.temperature.is_some_and(|t| !(0.0..=2.0).contains(&t)) {
+        Some("temperature must be between 0 and 2".to_string())
+    } else if params.top_p.is_some_and(|p| p <= 0.0 || p > 1.0) {
+        Some("top_p must be greater than 0 and at most 1".to_string())
+    } else if params.top_k == Some(0) {
+        Some("top_k must be at least 1".to_string())
+    } else if !in_range(params.min_p, 0.0, 1.0) {
+        Some("min_p must be between 0 and 1".to_string())
+    } else if params.typical_p.is_some_and(|p| p <= 0.0 || p > 1.0) {
+        Some("typical_p must be greater than 0 and at most 1".to_string())
+    } else if params.logit_bias.values().any(|&bias| !(-100.0..=100.0).contains(&bias)) {
+        Some("logit_bias values must be between -100 and 100".to_string())
+    } else if params.repeat_penalty.is_some_and(|p| p <= 0.0) {
+        Some("repeat_penalty must be greater than 0".to_string())
+    } else if !in_range(params.presence_penalty, -2.0, 2.0) {
+        Some("presence_penalty must be between -2 and 2".to_string())
+    } else if !in_range(params.frequency_penalty, -2.0, 2.0) {
+        Some("frequency_penalty must be between -2 and 2".to_string())
+    } else {
+        None
+    };
+
+    match problem {
+        Some(message) => Err(invalid_request(message)),
+        None => Ok(params),
+    }
+}
+
+fn invalid_request(message: String) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({
+            "error": { "message": message, "type": "invalid_request_error" }
+        })),
+    )
+}
+
+fn choice_logprobs(logprobs: &[TokenLogprob]) -> ChoiceLogprobs {
+    let token = |logprob: &TokenLogprob| String::from_utf8_lossy(&logprob.bytes).into_owned();
+    ChoiceLogprobs {
+        content: logprobs
+            .iter()
+            .map(|logprob| LogprobContent {
+                token: token(logprob),
+                logprob: logprob.logprob,
+                bytes: logprob.bytes.clone(),
+                top_logprobs: logprob
+                    .top_logprobs
+                    .iter()
+                    .map(|top| TopLogprob {
+                        token: token(top),
+                        logprob: top.logprob,
+                        bytes: top.bytes.clone(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+/// Usage of a request's choices, which share one prompt but each add their own completion
+fn usage(stats: &[GenerationStats]) -> Usage {
+    let prompt_tokens = stats.first().map_or(0, |stats| stats.prompt_tokens);
+    let completion_tokens = stats.iter().map(|stats| stats.completion_tokens).sum();
+    Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+    }
+}
+
+/// Timing of a request's choices: how long the first token of the first choice
+/// took and the average decoding speed of all of them
+fn timing(stats: &[GenerationStats]) -> Timing {
+    let time_to_first_token = stats.first().and_then(|stats| stats.time_to_first_token);
+    let tokens_per_second = match stats.len() {
+        0 => 0.0,
+        n => stats.iter().map(GenerationStats::tokens_per_second).sum::<f64>() / n as f64,
+    };
+    Timing {
+        time_to_first_token_ms: time_to_first_token.unwrap_or_default().as_secs_f64() * 1000.0,
+        tokens_per_second,
+    }
+}
+
+fn completion_id(prefix: &str) -> String {
+    format!("{}-{}", prefix, Uuid::new_v4().to_string().replace("-", ""))
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
 }
 
 // Create the router with the chat completions endpoint
@@ -120,6 +442,7 @@ pub fn create_router(app_state: AppState) -> Router {
     Router::new()
         // OpenAI compatible endpoints
         .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/completions", post(completions))
         // Add more endpoints as needed
         .layer(cors)
         .with_state(app_state)
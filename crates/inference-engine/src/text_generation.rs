@@ -1,19 +1,133 @@
 use anyhow::{Error as E, Result};
 use candle_core::{DType, Device, Tensor};
-use candle_transformers::generation::LogitsProcessor;
+use candle_transformers::generation::{LogitsProcessor, Sampling};
 use tokenizers::Tokenizer;
-use std::io::Write;
-
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+use crate::logits_pipeline::{
+    log_softmax, top_tokens, History, LogitBias, LogitsPipeline, MinP, OccurrencePenalty, RepeatPenalty,
+    TopK, TypicalP,
+};
 use crate::model::Model;
 use crate::token_output_stream::TokenOutputStream;
 
+/// Settings for a single generation request. Sampling settings left unset
+/// fall back to the ones the pipeline was created with.
+#[derive(Debug, Clone, Default)]
+pub struct GenerationParams {
+    /// Generation stops at the first of these strings, which is left out of the output
+    pub stop: Vec<String>,
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    /// Only sample from the `top_k` most likely tokens
+    pub top_k: Option<usize>,
+    /// Only sample from tokens at least `min_p` times as likely as the most likely one
+    pub min_p: Option<f32>,
+    /// Locally typical sampling over the tokens covering `typical_p` of the distribution
+    pub typical_p: Option<f32>,
+    /// Makes sampling reproducible; without one every run uses a different seed
+    pub seed: Option<u64>,
+    /// Penalizes tokens seen in the last `repeat_last_n` tokens, prompt included
+    pub repeat_penalty: Option<f32>,
+    /// Subtracted once from the logits of every token already generated
+    pub presence_penalty: Option<f32>,
+    /// Subtracted from the logits of generated tokens for each time they were generated
+    pub frequency_penalty: Option<f32>,
+    /// Added to the logits of the given token ids
+    pub logit_bias: HashMap<u32, f32>,
+    /// Report the log probability of every generated token along with this
+    /// many of the most likely alternatives
+    pub logprobs: Option<usize>,
+}
+
+impl GenerationParams {
+    /// Settings for one of several choices generated for the same request. A
+    /// fixed seed is offset by the choice's index so the samples still differ.
+    pub fn for_choice(&self, index: usize) -> Self {
+        Self {
+            seed: self.seed.map(|seed| seed.wrapping_add(index as u64)),
+            ..self.clone()
+        }
+    }
+}
+
+/// The log probability the model gave a token, before penalties and sampling filters
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenLogprob {
+    pub token: u32,
+    /// The token's text, which may be part of a multi-byte character
+    pub bytes: Vec<u8>,
+    pub logprob: f32,
+    /// The most likely tokens at this position, empty for the alternatives themselves
+    pub top_logprobs: Vec<TokenLogprob>,
+}
+
+/// Why a generation ended
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FinishReason {
+    /// The model produced an end-of-sequence or end-of-turn token
+    StopToken,
+    /// One of the requested stop sequences was generated
+    StopSequence,
+    /// The token limit was reached, which is where a run ends unless something stops it earlier
+    #[default]
+    Length,
+    /// The consumer of the generated text asked to stop, e.g. because a streaming client went away
+    Cancelled,
+}
+
+impl FinishReason {
+    /// The matching OpenAI `finish_reason`
+    pub fn as_openai_str(&self) -> &'static str {
+        match self {
+            Self::Length => "length",
+            Self::StopToken | Self::StopSequence | Self::Cancelled => "stop",
+        }
+    }
+}
+
+/// How a generation finished, with its token counts and timings
+#[derive(Debug, Clone, Default)]
+pub struct GenerationStats {
+    pub finish_reason: FinishReason,
+    pub prompt_tokens: usize,
+    /// Sampled tokens, including the end-of-turn token that stopped generation
+    pub completion_tokens: usize,
+    /// Time from the start of the run to the first sampled token, covering the prompt prefill
+    pub time_to_first_token: Option<Duration>,
+    pub total_time: Duration,
+}
+
+impl GenerationStats {
+    pub fn total_tokens(&self) -> usize {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// Decoding speed after the first token, so the prompt prefill is not counted
+    pub fn tokens_per_second(&self) -> f64 {
+        let decode_time = self.total_time.saturating_sub(self.time_to_first_token.unwrap_or_default());
+        let decoded_tokens = self.completion_tokens.saturating_sub(1);
+        if decoded_tokens == 0 || decode_time.is_zero() {
+            return 0.0;
+        }
+        decoded_tokens as f64 / decode_time.as_secs_f64()
+    }
+}
+
 pub struct TextGeneration {
     model: Model,
     device: Device,
     tokenizer: TokenOutputStream,
-    logits_processor: LogitsProcessor,
+    /// Seed for the next run that does not set its own
+    next_seed: u64,
+    temperature: Option<f64>,
+    top_p: Option<f64>,
     repeat_penalty: f32,
     repeat_last_n: usize,
+    /// The token that ends a sequence, `<eos>` unless the tokenizer config names another
+    eos_token: String,
 }
 
 impl TextGeneration {
@@ -28,20 +142,102 @@ impl TextGeneration {
         repeat_last_n: usize,
         device: &Device,
     ) -> Self {
-        let logits_processor = LogitsProcessor::new(seed, temp, top_p);
         Self {
             model,
             tokenizer: TokenOutputStream::new(tokenizer),
-            logits_processor,
+            next_seed: seed,
+            temperature: temp,
+            top_p,
             repeat_penalty,
             repeat_last_n,
+            eos_token: "<eos>".to_string(),
             device: device.clone(),
         }
     }
 
+    /// Stops generation on `token` instead of `<eos>`, e.g. the `eos_token`
+    /// from `tokenizer_config.json`. Tokens missing from the vocabulary are ignored.
+    pub fn set_eos_token(&mut self, token: &str) {
+        if self.tokenizer.get_token(token).is_some() {
+            self.eos_token = token.to_string();
+        } else {
+            tracing::warn!("Ignoring the eos_token {}, which is not in the tokenizer", token);
+        }
+    }
+
+    /// Builds the sampler for one run from the request's settings and the pipeline's defaults
+    fn logits_processor(&mut self, params: &GenerationParams) -> LogitsProcessor {
+        let temperature = params.temperature.or(self.temperature).filter(|t| *t >= 1e-7);
+        let top_p = params.top_p.or(self.top_p);
+        let sampling = match temperature {
+            None => Sampling::ArgMax,
+            // Top-k and the other filters run in the logits pipeline ahead of this
+            Some(temperature) => match top_p {
+                None => Sampling::All { temperature },
+                Some(p) => Sampling::TopP { p, temperature },
+            },
+        };
+        let seed = params.seed.unwrap_or_else(|| {
+            let seed = self.next_seed;
+            self.next_seed = self.next_seed.wrapping_add(1);
+            seed
+        });
+        LogitsProcessor::from_sampling(seed, sampling)
+    }
+
+    fn token_logprob(&self, token: u32, logprobs: &[f32], top_n: usize) -> TokenLogprob {
+        TokenLogprob {
+            token,
+            bytes: self.tokenizer.token_bytes(token),
+            logprob: logprobs[token as usize],
+            top_logprobs: top_tokens(logprobs, top_n)
+                .into_iter()
+                .map(|(token, logprob)| TokenLogprob {
+                    token,
+                    bytes: self.tokenizer.token_bytes(token),
+                    logprob,
+                    top_logprobs: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Builds the transforms applied to the logits of one run ahead of sampling
+    fn logits_pipeline(&self, params: &GenerationParams) -> LogitsPipeline {
+        let mut pipeline = LogitsPipeline::new();
+        let repeat_penalty = params.repeat_penalty.unwrap_or(self.repeat_penalty);
+        if repeat_penalty != 1. {
+            pipeline = pipeline.with(RepeatPenalty {
+                penalty: repeat_penalty,
+                last_n: self.repeat_last_n,
+            });
+        }
+        let presence = params.presence_penalty.unwrap_or(0.);
+        let frequency = params.frequency_penalty.unwrap_or(0.);
+        if presence != 0. || frequency != 0. {
+            pipeline = pipeline.with(OccurrencePenalty { presence, frequency });
+        }
+        if !params.logit_bias.is_empty() {
+            pipeline = pipeline.with(LogitBias(params.logit_bias.clone()));
+        }
+        if let Some(k) = params.top_k {
+            pipeline = pipeline.with(TopK(k));
+        }
+        if let Some(typical_p) = params.typical_p {
+            pipeline = pipeline.with(TypicalP(typical_p));
+        }
+        if let Some(min_p) = params.min_p {
+            pipeline = pipeline.with(MinP(min_p));
+        }
+        pipeline
+    }
+
     // Run text generation and print to stdout
     pub fn run(&mut self, prompt: &str, sample_len: usize) -> Result<()> {
         use std::io::Write;
+        let mut logits_processor = self.logits_processor(&GenerationParams::default());
+        let pipeline = self.logits_pipeline(&GenerationParams::default());
+        self.model.clear_kv_cache();
         self.tokenizer.clear();
         let mut tokens = self
             .tokenizer
@@ -56,18 +252,20 @@ impl TextGeneration {
             }
         }
         std::io::stdout().flush()?;
+        let prompt_len = tokens.len();
 
         let mut generated_tokens = 0usize;
-        let eos_token = match self.tokenizer.get_token("<eos>") {
+        let eos_token = match self.tokenizer.get_token(&self.eos_token) {
             Some(token) => token,
-            None => anyhow::bail!("cannot find the <eos> token"),
+            None => anyhow::bail!("cannot find the {} token", self.eos_token),
         };
 
         let eot_token = match self.tokenizer.get_token("<end_of_turn>") {
             Some(token) => token,
             None => {
                 println!(
-                    "Warning: <end_of_turn> token not found in tokenizer, using <eos> as a backup"
+                    "Warning: <end_of_turn> token not found in tokenizer, using {} as a backup",
+                    self.eos_token
                 );
                 eos_token
             }
@@ -81,31 +279,9 @@ impl TextGeneration {
             let input = Tensor::new(ctxt, &self.device)?.unsqueeze(0)?;
             let logits = self.model.forward(&input, start_pos)?;
             let logits = logits.squeeze(0)?.squeeze(0)?.to_dtype(DType::F32)?;
-            let logits = if self.repeat_penalty == 1. {
-                logits
-            } else {
-                let start_at = tokens.len().saturating_sub(self.repeat_last_n);
-
-                // Manual implementation of repeat penalty to avoid type conflicts
-                let mut logits_vec = logits.to_vec1::<f32>()?;
+            let logits = pipeline.apply(logits, History::new(&tokens, prompt_len))?;
 
-                for &token_id in &tokens[start_at..] {
-                    let token_id = token_id as usize;
-                    if token_id < logits_vec.len() {
-                        let score = logits_vec[token_id];
-                        let sign = if score < 0.0 { -1.0 } else { 1.0 };
-                        logits_vec[token_id] = sign * score / self.repeat_penalty;
-                    }
-                }
-
-                // Create a new tensor with the modified logits
-                let device = logits.device().clone();
-                let shape = logits.shape().clone();
-                let new_logits = Tensor::new(&logits_vec[..], &device)?;
-                new_logits.reshape(shape)?
-            };
-
-            let next_token = self.logits_processor.sample(&logits)?;
+            let next_token = logits_processor.sample(&logits)?;
             tokens.push(next_token);
             generated_tokens += 1;
             if next_token == eos_token || next_token == eot_token {
@@ -128,9 +304,42 @@ impl TextGeneration {
         Ok(())
     }
 
-    // Run text generation and write to a buffer
-    pub fn run_with_output(&mut self, prompt: &str, sample_len: usize, output: &mut Vec<u8>) -> Result<()> {
+    // Run text generation and write the generated text, without the prompt, to a buffer
+    pub fn run_with_output(
+        &mut self,
+        prompt: &str,
+        sample_len: usize,
+        params: &GenerationParams,
+        output: &mut Vec<u8>,
+    ) -> Result<GenerationStats> {
+        self.run_with_callback(prompt, sample_len, params, |text, _| {
+            output.extend_from_slice(text.as_bytes());
+            ControlFlow::Continue(())
+        })
+    }
+
+    /// Runs text generation, handing each piece of generated text to `on_text`
+    /// as soon as the tokenizer can decode it, along with the log probabilities
+    /// of the tokens it was decoded from when `params.logprobs` asks for them.
+    /// Generation is cancelled when `on_text` breaks, e.g. because a streaming
+    /// client went away.
+    pub fn run_with_callback<F>(
+        &mut self,
+        prompt: &str,
+        sample_len: usize,
+        params: &GenerationParams,
+        mut on_text: F,
+    ) -> Result<GenerationStats>
+    where
+        F: FnMut(&str, &[TokenLogprob]) -> ControlFlow<()>,
+    {
+        let start = Instant::now();
+        let mut logits_processor = self.logits_processor(params);
+        let pipeline = self.logits_pipeline(params);
+        // Every run prefills its prompt from scratch, so nothing cached by an earlier run may leak into it
+        self.model.clear_kv_cache();
         self.tokenizer.clear();
+        self.tokenizer.set_stop_sequences(params.stop.clone());
         let mut tokens = self
             .tokenizer
             .tokenizer()
@@ -139,139 +348,86 @@ impl TextGeneration {
             .get_ids()
             .to_vec();
 
-        // Write prompt tokens to output
-        for &t in tokens.iter() {
-            if let Some(t) = self.tokenizer.next_token(t)? {
-                write!(output, "{}", t)?;
-            }
-        }
+        // Generated text decodes in the context of the prompt without repeating it
+        self.tokenizer.push_context(&tokens);
+        let mut stats = GenerationStats {
+            prompt_tokens: tokens.len(),
+            ..GenerationStats::default()
+        };
+        // Log probabilities of tokens whose text the tokenizer has not returned yet
+        let mut pending_logprobs = Vec::new();
 
-        let mut generated_tokens = 0usize;
-        let eos_token = match self.tokenizer.get_token("<eos>") {
+        let eos_token = match self.tokenizer.get_token(&self.eos_token) {
             Some(token) => token,
-            None => anyhow::bail!("cannot find the <eos> token"),
+            None => anyhow::bail!("cannot find the {} token", self.eos_token),
         };
 
         let eot_token = match self.tokenizer.get_token("<end_of_turn>") {
             Some(token) => token,
             None => {
-                write!(output, "Warning: <end_of_turn> token not found in tokenizer, using <eos> as a backup")?;
+                tracing::warn!("<end_of_turn> token not found in tokenizer, using {} as a backup", self.eos_token);
                 eos_token
             }
         };
 
-        // Determine if we're using a Model3 (gemma-3) variant
-        let is_model3 = match &self.model {
-            Model::V3(_) => true,
-            _ => false,
-        };
-
-        // For Model3, we need to use a different approach
-        if is_model3 {
-            // For gemma-3 models, we'll generate one token at a time with the full context
-            let start_gen = std::time::Instant::now();
-
-            // Initial generation with the full prompt
-            let input = Tensor::new(tokens.as_slice(), &self.device)?.unsqueeze(0)?;
-            let mut logits = self.model.forward(&input, 0)?;
-            logits = logits.squeeze(0)?.squeeze(0)?.to_dtype(DType::F32)?;
-
-            for _ in 0..sample_len {
-                // Apply repeat penalty if needed
-                let current_logits = if self.repeat_penalty == 1. {
-                    logits.clone()
-                } else {
-                    let start_at = tokens.len().saturating_sub(self.repeat_last_n);
-
-                    // Manual implementation of repeat penalty to avoid type conflicts
-                    let mut logits_vec = logits.to_vec1::<f32>()?;
-
-                    for &token_id in &tokens[start_at..] {
-                        let token_id = token_id as usize;
-                        if token_id < logits_vec.len() {
-                            let score = logits_vec[token_id];
-                            let sign = if score < 0.0 { -1.0 } else { 1.0 };
-                            logits_vec[token_id] = sign * score / self.repeat_penalty;
-                        }
-                    }
-
-                    // Create a new tensor with the modified logits
-                    let device = logits.device().clone();
-                    let shape = logits.shape().clone();
-                    let new_logits = Tensor::new(&logits_vec[..], &device)?;
-                    new_logits.reshape(shape)?
-                };
-
-                let next_token = self.logits_processor.sample(&current_logits)?;
-                tokens.push(next_token);
-                generated_tokens += 1;
-
-                if next_token == eos_token || next_token == eot_token {
-                    break;
-                }
-
-                if let Some(t) = self.tokenizer.next_token(next_token)? {
-                    write!(output, "{}", t)?;
-                }
-
-                // For the next iteration, just use the new token
-                let new_input = Tensor::new(&[next_token], &self.device)?.unsqueeze(0)?;
-                logits = self.model.forward(&new_input, tokens.len() - 1)?;
-                logits = logits.squeeze(0)?.squeeze(0)?.to_dtype(DType::F32)?;
-            }
-
-            return Ok(());
-        }
-
-        // Standard approach for other models
-        let start_gen = std::time::Instant::now();
         for index in 0..sample_len {
+            // The first pass prefills the whole prompt, later passes only feed the new token
             let context_size = if index > 0 { 1 } else { tokens.len() };
             let start_pos = tokens.len().saturating_sub(context_size);
             let ctxt = &tokens[start_pos..];
             let input = Tensor::new(ctxt, &self.device)?.unsqueeze(0)?;
             let logits = self.model.forward(&input, start_pos)?;
             let logits = logits.squeeze(0)?.squeeze(0)?.to_dtype(DType::F32)?;
-            let logits = if self.repeat_penalty == 1. {
-                logits
-            } else {
-                let start_at = tokens.len().saturating_sub(self.repeat_last_n);
-
-                // Manual implementation of repeat penalty to avoid type conflicts
-                let mut logits_vec = logits.to_vec1::<f32>()?;
-
-                for &token_id in &tokens[start_at..] {
-                    let token_id = token_id as usize;
-                    if token_id < logits_vec.len() {
-                        let score = logits_vec[token_id];
-                        let sign = if score < 0.0 { -1.0 } else { 1.0 };
-                        logits_vec[token_id] = sign * score / self.repeat_penalty;
-                    }
-                }
-
-                // Create a new tensor with the modified logits
-                let device = logits.device().clone();
-                let shape = logits.shape().clone();
-                let new_logits = Tensor::new(&logits_vec[..], &device)?;
-                new_logits.reshape(shape)?
+            let logprobs = match params.logprobs {
+                Some(_) => Some(log_softmax(&logits.to_vec1::<f32>()?)),
+                None => None,
             };
+            let logits = pipeline.apply(logits, History::new(&tokens, stats.prompt_tokens))?;
 
-            let next_token = self.logits_processor.sample(&logits)?;
+            let next_token = logits_processor.sample(&logits)?;
             tokens.push(next_token);
-            generated_tokens += 1;
+            stats.completion_tokens += 1;
+            stats.time_to_first_token.get_or_insert_with(|| start.elapsed());
             if next_token == eos_token || next_token == eot_token {
+                stats.finish_reason = FinishReason::StopToken;
                 break;
             }
+            if let (Some(logprobs), Some(top_n)) = (&logprobs, params.logprobs) {
+                pending_logprobs.push(self.token_logprob(next_token, logprobs, top_n));
+            }
             if let Some(t) = self.tokenizer.next_token(next_token)? {
-                write!(output, "{}", t)?;
+                if on_text(&t, &pending_logprobs).is_break() {
+                    stats.finish_reason = FinishReason::Cancelled;
+                    break;
+                }
+                pending_logprobs.clear();
+            }
+            if self.tokenizer.is_stopped() {
+                stats.finish_reason = FinishReason::StopSequence;
+                break;
             }
         }
 
-        // Write any remaining tokens
-        if let Some(rest) = self.tokenizer.decode_rest().map_err(E::msg)? {
-            write!(output, "{}", rest)?;
+        // Flush any text still held back by the tokenizer, which can complete a stop sequence
+        if stats.finish_reason != FinishReason::Cancelled {
+            if let Some(rest) = self.tokenizer.decode_rest().map_err(E::msg)? {
+                let _ = on_text(&rest, &pending_logprobs);
+            }
+            if self.tokenizer.is_stopped() {
+                stats.finish_reason = FinishReason::StopSequence;
+            }
         }
 
-        Ok(())
+        stats.total_time = start.elapsed();
+        tracing::info!(
+            prompt_tokens = stats.prompt_tokens,
+            completion_tokens = stats.completion_tokens,
+            finish_reason = ?stats.finish_reason,
+            "Generated {} tokens, first token after {:.0?}, {:.2} token/s",
+            stats.completion_tokens,
+            stats.time_to_first_token.unwrap_or_default(),
+            stats.tokens_per_second(),
+        );
+        Ok(stats)
     }
-}
\ No newline at end of file
+}
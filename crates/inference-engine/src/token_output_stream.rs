@@ -7,6 +7,10 @@ pub struct TokenOutputStream {
     tokens: Vec<u32>,
     prev_index: usize,
     current_index: usize,
+    stop_sequences: Vec<String>,
+    /// Decoded text held back because it could be the start of a stop sequence
+    held: String,
+    stopped: bool,
 }
 
 impl TokenOutputStream {
@@ -16,6 +20,9 @@ impl TokenOutputStream {
             tokens: Vec::new(),
             prev_index: 0,
             current_index: 0,
+            stop_sequences: Vec::new(),
+            held: String::new(),
+            stopped: false,
         }
     }
 
@@ -44,13 +51,23 @@ impl TokenOutputStream {
             let text = text.split_at(prev_text.len());
             self.prev_index = self.current_index;
             self.current_index = self.tokens.len();
-            Ok(Some(text.1.to_string()))
+            Ok(self.release(text.1, false))
         } else {
             Ok(None)
         }
     }
 
-    pub fn decode_rest(&self) -> Result<Option<String>> {
+    /// Adds tokens whose text is not returned, e.g. a prompt, so that later
+    /// tokens still decode in their context.
+    pub fn push_context(&mut self, tokens: &[u32]) {
+        self.tokens.extend_from_slice(tokens);
+        self.prev_index = self.tokens.len().saturating_sub(1);
+        self.current_index = self.tokens.len();
+    }
+
+    /// Returns the text not returned yet, including any text held back for a
+    /// possible stop sequence.
+    pub fn decode_rest(&mut self) -> Result<Option<String>> {
         let prev_text = if self.tokens.is_empty() {
             String::new()
         } else {
@@ -58,12 +75,59 @@ impl TokenOutputStream {
             self.decode(tokens)?
         };
         let text = self.decode(&self.tokens[self.prev_index..])?;
-        if text.len() > prev_text.len() {
-            let text = text.split_at(prev_text.len());
-            Ok(Some(text.1.to_string()))
+        let rest = if text.len() > prev_text.len() {
+            text.split_at(prev_text.len()).1
         } else {
-            Ok(None)
+            ""
+        };
+        Ok(self.release(rest, true))
+    }
+
+    /// Stops the stream at the first of `stop_sequences` in the decoded text.
+    /// Text is checked across token boundaries, so the end of the returned
+    /// text is held back while it could still turn into a stop sequence.
+    pub fn set_stop_sequences(&mut self, stop_sequences: Vec<String>) {
+        self.stop_sequences = stop_sequences.into_iter().filter(|s| !s.is_empty()).collect();
+    }
+
+    /// Whether a stop sequence was decoded. Text from the stop sequence on is
+    /// never returned.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Checks newly decoded `text` for stop sequences and returns what can be
+    /// passed on. With `flush` nothing is held back as no more text follows.
+    fn release(&mut self, text: &str, flush: bool) -> Option<String> {
+        if self.stopped {
+            return None;
         }
+        self.held.push_str(text);
+
+        let stop_at = self
+            .stop_sequences
+            .iter()
+            .filter_map(|stop| self.held.find(stop.as_str()))
+            .min();
+        let released = match stop_at {
+            Some(stop_at) => {
+                self.stopped = true;
+                self.held.truncate(stop_at);
+                std::mem::take(&mut self.held)
+            }
+            None if flush => std::mem::take(&mut self.held),
+            None => {
+                let keep = self
+                    .stop_sequences
+                    .iter()
+                    .map(|stop| partial_match_len(&self.held, stop))
+                    .max()
+                    .unwrap_or(0);
+                let tail = self.held.split_off(self.held.len() - keep);
+                std::mem::replace(&mut self.held, tail)
+            }
+        };
+        (!released.is_empty()).then_some(released)
     }
 
     pub fn decode_all(&self) -> Result<String> {
@@ -74,6 +138,23 @@ impl TokenOutputStream {
         self.tokenizer.get_vocab(true).get(token_s).copied()
     }
 
+    /// The text of a single token as bytes. SentencePiece word boundaries
+    /// become spaces and byte fallback tokens such as `<0x0A>` their byte.
+    pub fn token_bytes(&self, token: u32) -> Vec<u8> {
+        let Some(piece) = self.tokenizer.id_to_token(token) else {
+            return Vec::new();
+        };
+        let byte = piece
+            .strip_prefix("<0x")
+            .and_then(|hex| hex.strip_suffix('>'))
+            .filter(|hex| hex.len() == 2)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match byte {
+            Some(byte) => vec![byte],
+            None => piece.replace('\u{2581}', " ").into_bytes(),
+        }
+    }
+
     pub fn tokenizer(&self) -> &tokenizers::Tokenizer {
         &self.tokenizer
     }
@@ -82,5 +163,17 @@ impl TokenOutputStream {
         self.tokens.clear();
         self.prev_index = 0;
         self.current_index = 0;
+        self.held.clear();
+        self.stopped = false;
     }
+}
+
+/// Length of the longest end of `text` that is the start of `stop`, without being all of it
+fn partial_match_len(text: &str, stop: &str) -> usize {
+    stop.char_indices()
+        .map(|(i, _)| i)
+        .skip(1)
+        .filter(|&len| text.ends_with(&stop[..len]))
+        .max()
+        .unwrap_or(0)
 }
\ No newline at end of file
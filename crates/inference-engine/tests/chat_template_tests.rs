@@ -0,0 +1,109 @@
+use inference_engine::chat_template::{eos_token_from_tokenizer_config, ChatTemplate};
+use inference_engine::model::Which;
+use inference_engine::openai_types::Message;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The template published with google/gemma-2b-it
+    const GEMMA_JINJA: &str = "{{ bos_token }}{% if messages[0]['role'] == 'system' %}{{ raise_exception('System role not supported') }}{% endif %}{% for message in messages %}{% if (message['role'] == 'user') != (loop.index0 % 2 == 0) %}{{ raise_exception('Conversation roles must alternate user/assistant/user/assistant/...') }}{% endif %}{% if (message['role'] == 'assistant') %}{% set role = 'model' %}{% else %}{% set role = message['role'] %}{% endif %}{{ '<start_of_turn>' + role + '\n' + message['content'] | trim + '<end_of_turn>\n' }}{% endfor %}{% if add_generation_prompt %}{{'<start_of_turn>model\n'}}{% endif %}";
+
+    fn gemma_jinja() -> ChatTemplate {
+        ChatTemplate::Jinja {
+            template: GEMMA_JINJA.to_string(),
+            eos_token: "<eos>".to_string(),
+        }
+    }
+
+    fn write_config(name: &str, config: serde_json::Value) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("inference-engine-{}-{}.json", name, std::process::id()));
+        std::fs::write(&path, config.to_string()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_builtin_template_for_model() {
+        assert_eq!(ChatTemplate::for_model(Which::InstructV3_1B), ChatTemplate::Gemma);
+        assert_eq!(ChatTemplate::for_model(Which::InstructV2_2B), ChatTemplate::Gemma);
+        assert_eq!(ChatTemplate::for_model(Which::BaseV3_1B), ChatTemplate::Plain);
+    }
+
+    #[test]
+    fn test_gemma_folds_system_prompt_into_first_user_turn() {
+        let messages = vec![
+            Message::text("system", "You are terse."),
+            Message::text("user", "Hi"),
+            Message::text("assistant", "Hello."),
+            Message::text("user", " Capital of France? "),
+        ];
+        let prompt = ChatTemplate::Gemma.render(&messages).unwrap();
+        assert_eq!(
+            prompt,
+            "<start_of_turn>user\nYou are terse.\n\nHi<end_of_turn>\n\
+             <start_of_turn>model\nHello.<end_of_turn>\n\
+             <start_of_turn>user\nCapital of France?<end_of_turn>\n\
+             <start_of_turn>model\n"
+        );
+    }
+
+    #[test]
+    fn test_plain_template() {
+        let messages = vec![Message::text("system", "Be brief."), Message::text("user", "Hi")];
+        let prompt = ChatTemplate::Plain.render(&messages).unwrap();
+        assert_eq!(prompt, "System: Be brief.\nUser: Hi\nAssistant: ");
+    }
+
+    #[test]
+    fn test_jinja_template_matches_builtin_gemma() {
+        let messages = vec![
+            Message::text("user", "Hi"),
+            Message::text("assistant", "Hello."),
+            Message::text("user", "Bye"),
+        ];
+        let jinja = gemma_jinja().render(&messages).unwrap();
+        assert_eq!(jinja, ChatTemplate::Gemma.render(&messages).unwrap());
+
+        // Templates can reject a conversation
+        let error = gemma_jinja()
+            .render(&[Message::text("system", "x"), Message::text("user", "Hi")])
+            .unwrap_err();
+        assert!(error.to_string().contains("System role not supported"));
+    }
+
+    #[test]
+    fn test_load_from_tokenizer_config() {
+        let path = write_config("single", serde_json::json!({ "chat_template": GEMMA_JINJA, "eos_token": "<eos>" }));
+        assert_eq!(ChatTemplate::load(Which::InstructV3_1B, Some(&path)), gemma_jinja());
+
+        let path = write_config(
+            "named",
+            serde_json::json!({ "chat_template": [
+                { "name": "tool_use", "template": "{{ messages }}" },
+                { "name": "default", "template": "{% for m in messages %}{{ m.content }}{% endfor %}" },
+            ] }),
+        );
+        let template = ChatTemplate::load(Which::InstructV3_1B, Some(&path));
+        assert_eq!(template.render(&[Message::text("user", "Hi")]).unwrap(), "Hi");
+
+        // The EOS token comes from the config, as a string or an added-token object
+        let path = write_config(
+            "eos",
+            serde_json::json!({
+                "chat_template": "{% for m in messages %}{{ m.content }}{{ eos_token }}{% endfor %}",
+                "eos_token": { "content": "</s>", "special": true },
+            }),
+        );
+        let template = ChatTemplate::load(Which::InstructV3_1B, Some(&path));
+        assert_eq!(template.render(&[Message::text("user", "Hi")]).unwrap(), "Hi</s>");
+        assert_eq!(eos_token_from_tokenizer_config(&path).unwrap(), Some("</s>".to_string()));
+
+        // Broken or missing templates fall back to the built-in one
+        let path = write_config("broken", serde_json::json!({ "chat_template": "{% for %}" }));
+        assert_eq!(ChatTemplate::load(Which::InstructV3_1B, Some(&path)), ChatTemplate::Gemma);
+        let path = write_config("missing", serde_json::json!({}));
+        assert_eq!(ChatTemplate::load(Which::BaseV3_1B, Some(&path)), ChatTemplate::Plain);
+        assert_eq!(eos_token_from_tokenizer_config(&path).unwrap(), None);
+        assert_eq!(ChatTemplate::load(Which::BaseV3_1B, None), ChatTemplate::Plain);
+    }
+}
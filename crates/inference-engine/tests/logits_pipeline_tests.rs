@@ -0,0 +1,128 @@
+use anyhow::Result;
+use candle_core::{Device, Tensor};
+use inference_engine::logits_pipeline::{
+    log_softmax, softmax, top_tokens, History, LogitBias, LogitsPipeline, MinP, OccurrencePenalty, RepeatPenalty, TopK, TypicalP,
+};
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEG_INF: f32 = f32::NEG_INFINITY;
+
+    fn run(pipeline: &LogitsPipeline, logits: &[f32], history: History<'_>) -> Result<Vec<f32>> {
+        let logits = Tensor::new(logits, &Device::Cpu)?;
+        Ok(pipeline.apply(logits, history)?.to_vec1::<f32>()?)
+    }
+
+    fn no_history() -> History<'static> {
+        History::new(&[], 0)
+    }
+
+    #[test]
+    fn test_empty_pipeline_leaves_logits_unchanged() -> Result<()> {
+        let logits = [1.0, -2.0, 3.0];
+        assert_eq!(run(&LogitsPipeline::new(), &logits, no_history())?, logits);
+        Ok(())
+    }
+
+    #[test]
+    fn test_repeat_penalty_counts_recent_prompt_tokens() -> Result<()> {
+        let pipeline = LogitsPipeline::new().with(RepeatPenalty { penalty: 2.0, last_n: 2 });
+        // Token 0 is outside the last 2 tokens, 1 and 2 are penalized once each
+        let tokens = [0, 1, 2];
+        let logits = run(&pipeline, &[4.0, 4.0, -4.0, 4.0], History::new(&tokens, 3))?;
+        assert_eq!(logits, vec![4.0, 2.0, -8.0, 4.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_occurrence_penalty_counts_generated_tokens_only() -> Result<()> {
+        let pipeline = LogitsPipeline::new().with(OccurrencePenalty {
+            presence: 0.5,
+            frequency: 0.25,
+        });
+        // Token 0 is only in the prompt, token 1 was generated twice and token 2 once
+        let tokens = [0, 1, 2, 1];
+        let logits = run(&pipeline, &[1.0, 1.0, 1.0, 1.0], History::new(&tokens, 1))?;
+        assert_eq!(logits, vec![1.0, 0.0, 0.25, 1.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_logit_bias() -> Result<()> {
+        let pipeline = LogitsPipeline::new().with(LogitBias(HashMap::from([(0, -100.0), (2, 1.5), (9, 5.0)])));
+        // Token ids outside the vocabulary are ignored
+        let logits = run(&pipeline, &[1.0, 1.0, 1.0], no_history())?;
+        assert_eq!(logits, vec![-99.0, 1.0, 2.5]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_top_k_keeps_ties() -> Result<()> {
+        let logits = [0.5, 3.0, 1.0, 2.0, 1.0];
+        let top_2 = run(&LogitsPipeline::new().with(TopK(2)), &logits, no_history())?;
+        assert_eq!(top_2, vec![NEG_INF, 3.0, NEG_INF, 2.0, NEG_INF]);
+
+        let top_3 = run(&LogitsPipeline::new().with(TopK(3)), &logits, no_history())?;
+        assert_eq!(top_3, vec![NEG_INF, 3.0, 1.0, 2.0, 1.0]);
+
+        let all = run(&LogitsPipeline::new().with(TopK(10)), &logits, no_history())?;
+        assert_eq!(all, logits);
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_p_is_relative_to_the_most_likely_token() -> Result<()> {
+        // Probabilities relative to the first token: 1, 1/2, 1/4 and 1/8
+        let logits = [0.0, -(2f32.ln()), -(4f32.ln()), -(8f32.ln())];
+        let filtered = run(&LogitsPipeline::new().with(MinP(0.3)), &logits, no_history())?;
+        assert_eq!(&filtered[..2], &logits[..2]);
+        assert_eq!(&filtered[2..], &[NEG_INF, NEG_INF]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_typical_p_keeps_tokens_closest_to_the_entropy() -> Result<()> {
+        // A uniform distribution has every token exactly at the entropy, so
+        // covering 50% takes two of the four tokens
+        let uniform = run(&LogitsPipeline::new().with(TypicalP(0.5)), &[1.0; 4], no_history())?;
+        assert_eq!(uniform.iter().filter(|&&score| score == NEG_INF).count(), 2);
+
+        // With one dominant token, the rare tokens are the least typical ones
+        let logits = [5.0, 0.0, 0.0, -5.0];
+        let filtered = run(&LogitsPipeline::new().with(TypicalP(0.9)), &logits, no_history())?;
+        assert_eq!(filtered, vec![5.0, NEG_INF, NEG_INF, NEG_INF]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transforms_compose_in_order() -> Result<()> {
+        // The bias makes token 2 the most likely before top-k runs
+        let pipeline = LogitsPipeline::new()
+            .with(LogitBias(HashMap::from([(2, 10.0)])))
+            .with(TopK(1));
+        let logits = run(&pipeline, &[3.0, 2.0, 1.0], no_history())?;
+        assert_eq!(logits, vec![NEG_INF, NEG_INF, 11.0]);
+
+        let probs = softmax(&logits);
+        assert_eq!(probs, vec![0.0, 0.0, 1.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_log_softmax_and_top_tokens() {
+        let logprobs = log_softmax(&[2f32.ln(), 0.0, NEG_INF, 0.0]);
+        let expected = [0.5f32.ln(), 0.25f32.ln(), NEG_INF, 0.25f32.ln()];
+        for (logprob, expected) in logprobs.iter().zip(expected) {
+            assert!(*logprob == expected || (logprob - expected).abs() < 1e-6);
+        }
+
+        // Ties go to the lower token id
+        let top = top_tokens(&logprobs, 2);
+        assert_eq!(top.iter().map(|(token, _)| *token).collect::<Vec<_>>(), vec![0, 1]);
+        assert!(top_tokens(&logprobs, 0).is_empty());
+        assert_eq!(top_tokens(&logprobs, 10).len(), 4);
+    }
+}
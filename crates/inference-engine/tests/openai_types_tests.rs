@@ -0,0 +1,132 @@
+use inference_engine::openai_types::{
+    ChatCompletionChunk, ChatCompletionChunkChoice, ChatCompletionRequest, ChoiceLogprobs, CompletionRequest,
+    Delta, LogprobContent, TopLogprob,
+};
+use inference_engine::text_generation::GenerationParams;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(delta: Delta, finish_reason: Option<&str>) -> serde_json::Value {
+        serde_json::to_value(ChatCompletionChunk {
+            id: "chatcmpl-test".to_string(),
+            object: "chat.completion.chunk".to_string(),
+            created: 0,
+            model: "gemma-3-1b-it".to_string(),
+            choices: vec![ChatCompletionChunkChoice {
+                index: 0,
+                delta,
+                logprobs: None,
+                finish_reason: finish_reason.map(str::to_string),
+            }],
+            usage: None,
+            timing: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_chunk_serialization() {
+        let first = chunk(
+            Delta {
+                role: Some("assistant".to_string()),
+                content: Some(String::new()),
+            },
+            None,
+        );
+        assert_eq!(first["object"], "chat.completion.chunk");
+        assert_eq!(first["choices"][0]["delta"], serde_json::json!({ "role": "assistant", "content": "" }));
+        assert!(first["choices"][0]["finish_reason"].is_null());
+        assert!(first["choices"][0]["logprobs"].is_null());
+        assert!(first.get("usage").is_none());
+
+        // The last chunk has an empty delta and the finish reason
+        let last = chunk(Delta::default(), Some("stop"));
+        assert_eq!(last["choices"][0]["delta"], serde_json::json!({}));
+        assert_eq!(last["choices"][0]["finish_reason"], "stop");
+    }
+
+    #[test]
+    fn test_completion_request_echo_defaults_to_false() {
+        let request: CompletionRequest = serde_json::from_value(serde_json::json!({ "prompt": "Once upon a time" })).unwrap();
+        assert!(!request.echo);
+        assert_eq!(request.model, "default");
+
+        let request: CompletionRequest =
+            serde_json::from_value(serde_json::json!({ "prompt": "Once upon a time", "echo": true })).unwrap();
+        assert!(request.echo);
+    }
+
+    #[test]
+    fn test_chat_request_choices() {
+        let request = |body: serde_json::Value| -> ChatCompletionRequest { serde_json::from_value(body).unwrap() };
+        let messages = serde_json::json!([{ "role": "user", "content": "Hi" }]);
+        assert_eq!(request(serde_json::json!({ "messages": messages })).n_choices, 1);
+        assert_eq!(request(serde_json::json!({ "messages": messages, "n": 3 })).n_choices, 3);
+    }
+
+    #[test]
+    fn test_request_sampling_settings() {
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "messages": [{ "role": "user", "content": "Hi" }],
+            "temperature": 0.2,
+            "top_k": 40,
+            "seed": 7,
+            "frequency_penalty": 0.5,
+            "stop": "\n",
+        }))
+        .unwrap();
+        let params = GenerationParams::from(&request);
+        assert_eq!(params.temperature, Some(0.2));
+        assert_eq!(params.top_k, Some(40));
+        assert_eq!(params.seed, Some(7));
+        assert_eq!(params.frequency_penalty, Some(0.5));
+        assert_eq!(params.stop, vec!["\n".to_string()]);
+
+        // Settings a request leaves out fall back to the server's
+        let params = GenerationParams::from(&request_without_settings());
+        assert_eq!(params.temperature, None);
+        assert_eq!(params.repeat_penalty, None);
+        assert!(params.stop.is_empty());
+    }
+
+    fn request_without_settings() -> CompletionRequest {
+        serde_json::from_value(serde_json::json!({ "prompt": "Once upon a time" })).unwrap()
+    }
+
+    #[test]
+    fn test_logprobs_serialization() {
+        let logprobs = ChoiceLogprobs {
+            content: vec![LogprobContent {
+                token: " Paris".to_string(),
+                logprob: -0.25,
+                bytes: b" Paris".to_vec(),
+                top_logprobs: vec![TopLogprob {
+                    token: " Paris".to_string(),
+                    logprob: -0.25,
+                    bytes: b" Paris".to_vec(),
+                }],
+            }],
+        };
+        let value = serde_json::to_value(&logprobs).unwrap();
+        assert_eq!(value["content"][0]["token"], " Paris");
+        assert_eq!(value["content"][0]["logprob"], -0.25);
+        assert_eq!(value["content"][0]["bytes"], serde_json::json!([32, 80, 97, 114, 105, 115]));
+        assert_eq!(value["content"][0]["top_logprobs"][0]["token"], " Paris");
+    }
+
+    #[test]
+    fn test_top_logprobs_require_logprobs() {
+        let request = |logprobs: bool| -> ChatCompletionRequest {
+            serde_json::from_value(serde_json::json!({
+                "messages": [{ "role": "user", "content": "Hi" }],
+                "logprobs": logprobs,
+                "top_logprobs": 3,
+            }))
+            .unwrap()
+        };
+        assert_eq!(GenerationParams::from(&request(true)).logprobs, Some(3));
+        assert_eq!(GenerationParams::from(&request(false)).logprobs, None);
+    }
+}
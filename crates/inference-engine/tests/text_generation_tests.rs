@@ -1,6 +1,7 @@
 use anyhow::Result;
 use candle_transformers::generation::LogitsProcessor;
 use inference_engine::model::Which;
+use inference_engine::text_generation::{FinishReason, GenerationParams, GenerationStats};
 use inference_engine::token_output_stream::TokenOutputStream;
 use tokenizers::Tokenizer;
 
@@ -37,6 +38,54 @@ mod tests {
         assert!(Which::BaseV3_1B.is_v3_model());
     }
 
+    // Test the speed reported for a generation
+    #[test]
+    fn test_generation_stats() {
+        let stats = GenerationStats {
+            finish_reason: FinishReason::StopToken,
+            prompt_tokens: 12,
+            completion_tokens: 11,
+            time_to_first_token: Some(std::time::Duration::from_millis(500)),
+            total_time: std::time::Duration::from_millis(2500),
+        };
+        assert_eq!(stats.total_tokens(), 23);
+        // The first token arrives after the prefill, the other 10 take 2 seconds
+        assert_eq!(stats.tokens_per_second(), 5.0);
+
+        let single = GenerationStats {
+            completion_tokens: 1,
+            ..stats
+        };
+        assert_eq!(single.tokens_per_second(), 0.0);
+    }
+
+    // Test how finish reasons map to OpenAI's values
+    #[test]
+    fn test_finish_reason_openai_values() {
+        assert_eq!(FinishReason::StopToken.as_openai_str(), "stop");
+        assert_eq!(FinishReason::StopSequence.as_openai_str(), "stop");
+        assert_eq!(FinishReason::Cancelled.as_openai_str(), "stop");
+        assert_eq!(FinishReason::Length.as_openai_str(), "length");
+        // A run that nothing stopped early used up its tokens
+        assert_eq!(GenerationStats::default().finish_reason, FinishReason::Length);
+    }
+
+    // Test the settings each choice of a request generates with
+    #[test]
+    fn test_choice_params_offset_the_seed() {
+        let params = GenerationParams {
+            seed: Some(42),
+            temperature: Some(0.7),
+            ..GenerationParams::default()
+        };
+        assert_eq!(params.for_choice(0).seed, Some(42));
+        assert_eq!(params.for_choice(2).seed, Some(44));
+        assert_eq!(params.for_choice(2).temperature, Some(0.7));
+
+        // Unseeded choices already differ, as every run takes a new seed
+        assert_eq!(GenerationParams::default().for_choice(3).seed, None);
+    }
+
     // Test the TokenOutputStream functionality
     #[test]
     fn test_token_output_stream() -> Result<()> {
@@ -126,4 +126,104 @@ mod tests {
         
         Ok(())
     }
-}
\ No newline at end of file
+
+    // Helper function to create a word-level tokenizer that needs no download
+    fn create_word_tokenizer() -> Tokenizer {
+        use tokenizers::models::wordlevel::WordLevel;
+
+        let vocab = [("Hello", 0), ("world", 1), ("!", 2), ("<unk>", 3)]
+            .into_iter()
+            .map(|(word, id)| (word.to_string(), id))
+            .collect();
+        let model = WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("<unk>".to_string())
+            .build()
+            .unwrap();
+        Tokenizer::new(model)
+    }
+
+    #[test]
+    fn test_push_context_is_not_returned() -> Result<()> {
+        let mut token_stream = TokenOutputStream::new(create_word_tokenizer());
+
+        // The context decodes with the next token but is never returned itself
+        token_stream.push_context(&[0]);
+        assert_eq!(token_stream.decode_rest()?, None);
+        assert_eq!(token_stream.next_token(1)?, Some(" world".to_string()));
+
+        // Text ending in punctuation is held back until the rest is decoded
+        assert_eq!(token_stream.next_token(2)?, None);
+        assert_eq!(token_stream.decode_rest()?, Some(" !".to_string()));
+        assert_eq!(token_stream.decode_all()?, "Hello world !");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stop_sequence_across_token_boundaries() -> Result<()> {
+        let mut token_stream = TokenOutputStream::new(create_word_tokenizer());
+        token_stream.set_stop_sequences(vec!["lo wor".to_string()]);
+
+        // "lo" could start the stop sequence, so it is held back
+        assert_eq!(token_stream.next_token(0)?, Some("Hel".to_string()));
+        assert!(!token_stream.is_stopped());
+
+        // The next token completes the stop sequence, which is never returned
+        assert_eq!(token_stream.next_token(1)?, None);
+        assert!(token_stream.is_stopped());
+        assert_eq!(token_stream.decode_rest()?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stop_sequence_in_held_back_text() -> Result<()> {
+        let mut token_stream = TokenOutputStream::new(create_word_tokenizer());
+        token_stream.set_stop_sequences(vec!["!".to_string(), "Goodbye".to_string()]);
+
+        assert_eq!(token_stream.next_token(0)?, Some("Hello".to_string()));
+        assert_eq!(token_stream.next_token(1)?, Some(" world".to_string()));
+        assert_eq!(token_stream.next_token(2)?, None);
+        assert!(!token_stream.is_stopped());
+
+        // The stop sequence only shows up when the rest is decoded
+        assert_eq!(token_stream.decode_rest()?, Some(" ".to_string()));
+        assert!(token_stream.is_stopped());
+
+        // Clearing the stream starts a new generation with the same stop sequences
+        token_stream.clear();
+        assert!(!token_stream.is_stopped());
+        assert_eq!(token_stream.next_token(1)?, Some("world".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_held_back_text_is_released_when_it_cannot_stop() -> Result<()> {
+        let mut token_stream = TokenOutputStream::new(create_word_tokenizer());
+        token_stream.set_stop_sequences(vec!["lo there".to_string()]);
+
+        assert_eq!(token_stream.next_token(0)?, Some("Hel".to_string()));
+        assert_eq!(token_stream.next_token(1)?, Some("lo world".to_string()));
+        assert!(!token_stream.is_stopped());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_bytes() {
+        use tokenizers::models::wordlevel::WordLevel;
+
+        let vocab = [("\u{2581}Paris", 0), ("<0x0A>", 1), ("<unk>", 2)]
+            .into_iter()
+            .map(|(word, id)| (word.to_string(), id))
+            .collect();
+        let model = WordLevel::builder().vocab(vocab).unk_token("<unk>".to_string()).build().unwrap();
+        let token_stream = TokenOutputStream::new(Tokenizer::new(model));
+
+        assert_eq!(token_stream.token_bytes(0), b" Paris".to_vec());
+        assert_eq!(token_stream.token_bytes(1), vec![b'\n']);
+        assert!(token_stream.token_bytes(99).is_empty());
+    }
+}
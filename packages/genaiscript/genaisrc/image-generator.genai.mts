@@ -1,11 +1,24 @@
+import {copyFile, mkdir} from "fs/promises";
+import {extname, join} from "path";
+
 console.log("Generating image")
 
 def("USER_INPUT", env.vars.user_input);
 
 
+const parsed = JSON.parse(env.vars.user_input);
+
 const inputs = {
-    host: JSON.parse(env.vars.user_input).host,
-    imageId: JSON.parse(env.vars.user_input).imageId
+    host: parsed.host,
+    imageId: parsed.imageId,
+    description: parsed.description ?? env.vars.user_input,
+    outputDir: parsed.output_dir ?? "./open-web-agent-rs/generated/images",
 }
 
+// the agent-server reads the image back from `<outputDir>/<imageId>.<ext>`
+const {image} = await generateImage(inputs.description, {mime: "image/png"});
+
+await mkdir(inputs.outputDir, {recursive: true});
+await copyFile(image.filename, join(inputs.outputDir, `${inputs.imageId}${extname(image.filename) || ".png"}`));
+
 console.log(`![Generated Image](/generated/image/${inputs.imageId})`);
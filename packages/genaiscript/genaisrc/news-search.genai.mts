@@ -8,7 +8,22 @@ script({
     cache: false,
 });
 
-def("USER_INPUT", env.vars.user_input);
+// USER_INPUT is either a plain query or a JSON encoded news request
+let request: {
+    query: string,
+    max_results?: number,
+    time_range?: string,
+    language?: string,
+};
+try {
+    const parsed = JSON.parse(env.vars.user_input);
+    request = typeof parsed === "string" ? {query: parsed} : parsed;
+    if (!request?.query) throw "not a request";
+} catch (e) {
+    request = {query: env.vars.user_input};
+}
+
+def("USER_INPUT", request.query);
 def("TODAY", new Date().toISOString().split("T")[0]);
 def("LINK_FORMAT", "[Link](url)");
 
@@ -18,7 +33,9 @@ $`You are an assistant searching for news using complex queries to pinpoint resu
  
 - tailor search to answer the question in USER_INPUT
 - perform 2 searches in parallel sorted by relevance and date respectively
-- create a markdown table of <=5 results of both searches
+- create a markdown table of <=${request.max_results ?? 5} results of both searches
 - header row: Date, Title, Summary, and Link
+${request.time_range ? `- only include articles from the past ${request.time_range}` : ""}
+${request.language ? `- prefer articles in the language "${request.language}"` : ""}
  
 Respond with a single table, no extra text.`
@@ -10,15 +10,21 @@ script({
   "url": "Full URL in the conversation that references the URL being interacted with. No trailing slash!",
   "query": "Implied question about the resources at the URL.",
   "action": "read | scrape | crawl"
+  "format": "markdown | text | html"
 */
 
 try {
-    const {url, query, action} = JSON.parse(env.vars.user_input);
+    JSON.parse(env.vars.user_input);
 } catch (e) {
     throw "Sorry! Something went wrong.";
 }
 
-const {url, query, action} = JSON.parse(env.vars.user_input);
+const {
+    url,
+    query = "Describe the details of the page.",
+    action = "read",
+    format = "markdown",
+} = JSON.parse(env.vars.user_input);
 
 def("URL", url);
 
@@ -72,9 +78,14 @@ window.document.body.innerHTML = text;
 
 const textContent = window.document.body.textContent;
 
-def("PAGE_TEXT", textContent);
+if (format === "html") {
+    console.log(text);
+} else if (format === "text") {
+    console.log(textContent);
+} else {
+    def("PAGE_TEXT", textContent);
 
-$`You a helpful assistant interacting with resources found at the URL.
+    $`You a helpful assistant interacting with resources found at the URL.
 
 - markdown table is concise representation of PAGE_TEXT relevant to the QUERY
 
@@ -86,3 +97,4 @@ $`You a helpful assistant interacting with resources found at the URL.
 \n---[Example explanation of data significance to query.]
 ---
 Respond with the markdown table and an explanation of significance. Do not include extra text.`;
+}
@@ -11,18 +11,41 @@ script({
 
 
 
-def("USER_INPUT", env.vars.user_input);
+// USER_INPUT is either a plain query or a JSON encoded search request
+let request: {
+    query: string,
+    max_results?: number,
+    time_range?: string,
+    language?: string,
+    site?: string,
+};
+try {
+    const parsed = JSON.parse(env.vars.user_input);
+    request = typeof parsed === "string" ? {query: parsed} : parsed;
+    if (!request?.query) throw "not a request";
+} catch (e) {
+    request = {query: env.vars.user_input};
+}
+
+def("USER_INPUT", request.query);
 
 
 
 def("LINK_FORMAT", "[Link](url)");
 
+const constraints = [
+    request.time_range && `- only include results from the past ${request.time_range}`,
+    request.language && `- prefer results in the language "${request.language}"`,
+    request.site && `- restrict searches to site:${request.site}`,
+].filter(Boolean).join("\n");
+
 $`You are an assistant searching for web content using complex queries to pinpoint results.
 
  
 - tailor search to answer the question in USER_INPUT
 - perform 2 searches in parallel sorted by relevance and date respectively
-- create a markdown table of <=5 results of both searches
+- create a markdown table of <=${request.max_results ?? 5} results of both searches
 - header row: Title, Description, and Link
+${constraints}
  
 Respond with a single table, no extra text.`